@@ -0,0 +1,5 @@
+create table revision_retention_policies (
+  user_id integer PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+  max_revisions integer CHECK (max_revisions > 0),
+  max_age_days integer CHECK (max_age_days > 0)
+)
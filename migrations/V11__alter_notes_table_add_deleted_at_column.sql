@@ -0,0 +1,3 @@
+alter table notes add column deleted_at TIMESTAMPTZ;
+
+create index notes_deleted_at_idx on notes (deleted_at) where deleted_at is not null;
@@ -0,0 +1,14 @@
+create table tags (
+  id SERIAL PRIMARY KEY,
+  user_id integer NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  name varchar(50) NOT NULL,
+  UNIQUE (user_id, name)
+);
+
+create table note_tags (
+  note_id integer NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+  tag_id integer NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+  PRIMARY KEY (note_id, tag_id)
+);
+
+create index note_tags_tag_id_idx on note_tags (tag_id);
@@ -0,0 +1,13 @@
+create table notebooks (
+  id SERIAL PRIMARY KEY,
+  user_id integer NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  parent_id integer REFERENCES notebooks(id) ON DELETE CASCADE,
+  name varchar(100) NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+create index notebooks_parent_id_idx on notebooks (parent_id);
+
+alter table notes add column notebook_id integer REFERENCES notebooks(id) ON DELETE SET NULL;
+
+create index notes_notebook_id_idx on notes (notebook_id);
@@ -0,0 +1,6 @@
+alter table notes add column search_language regconfig NOT NULL DEFAULT 'english';
+
+alter table notes add column search_vector tsvector
+  GENERATED ALWAYS AS (to_tsvector(search_language, coalesce(text, ''))) STORED;
+
+create index notes_search_vector_idx on notes using GIN (search_vector);
@@ -0,0 +1,3 @@
+create extension if not exists pg_trgm;
+
+create index users_username_trgm_idx on users using GIN (username gin_trgm_ops);
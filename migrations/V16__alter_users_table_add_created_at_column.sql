@@ -0,0 +1 @@
+alter table users add column created_at TIMESTAMPTZ NOT NULL DEFAULT now()
\ No newline at end of file
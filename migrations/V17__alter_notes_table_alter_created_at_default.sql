@@ -0,0 +1 @@
+alter table notes alter column created_at set default now()
\ No newline at end of file
@@ -0,0 +1,9 @@
+create table note_shares (
+  note_id integer NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+  user_id integer NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  permission smallint NOT NULL CHECK (permission IN (0, 1)),
+  created_at timestamptz NOT NULL DEFAULT now(),
+  PRIMARY KEY (note_id, user_id)
+);
+
+create index note_shares_user_id_idx on note_shares (user_id, created_at);
@@ -0,0 +1,8 @@
+create table note_links (
+  note_id integer PRIMARY KEY REFERENCES notes(id) ON DELETE CASCADE,
+  token varchar(64) NOT NULL UNIQUE,
+  password varchar(64),
+  expires_at timestamptz,
+  view_count bigint NOT NULL DEFAULT 0,
+  created_at timestamptz NOT NULL DEFAULT now()
+);
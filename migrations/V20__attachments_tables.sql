@@ -0,0 +1,19 @@
+create table blobs (
+  hash char(64) PRIMARY KEY,
+  size bigint NOT NULL,
+  last_used_at timestamptz NOT NULL DEFAULT now()
+);
+
+create table attachments (
+  id SERIAL PRIMARY KEY,
+  note_id integer NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+  blob_hash char(64) NOT NULL REFERENCES blobs(hash),
+  filename varchar(255) NOT NULL,
+  content_type varchar(255) NOT NULL,
+  size bigint NOT NULL,
+  uploaded_by integer REFERENCES users(id) ON DELETE SET NULL,
+  created_at timestamptz NOT NULL DEFAULT now()
+);
+
+create index attachments_note_id_idx on attachments (note_id);
+create index attachments_blob_hash_idx on attachments (blob_hash);
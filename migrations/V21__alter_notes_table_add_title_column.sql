@@ -0,0 +1,23 @@
+alter table notes drop constraint notes_text_key;
+
+-- the generated search vector depends on text, so it is rebuilt around the type change
+alter table notes drop column search_vector;
+alter table notes alter column text type text;
+alter table notes add column title text NOT NULL DEFAULT '';
+
+alter table notes add column search_vector tsvector
+  GENERATED ALWAYS AS (
+    setweight(to_tsvector(search_language, title), 'A') ||
+    setweight(to_tsvector(search_language, coalesce(text, '')), 'B')
+  ) STORED;
+
+create index notes_search_vector_idx on notes using GIN (search_vector);
+create index notes_user_id_title_idx on notes (user_id, lower(title)) where deleted_at is null and title <> '';
+
+alter table note_revisions alter column text type text;
+alter table note_revisions add column title text NOT NULL DEFAULT '';
+
+create table note_settings (
+  user_id integer PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+  unique_titles boolean NOT NULL DEFAULT false
+);
@@ -0,0 +1 @@
+alter table notes add column format smallint NOT NULL DEFAULT 0;
@@ -0,0 +1,25 @@
+create table note_references (
+  source_id integer NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+  -- the note `target` resolved to, null while no note matches it
+  target_id integer REFERENCES notes(id) ON DELETE SET NULL,
+  -- as written between [[ and ]]: a title, or # followed by a note id
+  target text NOT NULL,
+  PRIMARY KEY (source_id, target)
+);
+
+create index note_references_target_id_idx on note_references (target_id);
+create index note_references_target_idx on note_references (lower(target)) where target_id is null;
+
+insert into note_references (source_id, target)
+select distinct on (n.id, lower(btrim(m[1]))) n.id, btrim(m[1])
+from notes n, regexp_matches(n.text, '\[\[([^][|\n]+)(?:\|[^][\n]*)?\]\]', 'g') m
+where btrim(m[1]) <> '';
+
+update note_references r set target_id = (
+  select n.id from notes n join notes s on s.id = r.source_id
+  where n.user_id = s.user_id and n.deleted_at is null and case
+    when r.target ~ '^#[0-9]{1,9}$' then n.id = substr(r.target, 2)::integer
+    else lower(n.title) = lower(r.target)
+  end
+  order by n.id limit 1
+);
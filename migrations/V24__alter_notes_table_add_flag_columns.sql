@@ -0,0 +1,5 @@
+alter table notes add column pinned boolean NOT NULL DEFAULT false;
+alter table notes add column archived boolean NOT NULL DEFAULT false;
+alter table notes add column favorite boolean NOT NULL DEFAULT false;
+
+create index notes_user_id_pinned_idx on notes (user_id) where pinned and deleted_at is null;
@@ -0,0 +1,44 @@
+alter table notes add column due_at timestamptz;
+
+create index notes_user_id_due_at_idx on notes (user_id, due_at) where due_at is not null and deleted_at is null;
+
+-- `occurrence` counts the occurrences of the recurrence before `next_at`, which is NULL once
+-- the reminder has nothing left to fire
+create table reminders (
+  id SERIAL PRIMARY KEY,
+  note_id integer NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+  user_id integer NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  remind_at timestamptz NOT NULL,
+  recurrence text,
+  occurrence integer NOT NULL DEFAULT 0,
+  next_at timestamptz,
+  created_at timestamptz NOT NULL DEFAULT now()
+);
+
+create index reminders_note_id_idx on reminders (note_id);
+create index reminders_user_id_next_at_idx on reminders (user_id, next_at);
+
+create table jobs (
+  id BIGSERIAL PRIMARY KEY,
+  payload jsonb NOT NULL,
+  run_at timestamptz NOT NULL,
+  attempts integer NOT NULL DEFAULT 0,
+  locked_until timestamptz,
+  last_error text,
+  failed_at timestamptz,
+  created_at timestamptz NOT NULL DEFAULT now()
+);
+
+create index jobs_run_at_idx on jobs (run_at) where failed_at is null;
+
+create table notifications (
+  id SERIAL PRIMARY KEY,
+  user_id integer NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  kind smallint NOT NULL,
+  note_id integer REFERENCES notes(id) ON DELETE CASCADE,
+  message text NOT NULL,
+  created_at timestamptz NOT NULL DEFAULT now(),
+  read_at timestamptz
+);
+
+create index notifications_user_id_idx on notifications (user_id, id);
@@ -0,0 +1,14 @@
+-- templates without a user are global, offered to everyone and managed by moderators
+create table templates (
+  id SERIAL PRIMARY KEY,
+  user_id integer REFERENCES users(id) ON DELETE CASCADE,
+  name varchar(100) NOT NULL,
+  title text NOT NULL DEFAULT '',
+  text text NOT NULL,
+  format smallint NOT NULL DEFAULT 0,
+  tags text[] NOT NULL DEFAULT '{}',
+  created_at timestamptz NOT NULL DEFAULT now(),
+  updated_at timestamptz NOT NULL DEFAULT now()
+);
+
+create unique index templates_user_id_name_idx on templates (coalesce(user_id, 0), lower(name));
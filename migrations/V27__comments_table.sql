@@ -0,0 +1,15 @@
+-- deleted comments keep their row, without text, so replies to them stay in their thread
+create table comments (
+  id SERIAL PRIMARY KEY,
+  note_id integer NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+  parent_id integer REFERENCES comments(id) ON DELETE CASCADE,
+  user_id integer NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  text text NOT NULL,
+  created_at timestamptz NOT NULL DEFAULT now(),
+  edited_at timestamptz,
+  deleted_at timestamptz,
+  deleted_by integer REFERENCES users(id) ON DELETE SET NULL
+);
+
+create index comments_note_id_parent_id_idx on comments (note_id, parent_id, id);
+create index comments_parent_id_idx on comments (parent_id);
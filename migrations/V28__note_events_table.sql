@@ -0,0 +1,77 @@
+-- every change to a note a user can see, kept a while so streams can resume after a reconnect;
+-- `user_ids` are the users who could see the note when it happened
+create table note_events (
+  id BIGSERIAL PRIMARY KEY,
+  note_id integer NOT NULL,
+  kind smallint NOT NULL,
+  user_ids integer[] NOT NULL,
+  created_at timestamptz NOT NULL DEFAULT now()
+);
+
+create index note_events_created_at_idx on note_events (created_at);
+
+-- kind: 0 created, 1 updated, 2 deleted; the event id is sent on the note_events channel
+create function record_note_event(event_note_id integer, event_kind smallint, event_user_ids integer[]) returns void as $$
+declare
+  event_id bigint;
+begin
+  insert into note_events (note_id, kind, user_ids) values (event_note_id, event_kind, event_user_ids)
+    returning id into event_id;
+  perform pg_notify('note_events', event_id::text);
+end;
+$$ language plpgsql;
+
+-- notes moved to the trash are deleted for their users, restored ones created again
+create function notes_record_event() returns trigger as $$
+declare
+  note record;
+  event_kind smallint;
+begin
+  if tg_op = 'INSERT' then
+    note := new;
+    event_kind := case when new.deleted_at is null then 0 end;
+  elsif tg_op = 'DELETE' then
+    note := old;
+    event_kind := case when old.deleted_at is null then 2 end;
+  else
+    note := new;
+    event_kind := case
+      when old.deleted_at is null and new.deleted_at is null then 1
+      when old.deleted_at is null then 2
+      when new.deleted_at is null then 0
+    end;
+  end if;
+
+  if event_kind is not null then
+    perform record_note_event(note.id, event_kind,
+      note.user_id || array(select s.user_id from note_shares s where s.note_id = note.id));
+  end if;
+
+  return null;
+end;
+$$ language plpgsql;
+
+create trigger notes_record_event after insert or delete on notes
+  for each row execute function notes_record_event();
+
+create trigger notes_record_update_event after update on notes
+  for each row when (old.* is distinct from new.*) execute function notes_record_event();
+
+-- sharing a note creates it for the recipient, revoking the share deletes it
+create function note_shares_record_event() returns trigger as $$
+declare
+  share record;
+begin
+  share := case when tg_op = 'DELETE' then old else new end;
+
+  if exists (select 1 from notes n where n.id = share.note_id and n.deleted_at is null) then
+    perform record_note_event(share.note_id, case tg_op when 'INSERT' then 0 when 'UPDATE' then 1 else 2 end::smallint,
+      array[share.user_id]);
+  end if;
+
+  return null;
+end;
+$$ language plpgsql;
+
+create trigger note_shares_record_event after insert or update or delete on note_shares
+  for each row execute function note_shares_record_event();
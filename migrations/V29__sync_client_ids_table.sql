@@ -0,0 +1,9 @@
+-- the notes offline clients created, by the id the client gave them, so a sync retried after a
+-- lost response doesn't create them twice
+create table sync_client_ids (
+  user_id integer NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+  client_id varchar(100) NOT NULL,
+  note_id integer NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+  created_at timestamptz NOT NULL DEFAULT now(),
+  PRIMARY KEY (user_id, client_id)
+);
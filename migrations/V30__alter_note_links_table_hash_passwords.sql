@@ -0,0 +1,7 @@
+-- link passwords are argon2 PHC strings from now on; the sha256 digests stored so far are still
+-- accepted and replaced by an argon2 hash the next time the link is opened with its password
+alter table note_links alter column password type text;
+
+-- wrong passwords in a row, the link refuses further tries until `locked_until` once they add up
+alter table note_links add column failed_attempts integer NOT NULL DEFAULT 0;
+alter table note_links add column locked_until timestamptz;
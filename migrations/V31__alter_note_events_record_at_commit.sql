@@ -0,0 +1,30 @@
+-- note events are recorded when their transaction commits, under a lock held until the commit is
+-- done: ids are then handed out in commit order, and a reader who sees an event also sees every
+-- event with a lower id, so "everything after id N" never misses one committed late
+create or replace function record_note_event(event_note_id integer, event_kind smallint, event_user_ids integer[]) returns void as $$
+declare
+  event_id bigint;
+begin
+  perform pg_advisory_xact_lock(hashtext('note_events'));
+
+  insert into note_events (note_id, kind, user_ids) values (event_note_id, event_kind, event_user_ids)
+    returning id into event_id;
+  perform pg_notify('note_events', event_id::text);
+end;
+$$ language plpgsql;
+
+drop trigger notes_record_event on notes;
+drop trigger notes_record_update_event on notes;
+drop trigger note_shares_record_event on note_shares;
+
+create constraint trigger notes_record_event after insert or delete on notes
+  deferrable initially deferred
+  for each row execute function notes_record_event();
+
+create constraint trigger notes_record_update_event after update on notes
+  deferrable initially deferred
+  for each row when (old.* is distinct from new.*) execute function notes_record_event();
+
+create constraint trigger note_shares_record_event after insert or update or delete on note_shares
+  deferrable initially deferred
+  for each row execute function note_shares_record_event();
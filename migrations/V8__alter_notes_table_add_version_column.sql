@@ -0,0 +1 @@
+alter table notes add column version integer NOT NULL DEFAULT 1
\ No newline at end of file
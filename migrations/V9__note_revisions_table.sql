@@ -0,0 +1,12 @@
+create table note_revisions (
+  id SERIAL PRIMARY KEY,
+  note_id integer NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+  version integer NOT NULL,
+  text varchar(200) NOT NULL,
+  author_id integer REFERENCES users(id) ON DELETE SET NULL,
+  created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+  UNIQUE (note_id, version)
+);
+
+insert into note_revisions (note_id, version, text, author_id)
+select id, version, text, user_id from notes where text is not null;
@@ -1,8 +1,8 @@
 use std::str::FromStr;
 
 use axum::{
-    Router, routing::{get, post},
-    middleware::from_fn_with_state, handler::Handler
+    Router, routing::{get, post, put, delete},
+    middleware::from_fn_with_state, handler::Handler, extract::DefaultBodyLimit
 };
 use bb8::{Pool, ManageConnection};
 use bb8_postgres::PostgresConnectionManager;
@@ -20,12 +20,47 @@ use crate::modules::users::api::*;
 use crate::modules::testable::api::*;
 use crate::modules::auth::api::*;
 use crate::modules::notes::api::*;
-
-use crate::types::{AppState, Roles};
+use crate::modules::revisions::api::*;
+use crate::modules::trash::api::*;
+use crate::modules::tags::api::*;
+use crate::modules::notebooks::api::*;
+use crate::modules::shares::api::*;
+use crate::modules::links::api::*;
+use crate::modules::references::api::*;
+use crate::modules::import_export::api::*;
+use crate::modules::bulk::api::*;
+use crate::modules::jobs::queue::run_jobs_periodically;
+use crate::modules::notifications::{api::*, channel::notifier_from_env};
+use crate::modules::reminders::api::*;
+use crate::modules::templates::api::*;
+use crate::modules::comments::api::*;
+use crate::modules::events::{api::*, listener::{event_channel, listen_for_note_events}};
+use crate::modules::sync::api::sync_notes;
+use crate::modules::attachments::{api::*, store::blob_store_from_env};
+
+use crate::types::{AppState, Roles, Settings};
 use crate::middleware::*;
 
-pub const USER_TABLE_NAME: &'static str = "users";
-pub const NOTES_TABLE_NAME: &'static str = "notes";
+pub const USER_TABLE_NAME: &str = "users";
+pub const NOTES_TABLE_NAME: &str = "notes";
+pub const NOTE_REVISIONS_TABLE_NAME: &str = "note_revisions";
+pub const REVISION_RETENTION_TABLE_NAME: &str = "revision_retention_policies";
+pub const NOTE_SETTINGS_TABLE_NAME: &str = "note_settings";
+pub const TAGS_TABLE_NAME: &str = "tags";
+pub const NOTE_TAGS_TABLE_NAME: &str = "note_tags";
+pub const NOTEBOOKS_TABLE_NAME: &str = "notebooks";
+pub const NOTE_SHARES_TABLE_NAME: &str = "note_shares";
+pub const NOTE_LINKS_TABLE_NAME: &str = "note_links";
+pub const NOTE_REFERENCES_TABLE_NAME: &str = "note_references";
+pub const BLOBS_TABLE_NAME: &str = "blobs";
+pub const ATTACHMENTS_TABLE_NAME: &str = "attachments";
+pub const REMINDERS_TABLE_NAME: &str = "reminders";
+pub const JOBS_TABLE_NAME: &str = "jobs";
+pub const NOTIFICATIONS_TABLE_NAME: &str = "notifications";
+pub const TEMPLATES_TABLE_NAME: &str = "templates";
+pub const COMMENTS_TABLE_NAME: &str = "comments";
+pub const NOTE_EVENTS_TABLE_NAME: &str = "note_events";
+pub const SYNC_CLIENT_IDS_TABLE_NAME: &str = "sync_client_ids";
 
 
 async fn run_migrations(client: &mut Client) {
@@ -57,7 +92,7 @@ async fn read_file(filename: &str) -> Result<String, String> {
         format!("reading file {filename} failed with: {e}")
     })?;
     let parsed = String::from_utf8_lossy(&contents).to_string();
-    return Ok(parsed)
+    Ok(parsed)
 }
 
 
@@ -84,7 +119,7 @@ async fn main() {
     // set up connection pool
     let url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set.");
     let config = tokio_postgres::Config::from_str(&url).expect("expect config for postgresql to be Ok");
-    let manager = PostgresConnectionManager::new(config, NoTls);
+    let manager = PostgresConnectionManager::new(config.clone(), NoTls);
     let mut client = manager.connect().await.expect("database will connect");
 
     run_migrations(&mut client).await;
@@ -93,11 +128,24 @@ async fn main() {
     let state = AppState {
         pool,
         secret: jwt_secret,
-        salt
+        salt,
+        settings: Settings::from_env(),
+        blobs: blob_store_from_env(),
+        notifier: notifier_from_env(),
+        events: event_channel()
     };
 
+    // notes arrive as JSON, leave room for escaping on top of the text limit checked by the handlers
+    let note_body_limit = (state.settings.note_max_bytes * 2).max(2 * 1024 * 1024);
+
+    tokio::spawn(purge_trash_periodically(state.clone()));
+    tokio::spawn(purge_orphaned_blobs_periodically(state.clone()));
+    tokio::spawn(run_jobs_periodically(state.clone()));
+    tokio::spawn(purge_note_events_periodically(state.clone()));
+    tokio::spawn(listen_for_note_events(config, state.clone()));
 
-    use http::header::{AUTHORIZATION, ACCEPT, CONTENT_TYPE, COOKIE, SET_COOKIE, CONTENT_LENGTH};
+
+    use http::header::{AUTHORIZATION, ACCEPT, CONTENT_TYPE, COOKIE, SET_COOKIE, CONTENT_LENGTH, ETAG, IF_MATCH, IF_NONE_MATCH, LINK, RANGE, CONTENT_RANGE, ACCEPT_RANGES, CONTENT_DISPOSITION};
 
     let origins = [
         HeaderValue::from_static("http://localhost:3000"),
@@ -107,13 +155,14 @@ async fn main() {
     ];
 
     let x_total_count = HeaderName::from_static("x-total-count");
+    let x_link_password = HeaderName::from_static("x-link-password");
 
     let cors = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST, Method::DELETE])
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
         .allow_origin(AllowOrigin::list(origins))
-        .allow_headers([AUTHORIZATION, ACCEPT, CONTENT_TYPE, CONTENT_LENGTH, COOKIE, SET_COOKIE])
+        .allow_headers([AUTHORIZATION, ACCEPT, CONTENT_TYPE, CONTENT_LENGTH, COOKIE, SET_COOKIE, IF_MATCH, IF_NONE_MATCH, RANGE, x_link_password])
         .allow_credentials(AllowCredentials::yes())
-        .expose_headers([AUTHORIZATION, ACCEPT, CONTENT_TYPE, CONTENT_LENGTH, COOKIE, SET_COOKIE, x_total_count]);
+        .expose_headers([AUTHORIZATION, ACCEPT, CONTENT_TYPE, CONTENT_LENGTH, COOKIE, SET_COOKIE, ETAG, LINK, CONTENT_RANGE, ACCEPT_RANGES, CONTENT_DISPOSITION, x_total_count]);
 
     // build our application with some routes
     let app  = Router::new()
@@ -122,13 +171,270 @@ async fn main() {
             .post(using_connection_extractor),
         )
         .route("/users",
-             get(get_users)
+             get(get_users.layer(from_fn_with_state(state.clone(), auth::auth)))
             .delete(delete_user.layer(from_fn_with_state(Roles::Admin, roles::roles)))
         )
+        .route("/users/autocomplete",
+             get(autocomplete_users)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
         .route("/notes",
              get(get_notes)
             .delete(delete_note)
             .post(create_note)
+            .layer(DefaultBodyLimit::max(note_body_limit))
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id",
+             get(get_note)
+            .put(update_note)
+            .delete(delete_note_by_id)
+            .layer(DefaultBodyLimit::max(note_body_limit))
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/rendered",
+             get(get_rendered_note)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/tasks/:index",
+             put(set_note_task)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/pin",
+             put(pin_note)
+            .delete(unpin_note)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/archive",
+             put(archive_note)
+            .delete(unarchive_note)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/favorite",
+             put(favorite_note)
+            .delete(unfavorite_note)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/due",
+             put(set_note_due)
+            .delete(clear_note_due)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/reminders",
+             get(get_note_reminders)
+            .post(create_reminder)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/references",
+             get(get_note_references)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/graph",
+             get(get_note_graph)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/export",
+             get(export_notes)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/import",
+             post(import_notes)
+            .layer(DefaultBodyLimit::max(state.settings.import_max_bytes))
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/bulk/create",
+             post(bulk_create_notes)
+            .layer(DefaultBodyLimit::max(state.settings.import_max_bytes))
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/bulk/delete",
+             post(bulk_delete_notes)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/bulk/move",
+             post(bulk_move_notes)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/bulk/tag",
+             post(bulk_tag_notes)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/bulk/untag",
+             post(bulk_untag_notes)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/bulk/flags",
+             post(bulk_flag_notes)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/settings",
+             get(get_note_settings)
+            .put(set_note_settings)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/shared",
+             get(get_shared_notes)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/shares",
+             get(get_shares)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/shares/:user_id",
+             put(share_note)
+            .delete(revoke_share)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/link",
+             get(get_note_link)
+            .put(set_note_link)
+            .delete(revoke_note_link)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/link/regenerate",
+             post(regenerate_note_link)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/attachments",
+             get(get_attachments)
+            .post(upload_attachment)
+            .layer(DefaultBodyLimit::disable())
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/attachments/:attachment_id",
+             get(download_attachment)
+            .delete(delete_attachment)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/public/notes/:token",
+             get(get_public_note)
+            .post(open_public_note)
+        )
+        .route("/notes/sync",
+             post(sync_notes)
+            .layer(DefaultBodyLimit::max(state.settings.import_max_bytes))
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/events",
+             get(stream_note_events)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/trash",
+             get(get_trash)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/trash/:id",
+             delete(delete_note_permanently)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/trash/:id/restore",
+             post(restore_note)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/move",
+             post(move_note)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/tags",
+             post(attach_note_tags)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/tags/:name",
+             delete(detach_note_tag)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/revisions",
+             get(get_revisions)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/revisions/:version",
+             get(get_revision)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/revisions/:version/restore",
+             post(restore_revision)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/diff",
+             get(diff_revisions)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/revisions/retention",
+             get(get_retention_policy)
+            .put(set_retention_policy)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/reminders",
+             get(get_reminders)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/reminders/:id",
+             put(update_reminder)
+            .delete(delete_reminder)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/templates",
+             get(get_templates)
+            .post(create_template)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/templates/:id",
+             get(get_template)
+            .put(update_template)
+            .delete(delete_template)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/templates/:id/notes",
+             post(create_note_from_template)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notes/:id/comments",
+             get(get_comments)
+            .post(create_comment)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/comments/:id",
+             put(update_comment)
+            .delete(delete_comment)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notifications",
+             get(get_notifications)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notifications/read",
+             post(read_all_notifications)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notifications/:id/read",
+             put(read_notification)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/tags",
+             get(get_tags)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/tags/:id",
+             put(rename_tag)
+            .delete(delete_tag)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/tags/:id/merge",
+             post(merge_tag)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notebooks",
+             get(get_notebooks)
+            .post(create_notebook)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notebooks/:id",
+             put(rename_notebook)
+            .delete(delete_notebook)
+            .route_layer(from_fn_with_state(state.clone(), auth::auth))
+        )
+        .route("/notebooks/:id/move",
+             post(move_notebook)
             .route_layer(from_fn_with_state(state.clone(), auth::auth))
         )
         .route("/promote",
@@ -0,0 +1,413 @@
+use std::{ops::Range, path::PathBuf, time::Duration};
+
+use axum::{
+    Extension,
+    extract::{State, Query, Path, Request, FromRequest, Multipart},
+    http::{StatusCode, HeaderMap, Response, header},
+    Json, response::IntoResponse,
+    body::{Body, Bytes}
+};
+
+use futures_util::{Stream, StreamExt};
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tokio_postgres::GenericClient;
+
+use crate::{
+    types::{internal_error, AppState, Settings},
+    modules::attachments::types::*,
+    modules::common::etag,
+    modules::notes::{api::ensure_note_access, types::Access},
+    modules::users::types::User,
+    ATTACHMENTS_TABLE_NAME, BLOBS_TABLE_NAME
+};
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Unreferenced blobs younger than this are kept, an upload may be about to reference them.
+const ORPHAN_GRACE_MINUTES: i32 = 60;
+
+const MAX_FILENAME_LENGTH: usize = 255;
+
+pub async fn get_attachments(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+) -> Result<Json<Vec<Attachment>>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+    ensure_note_access(&*conn, note_id, &user, Access::Viewer).await?;
+
+    let rows = conn.query(
+        &format!("SELECT {ATTACHMENT_COLUMNS} FROM {ATTACHMENTS_TABLE_NAME} WHERE note_id=$1 ORDER BY created_at, id"),
+        &[&note_id]
+    ).await.map_err(internal_error)?;
+
+    Ok(Json(rows.iter().map(Attachment::from).collect()))
+}
+
+/// Attaches a file to a note, either as the `file` field of a `multipart/form-data` form or
+/// streamed as the raw request body (see `UploadQuery`). Identical files are stored only once.
+pub async fn upload_attachment(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    query: Query<UploadQuery>,
+    request: Request,
+) -> Result<(StatusCode, Json<Attachment>), (StatusCode, String)> {
+    {
+        let conn = state.pool.get().await.map_err(internal_error)?;
+        ensure_note_access(&*conn, note_id, &user, Access::Editor).await?;
+    }
+
+    let max_bytes = state.settings.attachment_max_bytes;
+    let request_type = request.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let (filename, content_type, staged) = if request_type.as_deref().is_some_and(|value| value.starts_with("multipart/form-data")) {
+        let mut multipart = Multipart::from_request(request, &state).await
+            .map_err(|e| (e.status(), e.body_text()))?;
+
+        let field = loop {
+            match multipart.next_field().await.map_err(|e| (e.status(), e.body_text()))? {
+                Some(field) if field.name() == Some("file") => break field,
+                Some(_) => continue,
+                None => return Err((
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    "Send the file in a form field named \"file\"".to_string()
+                )),
+            }
+        };
+
+        let filename = validate_filename(field.file_name().unwrap_or_default())?;
+        let content_type = allowed_content_type(&state.settings, field.content_type())?;
+        (filename, content_type, stage(field, max_bytes).await?)
+    } else {
+        let filename = validate_filename(query.filename.as_deref().unwrap_or_default())?;
+        let content_type = allowed_content_type(&state.settings, request_type.as_deref())?;
+
+        let declared_length = request.headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<i64>().ok());
+        if declared_length.is_some_and(|length| length > max_bytes) {
+            return Err(too_large(max_bytes));
+        }
+
+        (filename, content_type, stage(request.into_body().into_data_stream(), max_bytes).await?)
+    };
+
+    store_blob(&state, &staged).await?;
+
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    let attachment = attach_file(&tx, note_id, &staged, &filename, &content_type, user.id).await?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok((StatusCode::CREATED, Json(attachment)))
+}
+
+/// Uploads a staged file to the blob store, ahead of the transaction attaching it so no
+/// database locks are held meanwhile. Its row in `blobs` is touched first, on its own, which
+/// keeps `purge_orphaned_blobs` off the blob for `ORPHAN_GRACE_MINUTES`; if the attachment
+/// never commits, the blob is purged like any other orphan afterwards.
+pub async fn store_blob(state: &AppState, staged: &StagedFile) -> Result<(), (StatusCode, String)> {
+    {
+        let conn = state.pool.get().await.map_err(internal_error)?;
+        conn.execute(
+            &format!("INSERT INTO {BLOBS_TABLE_NAME} (hash, size) VALUES ($1, $2) \
+                ON CONFLICT (hash) DO UPDATE SET last_used_at = now()"),
+            &[&staged.hash, &staged.size]
+        ).await.map_err(internal_error)?;
+    }
+
+    if !state.blobs.exists(&staged.hash).await.map_err(internal_error)? {
+        state.blobs.put(&staged.hash, &staged.path, staged.size as u64).await.map_err(internal_error)?;
+    }
+
+    Ok(())
+}
+
+/// Attaches a staged file to `note_id` inside the caller's transaction. Its blob must already
+/// be uploaded by `store_blob`.
+pub async fn attach_file<C: GenericClient>(
+    client: &C,
+    note_id: i32,
+    staged: &StagedFile,
+    filename: &str,
+    content_type: &str,
+    uploaded_by: i32,
+) -> Result<Attachment, (StatusCode, String)> {
+    // locks the blob row until commit, so `purge_orphaned_blobs` can't remove the blob meanwhile
+    client.execute(
+        &format!("INSERT INTO {BLOBS_TABLE_NAME} (hash, size) VALUES ($1, $2) \
+            ON CONFLICT (hash) DO UPDATE SET last_used_at = now()"),
+        &[&staged.hash, &staged.size]
+    ).await.map_err(internal_error)?;
+
+    let row = client.query_one(
+        &format!("INSERT INTO {ATTACHMENTS_TABLE_NAME} (note_id, blob_hash, filename, content_type, size, uploaded_by) \
+            VALUES ($1, $2, $3, $4, $5, $6) RETURNING {ATTACHMENT_COLUMNS}"),
+        &[&note_id, &staged.hash, &filename, &content_type, &staged.size, &uploaded_by]
+    ).await.map_err(internal_error)?;
+
+    Ok(Attachment::from(&row))
+}
+
+/// Serves the attachment's content, honoring single `Range` requests and `If-None-Match`.
+pub async fn download_attachment(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path((note_id, attachment_id)): Path<(i32, i32)>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let attachment = {
+        let conn = state.pool.get().await.map_err(internal_error)?;
+        ensure_note_access(&*conn, note_id, &user, Access::Viewer).await?;
+        find_attachment(&*conn, note_id, attachment_id).await?
+    };
+
+    // blobs never change, so their hash makes a strong entity tag
+    let attachment_etag = format!("\"{}\"", attachment.hash);
+    if etag::if_none_match(&headers, &attachment_etag) {
+        return etag::not_modified(&attachment_etag);
+    }
+
+    let size = attachment.size as u64;
+    let Ok(range) = requested_range(&headers, size) else {
+        return Ok((
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [(header::CONTENT_RANGE, format!("bytes */{size}"))]
+        ).into_response());
+    };
+
+    let stream = state.blobs.get(&attachment.hash, range.clone()).await.map_err(internal_error)?;
+
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, &attachment.content_type)
+        .header(header::CONTENT_DISPOSITION, content_disposition(&attachment.filename))
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ETAG, &attachment_etag);
+
+    response = match range {
+        Some(range) => response
+            .status(StatusCode::PARTIAL_CONTENT)
+            .header(header::CONTENT_LENGTH, range.end - range.start)
+            .header(header::CONTENT_RANGE, format!("bytes {}-{}/{size}", range.start, range.end - 1)),
+        None => response.header(header::CONTENT_LENGTH, size),
+    };
+
+    response.body(Body::from_stream(stream)).map_err(internal_error)
+}
+
+/// Detaches a file from a note. Its blob is left for `purge_orphaned_blobs` once nothing uses it.
+pub async fn delete_attachment(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path((note_id, attachment_id)): Path<(i32, i32)>,
+) -> Result<Json<Attachment>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+    ensure_note_access(&*conn, note_id, &user, Access::Editor).await?;
+
+    let row = conn.query_opt(
+        &format!("DELETE FROM {ATTACHMENTS_TABLE_NAME} WHERE id=$1 AND note_id=$2 RETURNING {ATTACHMENT_COLUMNS}"),
+        &[&attachment_id, &note_id]
+    ).await.map_err(internal_error)?
+    .ok_or_else(attachment_not_found)?;
+
+    Ok(Json(Attachment::from(&row)))
+}
+
+/// Removes blobs no attachment uses anymore, both from the blob store and from `blobs`.
+pub async fn purge_orphaned_blobs(state: &AppState) -> Result<usize, (StatusCode, String)> {
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    let hashes: Vec<String> = tx.query(
+        &format!("SELECT hash FROM {BLOBS_TABLE_NAME} b \
+            WHERE last_used_at < now() - make_interval(mins => $1) \
+            AND NOT EXISTS (SELECT 1 FROM {ATTACHMENTS_TABLE_NAME} a WHERE a.blob_hash = b.hash) \
+            LIMIT 1000 FOR UPDATE SKIP LOCKED"),
+        &[&ORPHAN_GRACE_MINUTES]
+    ).await.map_err(internal_error)?
+    .iter()
+    .map(|row| row.get(0))
+    .collect();
+
+    for hash in &hashes {
+        state.blobs.delete(hash).await.map_err(internal_error)?;
+    }
+
+    tx.execute(&format!("DELETE FROM {BLOBS_TABLE_NAME} WHERE hash = ANY($1)"), &[&hashes])
+        .await.map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(hashes.len())
+}
+
+/// Runs `purge_orphaned_blobs` forever, once per `PURGE_INTERVAL`. Meant to be spawned on startup.
+pub async fn purge_orphaned_blobs_periodically(state: AppState) {
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        match purge_orphaned_blobs(&state).await {
+            Ok(purged) => tracing::debug!("purged {} orphaned blobs", purged),
+            Err((_, e)) => tracing::error!("purging orphaned blobs failed: {}", e),
+        }
+    }
+}
+
+/// An upload written to a temporary file, removed again when dropped.
+pub struct StagedFile {
+    pub path: PathBuf,
+    pub hash: String,
+    pub size: i64,
+}
+
+impl Drop for StagedFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Streams an upload to a temporary file, hashing it on the way and giving up past `max_bytes`.
+pub async fn stage<S, E>(stream: S, max_bytes: i64) -> Result<StagedFile, (StatusCode, String)>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+    E: std::fmt::Display,
+{
+    let directory = std::env::temp_dir().join("axum-backend-uploads");
+    tokio::fs::create_dir_all(&directory).await.map_err(internal_error)?;
+
+    let mut name = [0u8; 16];
+    OsRng.fill_bytes(&mut name);
+
+    let mut staged = StagedFile { path: directory.join(hex::encode(name)), hash: String::new(), size: 0 };
+    let mut file = tokio::fs::File::create(&staged.path).await.map_err(internal_error)?;
+    let mut hasher = Sha256::new();
+
+    let mut stream = std::pin::pin!(stream);
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| (StatusCode::BAD_REQUEST, format!("Upload failed: {e}")))?;
+
+        staged.size += chunk.len() as i64;
+        if staged.size > max_bytes {
+            return Err(too_large(max_bytes));
+        }
+
+        hasher.update(&chunk);
+        file.write_all(&chunk).await.map_err(internal_error)?;
+    }
+
+    file.flush().await.map_err(internal_error)?;
+    staged.hash = hex::encode(hasher.finalize());
+
+    Ok(staged)
+}
+
+/// Checks `declared` against `ATTACHMENT_ALLOWED_TYPES` and returns it without parameters.
+pub fn allowed_content_type(settings: &Settings, declared: Option<&str>) -> Result<String, (StatusCode, String)> {
+    let mime = declared
+        .unwrap_or("application/octet-stream")
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    let mime_type = mime.split('/').next().unwrap_or_default();
+
+    let allowed = settings.attachment_allowed_types
+        .split(',')
+        .map(str::trim)
+        .any(|allowed| allowed == mime || allowed == "*/*" || allowed.strip_suffix("/*") == Some(mime_type));
+
+    if !allowed {
+        return Err((
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            format!("Attachments of type {mime} are not allowed")
+        ));
+    }
+
+    Ok(mime)
+}
+
+/// Keeps only the last path segment of `filename`, as some clients send full paths.
+pub fn validate_filename(filename: &str) -> Result<String, (StatusCode, String)> {
+    let name = filename.rsplit(['/', '\\']).next().unwrap_or_default().trim();
+
+    if name.is_empty() || name.chars().count() > MAX_FILENAME_LENGTH || name.chars().any(char::is_control) {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("A file name between 1 and {MAX_FILENAME_LENGTH} characters is required")
+        ));
+    }
+
+    Ok(name.to_string())
+}
+
+/// The single byte range asked for with `Range`, `None` for the whole blob. Other units,
+/// malformed values and multiple ranges get the whole blob too, which `Range` semantics allow.
+fn requested_range(headers: &HeaderMap, size: u64) -> Result<Option<Range<u64>>, ()> {
+    let Some(spec) = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().strip_prefix("bytes="))
+        .filter(|spec| !spec.contains(','))
+    else {
+        return Ok(None);
+    };
+
+    let Some((start, end)) = spec.split_once('-').map(|(start, end)| (start.trim(), end.trim())) else {
+        return Ok(None);
+    };
+
+    let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Ok(start), Ok(end)) if start <= end => start..(end + 1).min(size),
+        (Ok(start), Err(_)) if end.is_empty() => start..size,
+        (Err(_), Ok(suffix)) if start.is_empty() && suffix > 0 => size.saturating_sub(suffix)..size,
+        _ => return Ok(None),
+    };
+
+    if range.start >= size {
+        return Err(());
+    }
+
+    Ok(Some(range))
+}
+
+fn content_disposition(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| if (c.is_ascii_graphic() || c == ' ') && c != '"' && c != '\\' { c } else { '_' })
+        .collect();
+    let encoded: String = filename
+        .bytes()
+        .map(|b| if b.is_ascii_alphanumeric() || b"-._~".contains(&b) { (b as char).to_string() } else { format!("%{b:02X}") })
+        .collect();
+
+    format!("attachment; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+}
+
+async fn find_attachment<C: GenericClient>(client: &C, note_id: i32, attachment_id: i32) -> Result<Attachment, (StatusCode, String)> {
+    let row = client.query_opt(
+        &format!("SELECT {ATTACHMENT_COLUMNS} FROM {ATTACHMENTS_TABLE_NAME} WHERE id=$1 AND note_id=$2"),
+        &[&attachment_id, &note_id]
+    ).await.map_err(internal_error)?
+    .ok_or_else(attachment_not_found)?;
+
+    Ok(Attachment::from(&row))
+}
+
+fn too_large(max_bytes: i64) -> (StatusCode, String) {
+    (StatusCode::PAYLOAD_TOO_LARGE, format!("Attachments can be at most {max_bytes} bytes"))
+}
+
+fn attachment_not_found() -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, "Attachment not found".to_string())
+}
@@ -0,0 +1,4 @@
+pub mod api;
+pub mod types;
+pub mod store;
+pub mod s3;
\ No newline at end of file
@@ -0,0 +1,143 @@
+use std::{io, ops::Range, path::Path};
+
+use axum::{async_trait, http::{header, Method, StatusCode}};
+use chrono::Utc;
+use futures_util::TryStreamExt;
+use hmac::{Hmac, Mac};
+use reqwest::{Body, Client, RequestBuilder, Response, Url};
+use sha2::{Digest, Sha256};
+use tokio_util::io::ReaderStream;
+
+use crate::{modules::attachments::store::{BlobStore, BlobStream}, types::env_or};
+
+/// Payloads are streamed, so requests are signed without hashing their body.
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// Keeps blobs in a bucket of any S3-compatible service (AWS S3, MinIO, ...), addressing
+/// objects path-style as `{endpoint}/{bucket}/{key}` and signing requests with AWS Signature V4.
+pub struct S3Store {
+    client: Client,
+    endpoint: Url,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+impl S3Store {
+    pub fn from_env() -> Self {
+        let endpoint = std::env::var("S3_ENDPOINT").expect("S3_ENDPOINT must be set.");
+
+        S3Store {
+            client: Client::new(),
+            endpoint: Url::parse(&endpoint).expect("S3_ENDPOINT must be a URL"),
+            bucket: std::env::var("S3_BUCKET").expect("S3_BUCKET must be set."),
+            region: env_or("S3_REGION", "us-east-1".to_string()),
+            access_key: std::env::var("S3_ACCESS_KEY").expect("S3_ACCESS_KEY must be set."),
+            secret_key: std::env::var("S3_SECRET_KEY").expect("S3_SECRET_KEY must be set."),
+        }
+    }
+
+    fn object_url(&self, key: &str) -> Url {
+        let path = format!("{}/{}/{}", self.endpoint.path().trim_end_matches('/'), self.bucket, key);
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+        url
+    }
+
+    /// Starts a request for `key` carrying the `Authorization` header of Signature V4.
+    fn request(&self, method: Method, key: &str) -> RequestBuilder {
+        let url = self.object_url(key);
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+
+        let host = match url.port() {
+            Some(port) => format!("{}:{port}", url.host_str().unwrap_or_default()),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{method}\n{}\n\nhost:{host}\nx-amz-content-sha256:{UNSIGNED_PAYLOAD}\nx-amz-date:{amz_date}\n\n{signed_headers}\n{UNSIGNED_PAYLOAD}",
+            url.path()
+        );
+
+        let scope = format!("{date}/{}/s3/aws4_request", self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let signing_key = [self.region.as_bytes(), b"s3", b"aws4_request"]
+            .iter()
+            .fold(hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), date.as_bytes()), |key, part| hmac_sha256(&key, part));
+        let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            self.access_key
+        );
+
+        self.client
+            .request(method, url)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", UNSIGNED_PAYLOAD)
+            .header(header::AUTHORIZATION, authorization)
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+async fn send(request: RequestBuilder, key: &str) -> io::Result<Response> {
+    let response = request.send().await.map_err(io::Error::other)?;
+
+    match response.status() {
+        status if status.is_success() => Ok(response),
+        StatusCode::NOT_FOUND => Err(io::Error::new(io::ErrorKind::NotFound, format!("blob {key} not found"))),
+        status => Err(io::Error::other(format!("S3 request for blob {key} failed with {status}"))),
+    }
+}
+
+#[async_trait]
+impl BlobStore for S3Store {
+    async fn exists(&self, key: &str) -> io::Result<bool> {
+        match send(self.request(Method::HEAD, key), key).await {
+            Ok(_) => Ok(true),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn put(&self, key: &str, source: &Path, size: u64) -> io::Result<()> {
+        let file = tokio::fs::File::open(source).await?;
+
+        let request = self.request(Method::PUT, key)
+            .header(header::CONTENT_LENGTH, size)
+            .body(Body::wrap_stream(ReaderStream::new(file)));
+
+        send(request, key).await.map(|_| ())
+    }
+
+    async fn get(&self, key: &str, range: Option<Range<u64>>) -> io::Result<BlobStream> {
+        let mut request = self.request(Method::GET, key);
+        if let Some(range) = range {
+            request = request.header(header::RANGE, format!("bytes={}-{}", range.start, range.end - 1));
+        }
+
+        let response = send(request, key).await?;
+        Ok(Box::pin(response.bytes_stream().map_err(io::Error::other)))
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match send(self.request(Method::DELETE, key), key).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::{
+    io,
+    ops::Range,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+};
+
+use axum::{async_trait, body::Bytes};
+use futures_util::Stream;
+use rand::{rngs::OsRng, RngCore};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use crate::{modules::attachments::s3::S3Store, types::env_or};
+
+pub type BlobStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>;
+
+/// Where attachment contents live. Blobs are immutable and addressed by the hex sha256 of
+/// their content, so the same key always holds the same bytes.
+#[async_trait]
+pub trait BlobStore: Send + Sync {
+    async fn exists(&self, key: &str) -> io::Result<bool>;
+
+    /// Stores the `size` bytes of the staged file at `source` under `key`.
+    async fn put(&self, key: &str, source: &Path, size: u64) -> io::Result<()>;
+
+    /// Streams the blob, or only the bytes in `range`.
+    async fn get(&self, key: &str, range: Option<Range<u64>>) -> io::Result<BlobStream>;
+
+    /// Deleting a blob that doesn't exist is not an error.
+    async fn delete(&self, key: &str) -> io::Result<()>;
+}
+
+/// Picks the store from `BLOB_STORE` (`local` or `s3`), see `.env.example` for their settings.
+pub fn blob_store_from_env() -> Arc<dyn BlobStore> {
+    match env_or("BLOB_STORE", "local".to_string()).as_str() {
+        "s3" => Arc::new(S3Store::from_env()),
+        _ => Arc::new(LocalStore::new(env_or("BLOB_DIR", "blobs".to_string()))),
+    }
+}
+
+/// Keeps blobs as files under `root`, spread over subdirectories named by the first two
+/// characters of their key.
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalStore { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let shard = key.get(..2).unwrap_or("__");
+        self.root.join(shard).join(key)
+    }
+}
+
+#[async_trait]
+impl BlobStore for LocalStore {
+    async fn exists(&self, key: &str) -> io::Result<bool> {
+        tokio::fs::try_exists(self.path(key)).await
+    }
+
+    async fn put(&self, key: &str, source: &Path, _size: u64) -> io::Result<()> {
+        let path = self.path(key);
+        if let Some(directory) = path.parent() {
+            tokio::fs::create_dir_all(directory).await?;
+        }
+
+        // copy next to the destination first so readers never see a partially written blob, each
+        // writer to its own file as concurrent uploads of the same content share the key
+        let mut suffix = [0u8; 8];
+        OsRng.fill_bytes(&mut suffix);
+        let partial = path.with_extension(format!("{}.partial", hex::encode(suffix)));
+
+        let result = match tokio::fs::copy(source, &partial).await {
+            Ok(_) => tokio::fs::rename(&partial, &path).await,
+            Err(e) => Err(e),
+        };
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&partial).await;
+        }
+        result
+    }
+
+    async fn get(&self, key: &str, range: Option<Range<u64>>) -> io::Result<BlobStream> {
+        let mut file = tokio::fs::File::open(self.path(key)).await?;
+
+        match range {
+            Some(range) => {
+                file.seek(io::SeekFrom::Start(range.start)).await?;
+                Ok(Box::pin(ReaderStream::new(file.take(range.end - range.start))))
+            }
+            None => Ok(Box::pin(ReaderStream::new(file))),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> io::Result<()> {
+        match tokio::fs::remove_file(self.path(key)).await {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::TryStreamExt;
+
+    use super::*;
+
+    fn random_hex() -> String {
+        let mut bytes = [0u8; 16];
+        OsRng.fill_bytes(&mut bytes);
+        hex::encode(bytes)
+    }
+
+    async fn read(store: &dyn BlobStore, key: &str, range: Option<Range<u64>>) -> io::Result<Vec<u8>> {
+        store.get(key, range).await?
+            .try_fold(vec![], |mut content, chunk| async move {
+                content.extend_from_slice(&chunk);
+                Ok(content)
+            })
+            .await
+    }
+
+    /// What every `BlobStore` must do: put, get whole or by range, delete, and report missing blobs.
+    async fn check_contract(store: &dyn BlobStore) {
+        let key = random_hex();
+        let content = b"0123456789abcdef".repeat(1000);
+
+        let source = std::env::temp_dir().join(format!("blob-store-test-{}", random_hex()));
+        tokio::fs::write(&source, &content).await.unwrap();
+
+        assert!(!store.exists(&key).await.unwrap());
+        assert_eq!(read(store, &key, None).await.unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        store.put(&key, &source, content.len() as u64).await.unwrap();
+        tokio::fs::remove_file(&source).await.unwrap();
+
+        assert!(store.exists(&key).await.unwrap());
+        assert_eq!(read(store, &key, None).await.unwrap(), content);
+        assert_eq!(read(store, &key, Some(5..21)).await.unwrap(), &content[5..21]);
+        assert_eq!(read(store, &key, Some(15999..16000)).await.unwrap(), b"f");
+
+        store.delete(&key).await.unwrap();
+
+        assert!(!store.exists(&key).await.unwrap());
+        assert_eq!(read(store, &key, None).await.unwrap_err().kind(), io::ErrorKind::NotFound);
+        store.delete(&key).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn local_store_keeps_the_contract() {
+        let root = std::env::temp_dir().join(format!("blob-store-test-{}", random_hex()));
+
+        check_contract(&LocalStore::new(&root)).await;
+
+        tokio::fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    /// Runs against the `S3_*` settings, e.g. `docker compose up -d minio minio-bucket` and
+    /// `cargo test -- --ignored` with the values of `.env.example`.
+    #[tokio::test]
+    #[ignore = "needs an S3 service, see docker-compose.yml"]
+    async fn s3_store_keeps_the_contract() {
+        dotenvy::dotenv().ok();
+
+        check_contract(&S3Store::from_env()).await;
+    }
+}
@@ -0,0 +1,41 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+#[derive(Serialize)]
+pub struct Attachment {
+  pub id: i32,
+  pub note_id: i32,
+  pub filename: String,
+  pub content_type: String,
+  pub size: i64,
+  /// Hex sha256 of the content, also served as the download's `ETag`.
+  pub hash: String,
+  pub uploaded_by: Option<i32>,
+  pub created_at: DateTime<Utc>
+}
+
+/// Columns expected by `Attachment::from(&Row)`, in order.
+pub const ATTACHMENT_COLUMNS: &str = "id, note_id, filename, content_type, size, blob_hash, uploaded_by, created_at";
+
+impl From<&Row> for Attachment {
+  fn from(row: &Row) -> Attachment {
+    Attachment {
+      id: row.get(0),
+      note_id: row.get(1),
+      filename: row.get(2),
+      content_type: row.get(3),
+      size: row.get(4),
+      hash: row.get(5),
+      uploaded_by: row.get(6),
+      created_at: row.get(7)
+    }
+  }
+}
+
+/// Uploads that are not `multipart/form-data` stream the file as the request body,
+/// named by `filename` and typed by the `Content-Type` header.
+#[derive(Deserialize)]
+pub struct UploadQuery {
+  pub filename: Option<String>
+}
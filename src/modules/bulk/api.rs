@@ -0,0 +1,230 @@
+use std::collections::HashSet;
+
+use axum::{
+    Extension,
+    extract::State,
+    http::StatusCode,
+    Json
+};
+
+use tokio_postgres::GenericClient;
+
+use crate::{
+    types::{internal_error, AppState, Settings},
+    modules::bulk::types::*,
+    modules::common::pagination::{KeyKind, SortKey},
+    modules::notebooks::api::ensure_notebook_owner,
+    modules::notes::{
+        api::{filter_notes, insert_note, trash_note, write_note_changes},
+        types::{CreateNotePayload, Note, NoteChanges, TagChange}
+    },
+    modules::tags::api::normalize_tags,
+    modules::users::types::User
+};
+
+/// What a bulk operation does to one note.
+enum BulkStep {
+    Create(CreateNotePayload),
+    Trash(i32),
+    Change(i32, NoteChanges),
+}
+
+pub async fn bulk_create_notes(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(body): Json<BulkCreatePayload>,
+) -> Result<(StatusCode, Json<BulkReport>), (StatusCode, String)> {
+    ensure_within_limit(&state.settings, body.notes.len())?;
+
+    let steps = body.notes.into_iter().map(BulkStep::Create).collect();
+    run_steps(&state, &user, body.mode, steps).await
+}
+
+pub async fn bulk_delete_notes(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(body): Json<BulkDeletePayload>,
+) -> Result<(StatusCode, Json<BulkReport>), (StatusCode, String)> {
+    let note_ids = select_notes(&state, &user, body.selection).await?;
+
+    let steps = note_ids.into_iter().map(BulkStep::Trash).collect();
+    run_steps(&state, &user, body.mode, steps).await
+}
+
+pub async fn bulk_move_notes(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(body): Json<BulkMovePayload>,
+) -> Result<(StatusCode, Json<BulkReport>), (StatusCode, String)> {
+    if let Some(notebook_id) = body.notebook_id {
+        let conn = state.pool.get().await.map_err(internal_error)?;
+        ensure_notebook_owner(&*conn, notebook_id, &user).await?;
+    }
+
+    let note_ids = select_notes(&state, &user, body.selection).await?;
+
+    let steps = note_ids
+        .into_iter()
+        .map(|note_id| BulkStep::Change(note_id, NoteChanges { notebook: Some(body.notebook_id), ..Default::default() }))
+        .collect();
+    run_steps(&state, &user, body.mode, steps).await
+}
+
+pub async fn bulk_tag_notes(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(body): Json<BulkTagsPayload>,
+) -> Result<(StatusCode, Json<BulkReport>), (StatusCode, String)> {
+    change_tags(&state, &user, body, TagChange::Attach).await
+}
+
+pub async fn bulk_untag_notes(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(body): Json<BulkTagsPayload>,
+) -> Result<(StatusCode, Json<BulkReport>), (StatusCode, String)> {
+    change_tags(&state, &user, body, TagChange::Detach).await
+}
+
+pub async fn bulk_flag_notes(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(body): Json<BulkFlagsPayload>,
+) -> Result<(StatusCode, Json<BulkReport>), (StatusCode, String)> {
+    if body.flags.is_empty() {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "Set at least one of pinned, archived or favorite".to_string()));
+    }
+
+    let note_ids = select_notes(&state, &user, body.selection).await?;
+
+    let steps = note_ids
+        .into_iter()
+        .map(|note_id| BulkStep::Change(note_id, NoteChanges { flags: body.flags, ..Default::default() }))
+        .collect();
+    run_steps(&state, &user, body.mode, steps).await
+}
+
+async fn change_tags(
+    state: &AppState,
+    user: &User,
+    body: BulkTagsPayload,
+    change: fn(Vec<String>) -> TagChange,
+) -> Result<(StatusCode, Json<BulkReport>), (StatusCode, String)> {
+    let tags = normalize_tags(&body.tags)?;
+    let note_ids = select_notes(state, user, body.selection).await?;
+
+    let steps = note_ids
+        .into_iter()
+        .map(|note_id| BulkStep::Change(note_id, NoteChanges { tags: change(tags.clone()), ..Default::default() }))
+        .collect();
+    run_steps(state, user, body.mode, steps).await
+}
+
+fn ensure_within_limit(settings: &Settings, count: usize) -> Result<(), (StatusCode, String)> {
+    match count > settings.bulk_max_notes {
+        true => Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Bulk operations are limited to {} notes, split this one up", settings.bulk_max_notes)
+        )),
+        false => Ok(()),
+    }
+}
+
+/// Ids of the selected notes, in the order given or by id for a filter. Listed ids are not
+/// checked here: each note fails on its own when the user can't change it.
+async fn select_notes(state: &AppState, user: &User, selection: BulkSelection) -> Result<Vec<i32>, (StatusCode, String)> {
+    let note_ids = match (selection.ids, selection.filter) {
+        (Some(mut ids), None) => {
+            let mut seen = HashSet::new();
+            ids.retain(|id| seen.insert(*id));
+            ids
+        }
+        (None, Some(filter)) => {
+            let conn = state.pool.get().await.map_err(internal_error)?;
+            let query = filter_notes(&*conn, &state.settings, user, &filter).await?;
+
+            // one past the limit tells a filter that matches too many notes apart
+            let limit = state.settings.bulk_max_notes as i64 + 1;
+            let select = query.select("id").order_by(&[SortKey::asc("id", KeyKind::Int)]).limit(&limit);
+
+            let rows = conn.query(select.sql(), select.params()).await.map_err(internal_error)?;
+            rows.iter().map(|row| row.get(0)).collect()
+        }
+        _ => return Err((StatusCode::UNPROCESSABLE_ENTITY, "Select notes with either ids or filter".to_string())),
+    };
+
+    ensure_within_limit(&state.settings, note_ids.len())?;
+    Ok(note_ids)
+}
+
+/// Applies `steps` in one transaction, each inside its own savepoint so a failure doesn't
+/// abort the others. Answers `422 Unprocessable Entity` with the report when an atomic
+/// operation was rolled back.
+async fn run_steps(
+    state: &AppState,
+    user: &User,
+    mode: BulkMode,
+    steps: Vec<BulkStep>,
+) -> Result<(StatusCode, Json<BulkReport>), (StatusCode, String)> {
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let mut tx = conn.transaction().await.map_err(internal_error)?;
+
+    let mut report = BulkReport { mode, committed: false, succeeded: 0, failed: 0, items: vec![] };
+    let mut existing: Vec<bool> = vec![];
+
+    for (index, step) in steps.into_iter().enumerate() {
+        let note_id = match &step {
+            BulkStep::Create(_) => None,
+            BulkStep::Trash(note_id) | BulkStep::Change(note_id, _) => Some(*note_id),
+        };
+        existing.push(note_id.is_some());
+
+        let savepoint = tx.transaction().await.map_err(internal_error)?;
+
+        let item = match apply_step(&savepoint, &state.settings, user, step).await {
+            Ok(note) => {
+                savepoint.commit().await.map_err(internal_error)?;
+                report.succeeded += 1;
+                BulkItemReport { index, note_id: Some(note.id), status: BulkStatus::Done, error: None }
+            }
+            Err((_, message)) => {
+                savepoint.rollback().await.map_err(internal_error)?;
+                report.failed += 1;
+                BulkItemReport { index, note_id, status: BulkStatus::Failed, error: Some(message) }
+            }
+        };
+        report.items.push(item);
+    }
+
+    report.committed = mode == BulkMode::BestEffort || report.failed == 0;
+
+    if report.committed {
+        tx.commit().await.map_err(internal_error)?;
+        return Ok((StatusCode::OK, Json(report)));
+    }
+
+    tx.rollback().await.map_err(internal_error)?;
+
+    for (item, existing) in report.items.iter_mut().zip(existing) {
+        if item.status == BulkStatus::Done {
+            item.status = BulkStatus::RolledBack;
+            // notes created by a rolled back operation are gone with it
+            item.note_id = item.note_id.filter(|_| existing);
+        }
+    }
+
+    Ok((StatusCode::UNPROCESSABLE_ENTITY, Json(report)))
+}
+
+async fn apply_step<C: GenericClient>(
+    client: &C,
+    settings: &Settings,
+    user: &User,
+    step: BulkStep,
+) -> Result<Note, (StatusCode, String)> {
+    match step {
+        BulkStep::Create(body) => insert_note(client, settings, user, body, None).await,
+        BulkStep::Trash(note_id) => trash_note(client, user, note_id, None).await,
+        BulkStep::Change(note_id, changes) => write_note_changes(client, settings, user, note_id, changes, None).await,
+    }
+}
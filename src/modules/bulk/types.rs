@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+
+use crate::modules::notes::types::{CreateNotePayload, NoteFilter, NoteFlags};
+
+/// How a bulk operation treats notes it fails on. Either way it runs in a single transaction.
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkMode {
+  /// Any failure rolls back the whole operation.
+  #[default]
+  Atomic,
+  /// Failed notes are left out, the others are committed.
+  BestEffort
+}
+
+/// The notes a bulk operation applies to: either `ids`, or every note matching `filter`, which
+/// takes the same filters as the note listing.
+#[derive(Deserialize)]
+pub struct BulkSelection {
+  pub ids: Option<Vec<i32>>,
+  pub filter: Option<NoteFilter>
+}
+
+#[derive(Deserialize)]
+pub struct BulkCreatePayload {
+  pub notes: Vec<CreateNotePayload>,
+  #[serde(default)]
+  pub mode: BulkMode
+}
+
+/// Moves the selected notes to the trash.
+#[derive(Deserialize)]
+pub struct BulkDeletePayload {
+  #[serde(flatten)]
+  pub selection: BulkSelection,
+  #[serde(default)]
+  pub mode: BulkMode
+}
+
+/// Files the selected notes in `notebook_id`, or takes them out of their notebook when `null`.
+#[derive(Deserialize)]
+pub struct BulkMovePayload {
+  #[serde(flatten)]
+  pub selection: BulkSelection,
+  pub notebook_id: Option<i32>,
+  #[serde(default)]
+  pub mode: BulkMode
+}
+
+/// Attaches `tags` to the selected notes, or detaches them.
+#[derive(Deserialize)]
+pub struct BulkTagsPayload {
+  #[serde(flatten)]
+  pub selection: BulkSelection,
+  pub tags: Vec<String>,
+  #[serde(default)]
+  pub mode: BulkMode
+}
+
+/// Pins, archives or favorites the selected notes, or undoes it, e.g. `"archived": true`.
+#[derive(Deserialize)]
+pub struct BulkFlagsPayload {
+  #[serde(flatten)]
+  pub selection: BulkSelection,
+  #[serde(flatten)]
+  pub flags: NoteFlags,
+  #[serde(default)]
+  pub mode: BulkMode
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkStatus {
+  Done,
+  Failed,
+  /// Went through, then was undone because another note failed an atomic operation.
+  RolledBack
+}
+
+#[derive(Serialize)]
+pub struct BulkItemReport {
+  /// Position of the note in the request, or among the notes matching the filter, starting at 0.
+  pub index: usize,
+  /// Unset for notes a rolled back operation would have created.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub note_id: Option<i32>,
+  pub status: BulkStatus,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct BulkReport {
+  pub mode: BulkMode,
+  /// Whether anything was written: `false` once an atomic operation had a failure.
+  pub committed: bool,
+  pub succeeded: usize,
+  pub failed: usize,
+  pub items: Vec<BulkItemReport>
+}
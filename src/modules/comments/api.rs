@@ -0,0 +1,291 @@
+use axum::{
+    Extension,
+    extract::{State, Query, Path, OriginalUri},
+    http::{StatusCode, HeaderValue, Response, header},
+    Json, response::IntoResponse,
+    body::Body
+};
+
+use tokio_postgres::GenericClient;
+
+use crate::{
+    types::{internal_error, AppState, Pagination, Roles},
+    modules::common::pagination::{link_header, KeyKind, SortKey},
+    modules::common::query::{Condition, QueryBuilder, Sql},
+    modules::comments::types::*,
+    modules::notes::{api::ensure_note_access, types::Access},
+    modules::notifications::{api::notify, types::NotificationKind},
+    modules::users::types::User,
+    COMMENTS_TABLE_NAME, NOTES_TABLE_NAME, NOTE_SHARES_TABLE_NAME, USER_TABLE_NAME
+};
+
+const MAX_COMMENT_CHARS: usize = 5000;
+
+fn comment_not_found() -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, "Comment not found".to_string())
+}
+
+/// Answers `422 Unprocessable Entity` for a blank or too long comment.
+fn validate_comment(text: &str) -> Result<(), (StatusCode, String)> {
+    if text.trim().is_empty() || text.chars().count() > MAX_COMMENT_CHARS {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, format!("Comments must be between 1 and {MAX_COMMENT_CHARS} characters")));
+    }
+
+    Ok(())
+}
+
+fn is_username_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '-')
+}
+
+/// The usernames `text` mentions as `@username`, each once. An `@` inside a word, as in an
+/// email address, is not a mention, and trailing punctuation is left out.
+fn mentioned_usernames(text: &str) -> Vec<String> {
+    let mut usernames: Vec<String> = vec![];
+    let mut previous = None;
+
+    for (index, c) in text.char_indices() {
+        if c == '@' && !previous.is_some_and(is_username_char) {
+            let rest = &text[index + 1..];
+            let end = rest.find(|c| !is_username_char(c)).unwrap_or(rest.len());
+            let username = rest[..end].trim_end_matches(['.', '-']);
+
+            if !username.is_empty() && !usernames.iter().any(|existing| existing == username) {
+                usernames.push(username.to_string());
+            }
+        }
+        previous = Some(c);
+    }
+
+    usernames
+}
+
+/// Notifies the users among `usernames` who can see `note_id`, besides `author` themselves.
+async fn notify_mentions<C: GenericClient>(
+    client: &C,
+    state: &AppState,
+    note_id: i32,
+    author: &User,
+    usernames: &[String],
+) -> Result<(), (StatusCode, String)> {
+    if usernames.is_empty() {
+        return Ok(());
+    }
+
+    let rows = client.query(
+        &format!("SELECT u.id, n.title FROM {USER_TABLE_NAME} u JOIN {NOTES_TABLE_NAME} n ON n.id = $1 \
+            WHERE u.username = ANY($2) AND u.id <> $3 AND (n.user_id = u.id OR EXISTS ( \
+                SELECT 1 FROM {NOTE_SHARES_TABLE_NAME} s WHERE s.note_id = n.id AND s.user_id = u.id))"),
+        &[&note_id, &usernames, &author.id]
+    ).await.map_err(internal_error)?;
+
+    for row in rows {
+        let title: String = row.get(1);
+        let message = match title.is_empty() {
+            true => format!("{} mentioned you on an untitled note", author.username),
+            false => format!("{} mentioned you on {title}", author.username),
+        };
+        notify(client, state, row.get(0), NotificationKind::Mention, Some(note_id), &message).await?;
+    }
+
+    Ok(())
+}
+
+async fn find_comment<C: GenericClient>(client: &C, comment_id: i32) -> Result<Comment, (StatusCode, String)> {
+    let row = client.query_one(
+        &format!("SELECT {COMMENT_COLUMNS} FROM {COMMENTS_TABLE_NAME} c JOIN {USER_TABLE_NAME} u ON u.id = c.user_id WHERE c.id=$1"),
+        &[&comment_id]
+    ).await.map_err(internal_error)?;
+
+    Ok(Comment::from(&row))
+}
+
+/// Locks a comment that is not deleted, returning its note, author and text.
+async fn lock_comment<C: GenericClient>(client: &C, comment_id: i32) -> Result<(i32, i32, String), (StatusCode, String)> {
+    let row = client.query_opt(
+        &format!("SELECT note_id, user_id, text FROM {COMMENTS_TABLE_NAME} WHERE id=$1 AND deleted_at IS NULL FOR UPDATE"),
+        &[&comment_id]
+    ).await.map_err(internal_error)?
+    .ok_or_else(comment_not_found)?;
+
+    Ok((row.get(0), row.get(1), row.get(2)))
+}
+
+/// The comments starting a thread on a note, or the replies to `parent_id`, oldest first.
+/// Anyone who can see a note can read and write its comments.
+pub async fn get_comments(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    OriginalUri(uri): OriginalUri,
+    pagination: Query<Pagination>,
+    filter: Query<CommentFilter>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let page = pagination.page(&state.settings, vec![SortKey::asc("c.id", KeyKind::Int)])?;
+
+    let conn = state.pool.get().await.map_err(internal_error)?;
+    ensure_note_access(&*conn, note_id, &user, Access::Viewer).await?;
+
+    let mut query = QueryBuilder::new(Sql::new(&format!("{COMMENTS_TABLE_NAME} c JOIN {USER_TABLE_NAME} u ON u.id = c.user_id")));
+    query.filter(Condition::eq("c.note_id", note_id));
+    query.filter(Condition::compare("c.parent_id", "IS NOT DISTINCT FROM", filter.parent_id));
+
+    let query_comments = query.select(&format!("{COMMENT_COLUMNS}{}", page.key_columns())).page(&page);
+    let query_count = query.count();
+
+    let (rows, count) = tokio::try_join!(
+        conn.query(query_comments.sql(), query_comments.params()),
+        async {
+            match pagination.with_count() {
+                true => conn.query_one(query_count.sql(), query_count.params()).await.map(|row| Some(row.get::<usize, i64>(0))),
+                false => Ok(None),
+            }
+        }
+    ).map_err(internal_error)?;
+
+    let (rows, links) = page.finish(rows)?;
+    let comments: Vec<Comment> = rows.iter().map(Comment::from).collect();
+
+    let mut response = Json(comments).into_response();
+    if let Some(count) = count {
+        let header_count_value = HeaderValue::from_str(&count.to_string()).map_err(internal_error)?;
+        response.headers_mut().insert("x-total-count", header_count_value);
+    }
+    if let Some(link_value) = link_header(&uri, &links)? {
+        response.headers_mut().insert(header::LINK, link_value);
+    }
+
+    Ok(response)
+}
+
+pub async fn create_comment(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    Json(body): Json<CreateCommentPayload>,
+) -> Result<Json<Comment>, (StatusCode, String)> {
+    validate_comment(&body.text)?;
+
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    ensure_note_access(&tx, note_id, &user, Access::Viewer).await?;
+
+    if let Some(parent_id) = body.parent_id {
+        let parent = tx.query_opt(
+            &format!("SELECT deleted_at IS NOT NULL FROM {COMMENTS_TABLE_NAME} WHERE id=$1 AND note_id=$2"),
+            &[&parent_id, &note_id]
+        ).await.map_err(internal_error)?
+        .ok_or_else(comment_not_found)?;
+
+        if parent.get(0) {
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, "Deleted comments can't be replied to".to_string()));
+        }
+    }
+
+    let row = tx.query_one(
+        &format!("INSERT INTO {COMMENTS_TABLE_NAME} (note_id, parent_id, user_id, text) VALUES ($1, $2, $3, $4) RETURNING id"),
+        &[&note_id, &body.parent_id, &user.id, &body.text]
+    ).await.map_err(internal_error)?;
+
+    let comment = find_comment(&tx, row.get(0)).await?;
+    notify_mentions(&tx, &state, note_id, &user, &mentioned_usernames(&body.text)).await?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(comment))
+}
+
+/// Changes the text of one of the current user's comments, notifying only the users it
+/// newly mentions.
+pub async fn update_comment(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(comment_id): Path<i32>,
+    Json(body): Json<UpdateCommentPayload>,
+) -> Result<Json<Comment>, (StatusCode, String)> {
+    validate_comment(&body.text)?;
+
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    let (note_id, author_id, previous_text) = lock_comment(&tx, comment_id).await?;
+    ensure_note_access(&tx, note_id, &user, Access::Viewer).await?;
+
+    if author_id != user.id {
+        return Err((StatusCode::FORBIDDEN, "Only the author of a comment can edit it".to_string()));
+    }
+
+    tx.execute(
+        &format!("UPDATE {COMMENTS_TABLE_NAME} SET text=$2, edited_at=now() WHERE id=$1"),
+        &[&comment_id, &body.text]
+    ).await.map_err(internal_error)?;
+
+    let previous_mentions = mentioned_usernames(&previous_text);
+    let new_mentions: Vec<String> = mentioned_usernames(&body.text)
+        .into_iter()
+        .filter(|username| !previous_mentions.contains(username))
+        .collect();
+
+    let comment = find_comment(&tx, comment_id).await?;
+    notify_mentions(&tx, &state, note_id, &user, &new_mentions).await?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(comment))
+}
+
+/// Deletes a comment of the current user, or any comment for moderators. Replies stay.
+pub async fn delete_comment(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(comment_id): Path<i32>,
+) -> Result<Json<Comment>, (StatusCode, String)> {
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    let (note_id, author_id, _) = lock_comment(&tx, comment_id).await?;
+    let moderator = (user.role.clone() as i16) >= (Roles::Moderator as i16);
+
+    if !moderator {
+        ensure_note_access(&tx, note_id, &user, Access::Viewer).await?;
+
+        if author_id != user.id {
+            return Err((StatusCode::FORBIDDEN, "Only the author of a comment or a moderator can delete it".to_string()));
+        }
+    }
+
+    tx.execute(
+        &format!("UPDATE {COMMENTS_TABLE_NAME} SET text='', deleted_at=now(), deleted_by=$2 WHERE id=$1"),
+        &[&comment_id, &user.id]
+    ).await.map_err(internal_error)?;
+
+    let comment = find_comment(&tx, comment_id).await?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(comment))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_each_mention_once_in_order() {
+        assert_eq!(mentioned_usernames("@bob, can you ask @alice? cc @bob"), vec!["bob", "alice"]);
+        assert_eq!(mentioned_usernames("(@carol) [@dave_2]"), vec!["carol", "dave_2"]);
+        assert_eq!(mentioned_usernames("@@erin"), vec!["erin"]);
+        assert_eq!(mentioned_usernames("hé @zoë"), vec!["zoë"]);
+    }
+
+    #[test]
+    fn keeps_dots_and_dashes_inside_usernames_only() {
+        assert_eq!(mentioned_usernames("thanks @bob."), vec!["bob"]);
+        assert_eq!(mentioned_usernames("@first.last-name- and @x--"), vec!["first.last-name", "x"]);
+    }
+
+    #[test]
+    fn skips_what_is_not_a_mention() {
+        for text in ["", "@", "@ bob", "mail me at bob@example.com", "a_@bob", "@.-"] {
+            assert!(mentioned_usernames(text).is_empty(), "{text:?}");
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+#[derive(Serialize)]
+pub struct CommentAuthor {
+  pub id: i32,
+  pub username: String
+}
+
+/// A comment on a note, `parent_id` being the comment it replies to. Deleted comments keep
+/// their place in the thread with an empty text.
+#[derive(Serialize)]
+pub struct Comment {
+  pub id: i32,
+  pub note_id: i32,
+  pub parent_id: Option<i32>,
+  pub author: CommentAuthor,
+  pub text: String,
+  pub reply_count: i64,
+  pub created_at: DateTime<Utc>,
+  pub edited_at: Option<DateTime<Utc>>,
+  pub deleted_at: Option<DateTime<Utc>>
+}
+
+/// Columns expected by `Comment::from(&Row)`, in order, given `comments c` joined with `users u`.
+pub const COMMENT_COLUMNS: &str = "c.id, c.note_id, c.parent_id, c.user_id, u.username, c.text, \
+  (SELECT count(*) FROM comments r WHERE r.parent_id = c.id), c.created_at, c.edited_at, c.deleted_at";
+
+impl From<&Row> for Comment {
+  fn from(row: &Row) -> Comment {
+    Comment {
+      id: row.get(0),
+      note_id: row.get(1),
+      parent_id: row.get(2),
+      author: CommentAuthor { id: row.get(3), username: row.get(4) },
+      text: row.get(5),
+      reply_count: row.get(6),
+      created_at: row.get(7),
+      edited_at: row.get(8),
+      deleted_at: row.get(9)
+    }
+  }
+}
+
+#[derive(Deserialize)]
+pub struct CommentFilter {
+  /// Replies to this comment, instead of the comments starting a thread.
+  pub parent_id: Option<i32>
+}
+
+/// `@username` in the text notifies that user, if they can see the note.
+#[derive(Deserialize)]
+pub struct CreateCommentPayload {
+  pub text: String,
+  pub parent_id: Option<i32>
+}
+
+#[derive(Deserialize)]
+pub struct UpdateCommentPayload {
+  pub text: String
+}
@@ -0,0 +1,80 @@
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, Response, StatusCode},
+    response::IntoResponse,
+};
+
+/// Strong entity tag for a versioned row, e.g. `"3"`.
+pub fn version_etag(version: i32) -> String {
+    format!("\"{version}\"")
+}
+
+/// Strong entity tag derived from the hash of a response representation.
+pub fn digest_etag(representation: &str) -> String {
+    format!("\"{}\"", sha256::digest(representation))
+}
+
+pub fn etag_header_value(etag: &str) -> Result<HeaderValue, (StatusCode, String)> {
+    HeaderValue::from_str(etag).map_err(crate::types::internal_error)
+}
+
+fn opaque_tag(tag: &str) -> &str {
+    tag.trim().trim_start_matches("W/")
+}
+
+fn header_tags(headers: &HeaderMap, name: header::HeaderName) -> Option<Vec<&str>> {
+    let values = headers.get_all(name);
+    let mut tags = vec![];
+    let mut present = false;
+
+    for value in values.iter() {
+        present = true;
+        if let Ok(value) = value.to_str() {
+            tags.extend(value.split(',').map(str::trim).filter(|tag| !tag.is_empty()));
+        }
+    }
+
+    present.then_some(tags)
+}
+
+/// Returns `true` when `If-None-Match` matches `etag` and a `304 Not Modified` should be sent.
+/// Uses the weak comparison function, as required for `If-None-Match`.
+pub fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    match header_tags(headers, header::IF_NONE_MATCH) {
+        Some(tags) => tags.iter().any(|tag| *tag == "*" || opaque_tag(tag) == opaque_tag(etag)),
+        None => false,
+    }
+}
+
+/// Reads `If-Match` into the list of row versions the client expects.
+///
+/// `Ok(None)` means the request is unconditional: either the header is `*` or it is absent and
+/// not `required`. Tags that are not version tags can never match and are dropped, so the
+/// caller ends up answering `412 Precondition Failed` for them.
+pub fn if_match_versions(headers: &HeaderMap, required: bool) -> Result<Option<Vec<i32>>, (StatusCode, String)> {
+    let Some(tags) = header_tags(headers, header::IF_MATCH) else {
+        if required {
+            return Err((StatusCode::PRECONDITION_REQUIRED, "If-Match header is required".to_string()));
+        }
+        return Ok(None);
+    };
+
+    if tags.contains(&"*") {
+        return Ok(None);
+    }
+
+    let versions = tags
+        .iter()
+        // If-Match uses the strong comparison function, so weak tags never match
+        .filter(|tag| !tag.starts_with("W/"))
+        .filter_map(|tag| tag.strip_prefix('"')?.strip_suffix('"')?.parse::<i32>().ok())
+        .collect();
+
+    Ok(Some(versions))
+}
+
+pub fn not_modified(etag: &str) -> Result<Response<Body>, (StatusCode, String)> {
+    let mut response = StatusCode::NOT_MODIFIED.into_response();
+    response.headers_mut().insert(header::ETAG, etag_header_value(etag)?);
+    Ok(response)
+}
@@ -0,0 +1,123 @@
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use serde_json::{json, Value};
+use tokio_postgres::Row;
+
+use crate::{modules::notes::types::NoteFormat, types::Roles};
+
+/// How a selectable column is read back from a row.
+#[derive(Clone, Copy)]
+pub enum FieldKind {
+    Int,
+    BigInt,
+    Bool,
+    Float,
+    Text,
+    Time,
+    TextArray,
+    Role,
+    NoteFormat,
+    Json,
+}
+
+impl FieldKind {
+    fn read(self, row: &Row, index: usize) -> Value {
+        match self {
+            FieldKind::Int => json!(row.get::<usize, Option<i32>>(index)),
+            FieldKind::BigInt => json!(row.get::<usize, Option<i64>>(index)),
+            FieldKind::Bool => json!(row.get::<usize, Option<bool>>(index)),
+            FieldKind::Float => json!(row.get::<usize, Option<f32>>(index)),
+            FieldKind::Text => json!(row.get::<usize, Option<String>>(index)),
+            FieldKind::Time => json!(row.get::<usize, Option<DateTime<Utc>>>(index)),
+            FieldKind::TextArray => json!(row.get::<usize, Option<Vec<String>>>(index)),
+            FieldKind::Role => json!(row.get::<usize, Option<i16>>(index).map(Roles::from)),
+            FieldKind::NoteFormat => json!(row.get::<usize, Option<i16>>(index).map(NoteFormat::from)),
+            FieldKind::Json => row.get::<usize, Option<Value>>(index).unwrap_or(Value::Null),
+        }
+    }
+}
+
+/// A field clients may select with `fields=` or embed with `include=`:
+/// response name, SQL expression and kind.
+pub type Field = (&'static str, &'static str, FieldKind);
+
+/// `fields` and `include` are comma separated lists, e.g. `fields=id,text&include=owner`.
+#[derive(serde::Deserialize)]
+pub struct FieldsQuery {
+    pub fields: Option<String>,
+    pub include: Option<String>,
+}
+
+/// The fields a listing selects, in the order they are defined by the resource.
+pub struct Selection {
+    fields: Vec<Field>,
+}
+
+fn pick(
+    parameter: &str,
+    requested: &str,
+    allowed: &[Field],
+) -> Result<Vec<Field>, (StatusCode, String)> {
+    let names: Vec<&str> = requested.split(',').map(str::trim).filter(|name| !name.is_empty()).collect();
+
+    if let Some(unknown) = names.iter().find(|name| !allowed.iter().any(|(allowed_name, _, _)| allowed_name == *name)) {
+        let allowed_names: Vec<&str> = allowed.iter().map(|(name, _, _)| *name).collect();
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Unknown {parameter} \"{unknown}\", available are: {}", allowed_names.join(", "))
+        ));
+    }
+
+    Ok(allowed.iter().filter(|(name, _, _)| names.contains(name)).copied().collect())
+}
+
+impl FieldsQuery {
+    /// Resolves the requested fields among `fields` (all of them when `fields=` is missing)
+    /// followed by the requested `includes`.
+    pub fn selection(&self, fields: &[Field], includes: &[Field]) -> Result<Selection, (StatusCode, String)> {
+        let mut selected = match &self.fields {
+            Some(requested) => pick("field", requested, fields)?,
+            None => fields.to_vec(),
+        };
+
+        if selected.is_empty() {
+            return Err((StatusCode::BAD_REQUEST, "Select at least one field".to_string()));
+        }
+
+        if let Some(requested) = &self.include {
+            selected.extend(pick("include", requested, includes)?);
+        }
+
+        Ok(Selection { fields: selected })
+    }
+}
+
+impl Selection {
+    /// The select list for the selected fields, to read back with `read`.
+    pub fn columns(&self) -> String {
+        self.fields.iter().map(|(_, expression, _)| *expression).collect::<Vec<_>>().join(", ")
+    }
+
+    /// Reads the selected fields from the leading columns of `row`.
+    pub fn read(&self, row: &Row) -> SelectedRow {
+        SelectedRow(self.fields
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _, kind))| (*name, kind.read(row, i)))
+            .collect())
+    }
+}
+
+/// A row serialized as an object of its selected fields, keeping their order.
+pub struct SelectedRow(Vec<(&'static str, Value)>);
+
+impl Serialize for SelectedRow {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, value) in &self.0 {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
@@ -0,0 +1,63 @@
+/// Translates a user search string into `to_tsquery` syntax.
+///
+/// Words are AND-ed together, `"quoted words"` must appear as a phrase, `word*` matches by
+/// prefix, `-word` excludes a word or phrase and `OR` between two terms matches either of them.
+/// Everything except letters and digits is dropped, so the result never fails to parse.
+pub fn to_tsquery_syntax(input: &str) -> String {
+    let mut query = String::new();
+    let mut pending_or = false;
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let negated = c == '-';
+        if negated {
+            chars.next();
+        }
+
+        let phrase = chars.peek() == Some(&'"');
+        let raw: String = if phrase {
+            chars.next();
+            chars.by_ref().take_while(|c| *c != '"').collect()
+        } else {
+            chars.by_ref().take_while(|c| !c.is_whitespace()).collect()
+        };
+
+        if !phrase && !negated && raw == "OR" {
+            pending_or = !query.is_empty();
+            continue;
+        }
+
+        let words: Vec<&str> = raw
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect();
+
+        if words.is_empty() {
+            continue;
+        }
+
+        let mut term = words.join(" <-> ");
+        if !phrase && raw.ends_with('*') {
+            term += ":*";
+        }
+        if words.len() > 1 {
+            term = format!("({term})");
+        }
+        if negated {
+            term = format!("!{term}");
+        }
+
+        if !query.is_empty() {
+            query += if pending_or { " | " } else { " & " };
+        }
+        pending_or = false;
+        query += &term;
+    }
+
+    query
+}
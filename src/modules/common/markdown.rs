@@ -0,0 +1,170 @@
+use std::collections::HashSet;
+
+use ammonia::Builder;
+use pulldown_cmark::{html, Event, Options, Parser, Tag, TagEnd};
+use reqwest::Url;
+use serde::Serialize;
+
+/// The only URL schemes links may use, both in rendered HTML and in outlines.
+const URL_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+
+#[derive(Serialize)]
+pub struct Heading {
+    pub level: u8,
+    pub text: String,
+    pub line: usize
+}
+
+#[derive(Serialize)]
+pub struct Link {
+    pub url: String,
+    pub text: String
+}
+
+/// A `- [ ]` or `- [x]` list item. `index` counts task items in document order, starting at 0.
+#[derive(Serialize)]
+pub struct Task {
+    pub index: usize,
+    pub text: String,
+    pub checked: bool,
+    pub line: usize
+}
+
+/// Structure extracted from a Markdown document.
+#[derive(Serialize, Default)]
+pub struct Outline {
+    pub headings: Vec<Heading>,
+    pub links: Vec<Link>,
+    pub tasks: Vec<Task>
+}
+
+fn options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS
+}
+
+/// Renders Markdown to HTML that is safe to embed in a page: raw HTML, scripts, event handlers
+/// and non-web URLs are stripped, links get `rel="noopener noreferrer nofollow"`.
+pub fn render(markdown: &str) -> String {
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, Parser::new_ext(markdown, options()));
+
+    Builder::default()
+        .add_tags(["input"])
+        .add_tag_attributes("input", ["type", "checked", "disabled"])
+        // task list checkboxes are the only inputs Markdown produces
+        .attribute_filter(|element, attribute, value| match (element, attribute) {
+            ("input", "type") if value != "checkbox" => None,
+            _ => Some(value.into()),
+        })
+        .url_schemes(HashSet::from(URL_SCHEMES))
+        .link_rel(Some("noopener noreferrer nofollow"))
+        .clean(&unsafe_html)
+        .to_string()
+}
+
+/// Renders plain text as HTML paragraphs, keeping its line breaks.
+pub fn render_plain(text: &str) -> String {
+    text
+        .split("\n\n")
+        .filter(|paragraph| !paragraph.trim().is_empty())
+        .map(|paragraph| format!("<p>{}</p>", escape_html(paragraph).replace('\n', "<br>\n")))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Whether `url` is relative or uses one of `URL_SCHEMES`, the same rule `render` applies.
+fn is_safe_url(url: &str) -> bool {
+    // relative URLs take the scheme of the base, absolute ones keep their own
+    let base = Url::parse("http://localhost/").expect("the base is a valid URL");
+    base.join(url).is_ok_and(|url| URL_SCHEMES.contains(&url.scheme()))
+}
+
+fn line_of(text: &str, offset: usize) -> usize {
+    text[..offset].matches('\n').count() + 1
+}
+
+/// Collects the headings, links and task items of a Markdown document. Links to other schemes
+/// than `URL_SCHEMES`, like `javascript:` or `data:`, are left out.
+pub fn outline(markdown: &str) -> Outline {
+    let mut outline = Outline::default();
+
+    let mut heading: Option<Heading> = None;
+    let mut link: Option<Link> = None;
+    let mut task: Option<Task> = None;
+
+    for (event, range) in Parser::new_ext(markdown, options()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => {
+                heading = Some(Heading { level: level as u8, text: String::new(), line: line_of(markdown, range.start) });
+            }
+            Event::End(TagEnd::Heading(_)) => outline.headings.extend(heading.take()),
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                link = Some(Link { url: dest_url.to_string(), text: String::new() });
+            }
+            Event::End(TagEnd::Link) => outline.links.extend(link.take().filter(|link| is_safe_url(&link.url))),
+            Event::TaskListMarker(checked) => {
+                task = Some(Task { index: outline.tasks.len(), text: String::new(), checked, line: line_of(markdown, range.start) });
+            }
+            // a task's text ends where its item does, or where a nested list starts
+            Event::Start(Tag::List(_) | Tag::Item) | Event::End(TagEnd::Item) => {
+                if let Some(mut task) = task.take() {
+                    task.text = task.text.trim().to_string();
+                    outline.tasks.push(task);
+                }
+            }
+            Event::Text(text) | Event::Code(text) => {
+                for buffer in [heading.as_mut().map(|h| &mut h.text), link.as_mut().map(|l| &mut l.text), task.as_mut().map(|t| &mut t.text)] {
+                    buffer.into_iter().for_each(|buffer| buffer.push_str(&text));
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some(task) = task.as_mut() {
+                    task.text.push(' ');
+                }
+            }
+            _ => {}
+        }
+    }
+
+    outline
+}
+
+/// Checks or unchecks the task item at `index`, changing only its marker. `None` when the
+/// document has no such task.
+pub fn set_task(markdown: &str, index: usize, checked: bool) -> Option<String> {
+    let (_, range) = Parser::new_ext(markdown, options())
+        .into_offset_iter()
+        .filter(|(event, _)| matches!(event, Event::TaskListMarker(_)))
+        .nth(index)?;
+
+    // the marker spans `[ ]`, `[x]` or `[X]`
+    let marker = if checked { "[x]" } else { "[ ]" };
+    let start = range.start + markdown[range.clone()].find('[')?;
+
+    let mut updated = markdown.to_string();
+    updated.replace_range(start..start + 3, marker);
+    Some(updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outline_leaves_out_links_to_other_schemes() {
+        let markdown = "[a](https://example.com) [b](mailto:me@example.com) [c](/notes/1) [d](#top) \
+            [e](javascript:alert(1)) [f](JavaScript:alert(1)) [g](data:text/html,x) [h](vbscript:x)";
+
+        let urls: Vec<String> = outline(markdown).links.into_iter().map(|link| link.url).collect();
+
+        assert_eq!(urls, ["https://example.com", "mailto:me@example.com", "/notes/1", "#top"]);
+    }
+}
@@ -1,10 +1,97 @@
-use tokio_postgres::types::ToSql;
+pub mod etag;
+pub mod fields;
+pub mod fulltext;
+pub mod markdown;
+pub mod pagination;
+pub mod query;
+
+use std::{ops::Bound, str::FromStr};
+
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+
+use crate::modules::common::pagination::{KeyKind, SortKey};
+use crate::modules::common::query::{Condition, QueryBuilder};
 
 #[derive(serde::Deserialize)]
 pub struct Search {
     pub search: Option<String>,
 }
 
-pub type QuieryBuildParam = (&'static str, Box<(dyn ToSql + Sync + Send)>);
+/// Sorting and filters shared by list endpoints.
+///
+/// `sort` is a comma separated list of fields, each optionally prefixed with `-` for
+/// descending order, e.g. `-created_at,username`. `id_in` is a comma separated list of ids.
+#[derive(serde::Deserialize)]
+pub struct ListQuery {
+    pub sort: Option<String>,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub id_in: Option<String>,
+}
+
+/// A field clients may sort a listing by: query parameter name, SQL expression and kind.
+pub type SortField = (&'static str, &'static str, KeyKind);
+
+/// Escapes `LIKE`/`ILIKE` wildcards so `value` only matches itself.
+pub fn escape_like(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+}
+
+/// Turns a `sort` parameter into sort keys, accepting only the `allowed` fields.
+/// `id` is appended as the final tie breaker when missing, keeping the order total.
+pub fn parse_sort(sort: &str, allowed: &[SortField]) -> Result<Vec<SortKey>, (StatusCode, String)> {
+    let mut keys: Vec<SortKey> = vec![];
+
+    for field in sort.split(',').map(str::trim).filter(|field| !field.is_empty()) {
+        let (name, descending) = match field.strip_prefix('-') {
+            Some(name) => (name, true),
+            None => (field, false),
+        };
+
+        let Some((_, expression, kind)) = allowed.iter().find(|(allowed_name, _, _)| *allowed_name == name) else {
+            let names: Vec<&str> = allowed.iter().map(|(name, _, _)| *name).collect();
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Cannot sort by \"{name}\", sortable fields are: {}", names.join(", "))
+            ));
+        };
+
+        if !keys.iter().any(|key| key.expression == *expression) {
+            keys.push(SortKey { expression: expression.to_string(), descending, kind: *kind });
+        }
+    }
+
+    if !keys.iter().any(|key| key.expression == "id") {
+        keys.push(SortKey::asc("id", KeyKind::Int));
+    }
+
+    Ok(keys)
+}
+
+/// Parses a comma separated list parameter such as `id_in=1,2,3`.
+pub fn parse_list<T: FromStr>(name: &str, value: &str) -> Result<Vec<T>, (StatusCode, String)> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| item.parse().map_err(|_| (StatusCode::BAD_REQUEST, format!("Invalid value \"{item}\" in {name}"))))
+        .collect()
+}
+
+impl ListQuery {
+    /// Adds the `created_after`, `created_before` and `id_in` conditions to `query`.
+    pub fn push_filters(&self, query: &mut QueryBuilder) -> Result<(), (StatusCode, String)> {
+        if self.created_after.is_some() || self.created_before.is_some() {
+            let lower = self.created_after.map_or(Bound::Unbounded, Bound::Excluded);
+            let upper = self.created_before.map_or(Bound::Unbounded, Bound::Excluded);
+            query.filter(Condition::range("created_at", lower, upper));
+        }
 
-pub type SqlParams = Vec<QuieryBuildParam>;
+        if let Some(id_in) = &self.id_in {
+            query.filter(Condition::is_in("id", parse_list::<i32>("id_in", id_in)?));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,339 @@
+use axum::http::{HeaderValue, StatusCode, Uri};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::{types::ToSql, Row};
+
+use crate::types::{internal_error, Pagination, Settings};
+
+/// How a sort key is compared and carried in cursors.
+#[derive(Clone, Copy)]
+pub enum KeyKind {
+    Int,
+    Float,
+    Text,
+    Time,
+}
+
+impl KeyKind {
+    fn cast(self) -> &'static str {
+        match self {
+            KeyKind::Int => "bigint",
+            KeyKind::Float => "float8",
+            KeyKind::Text => "text",
+            KeyKind::Time => "timestamptz",
+        }
+    }
+}
+
+/// One expression of a listing's `ORDER BY`. The last key of a listing must be unique
+/// (usually `id`) so the order is total and cursors never skip or repeat rows.
+#[derive(Clone)]
+pub struct SortKey {
+    pub expression: String,
+    pub descending: bool,
+    pub kind: KeyKind,
+}
+
+impl SortKey {
+    pub fn asc(expression: &str, kind: KeyKind) -> Self {
+        SortKey { expression: expression.to_string(), descending: false, kind }
+    }
+
+    pub fn desc(expression: &str, kind: KeyKind) -> Self {
+        SortKey { expression: expression.to_string(), descending: true, kind }
+    }
+}
+
+pub fn order_by(keys: &[SortKey]) -> String {
+    keys.iter()
+        .map(|key| format!("{}{}", key.expression, if key.descending { " DESC" } else { "" }))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+enum KeyValue {
+    Int(i64),
+    Float(f64),
+    Text(String),
+    Time(DateTime<Utc>),
+}
+
+impl KeyValue {
+    fn read(kind: KeyKind, row: &Row, index: usize) -> KeyValue {
+        match kind {
+            KeyKind::Int => KeyValue::Int(row.get(index)),
+            KeyKind::Float => KeyValue::Float(row.get(index)),
+            KeyKind::Text => KeyValue::Text(row.get(index)),
+            KeyKind::Time => KeyValue::Time(row.get(index)),
+        }
+    }
+
+    fn parse(kind: KeyKind, value: serde_json::Value) -> Option<KeyValue> {
+        match kind {
+            KeyKind::Int => value.as_i64().map(KeyValue::Int),
+            KeyKind::Float => value.as_f64().map(KeyValue::Float),
+            KeyKind::Text => value.as_str().map(|text| KeyValue::Text(text.to_string())),
+            KeyKind::Time => serde_json::from_value(value).ok().map(KeyValue::Time),
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            KeyValue::Int(value) => serde_json::json!(value),
+            KeyValue::Float(value) => serde_json::json!(value),
+            KeyValue::Text(value) => serde_json::json!(value),
+            KeyValue::Time(value) => serde_json::json!(value),
+        }
+    }
+
+    fn as_sql(&self) -> &(dyn ToSql + Sync) {
+        match self {
+            KeyValue::Int(value) => value,
+            KeyValue::Float(value) => value,
+            KeyValue::Text(value) => value,
+            KeyValue::Time(value) => value,
+        }
+    }
+}
+
+/// Wire format of a cursor, before base64. `s` ties the cursor to the sort it was made for.
+#[derive(Serialize, Deserialize)]
+struct CursorData {
+    s: String,
+    v: Vec<serde_json::Value>,
+}
+
+fn signature(keys: &[SortKey]) -> String {
+    sha256::digest(order_by(keys))[..12].to_string()
+}
+
+fn invalid_cursor() -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, "Invalid or outdated cursor".to_string())
+}
+
+fn encode_cursor(keys: &[SortKey], row: &Row) -> Result<String, (StatusCode, String)> {
+    let first_key_column = row.len() - keys.len();
+
+    let data = CursorData {
+        s: signature(keys),
+        v: keys.iter()
+            .enumerate()
+            .map(|(i, key)| KeyValue::read(key.kind, row, first_key_column + i).to_json())
+            .collect(),
+    };
+
+    let json = serde_json::to_vec(&data).map_err(internal_error)?;
+    Ok(URL_SAFE_NO_PAD.encode(json))
+}
+
+fn decode_cursor(keys: &[SortKey], cursor: &str) -> Result<Vec<KeyValue>, (StatusCode, String)> {
+    let json = URL_SAFE_NO_PAD.decode(cursor).map_err(|_| invalid_cursor())?;
+    let data: CursorData = serde_json::from_slice(&json).map_err(|_| invalid_cursor())?;
+
+    if data.s != signature(keys) || data.v.len() != keys.len() {
+        return Err(invalid_cursor());
+    }
+
+    keys.iter()
+        .zip(data.v)
+        .map(|(key, value)| KeyValue::parse(key.kind, value).ok_or_else(invalid_cursor))
+        .collect()
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Direction {
+    Forward,
+    Backward,
+}
+
+/// A page of a listing, either by `LIMIT/OFFSET` or by keyset (`after`/`before` cursors).
+///
+/// Keyset pages select the sort keys as extra trailing columns (see `key_columns`), so the
+/// row mappers of a listing keep working as long as they read columns by leading position.
+pub struct Page {
+    keys: Vec<SortKey>,
+    limit: i64,
+    mode: Mode,
+}
+
+enum Mode {
+    Offset(i64),
+    Keyset { direction: Direction, cursor: Option<Vec<KeyValue>> },
+}
+
+#[derive(Default)]
+pub struct PageLinks {
+    pub next: Option<String>,
+    pub prev: Option<String>,
+}
+
+impl Pagination {
+    pub fn limit(&self, settings: &Settings) -> Result<i64, (StatusCode, String)> {
+        let limit = self.limit.unwrap_or(settings.default_page_size);
+
+        if limit < 1 {
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, "limit must be at least 1".to_string()));
+        }
+
+        Ok(limit.min(settings.max_page_size))
+    }
+
+    pub fn offset(&self) -> i64 {
+        self.offset.unwrap_or(0).max(0)
+    }
+
+    /// Whether the listing should also run its `count(*)` query for `x-total-count`.
+    pub fn with_count(&self) -> bool {
+        self.count.unwrap_or(true)
+    }
+
+    /// Offset mode is kept for requests sending `offset`; everything else is paginated by keyset.
+    pub fn page(&self, settings: &Settings, keys: Vec<SortKey>) -> Result<Page, (StatusCode, String)> {
+        let limit = self.limit(settings)?;
+
+        let mode = match (self.offset, &self.after, &self.before) {
+            (Some(_), None, None) => Mode::Offset(self.offset()),
+            (Some(_), _, _) | (None, Some(_), Some(_)) => return Err((
+                StatusCode::BAD_REQUEST,
+                "Use only one of offset, after and before".to_string()
+            )),
+            (None, Some(after), None) => Mode::Keyset {
+                direction: Direction::Forward,
+                cursor: Some(decode_cursor(&keys, after)?)
+            },
+            (None, None, Some(before)) => Mode::Keyset {
+                direction: Direction::Backward,
+                cursor: Some(decode_cursor(&keys, before)?)
+            },
+            (None, None, None) => Mode::Keyset { direction: Direction::Forward, cursor: None },
+        };
+
+        Ok(Page { keys, limit, mode })
+    }
+}
+
+impl Page {
+    /// Extra select list entries carrying the sort keys, to append after a listing's own columns.
+    pub fn key_columns(&self) -> String {
+        match self.mode {
+            Mode::Offset(_) => String::new(),
+            Mode::Keyset { .. } => self.keys
+                .iter()
+                .enumerate()
+                .map(|(i, key)| format!(", ({})::{} AS sort_key_{i}", key.expression, key.kind.cast()))
+                .collect(),
+        }
+    }
+
+    /// Appends the keyset condition, `ORDER BY` and `LIMIT/OFFSET` to `query`, numbering
+    /// placeholders after the `params` already bound, and binds the values they need.
+    /// `query` must already end in a `WHERE` clause.
+    pub fn apply<'a>(&'a self, query: &mut String, params: &mut Vec<&'a (dyn ToSql + Sync)>) {
+        let (keys, limit) = (&self.keys, self.limit);
+
+        match &self.mode {
+            Mode::Offset(offset) => {
+                *query += &format!(" ORDER BY {} LIMIT ${} OFFSET ${}", order_by(keys), params.len() + 1, params.len() + 2);
+                params.push(&self.limit);
+                params.push(offset);
+            }
+            Mode::Keyset { direction, cursor } => {
+                let backward = *direction == Direction::Backward;
+
+                if let Some(values) = cursor {
+                    let first_parameter = params.len();
+                    let placeholder = |i: usize| format!("${}::{}", first_parameter + i + 1, keys[i].kind.cast());
+
+                    // (k1 > v1) OR (k1 = v1 AND k2 > v2) OR ..., with `>` flipped per direction
+                    let alternatives = (0..keys.len())
+                        .map(|i| {
+                            let mut terms: Vec<String> = (0..i)
+                                .map(|j| format!("{} = {}", keys[j].expression, placeholder(j)))
+                                .collect();
+                            let operator = if keys[i].descending != backward { "<" } else { ">" };
+                            terms.push(format!("{} {} {}", keys[i].expression, operator, placeholder(i)));
+                            format!("({})", terms.join(" AND "))
+                        })
+                        .collect::<Vec<_>>();
+
+                    *query += &format!(" AND ({})", alternatives.join(" OR "));
+                    params.extend(values.iter().map(KeyValue::as_sql));
+                }
+
+                let order_keys: Vec<SortKey> = keys
+                    .iter()
+                    .map(|key| SortKey { descending: key.descending != backward, ..key.clone() })
+                    .collect();
+
+                // one extra row tells whether there is another page in this direction
+                *query += &format!(" ORDER BY {} LIMIT {}", order_by(&order_keys), limit + 1);
+            }
+        }
+    }
+
+    /// Trims the extra row fetched by `apply`, restores the listing order and works out the cursors.
+    pub fn finish(&self, mut rows: Vec<Row>) -> Result<(Vec<Row>, PageLinks), (StatusCode, String)> {
+        let Mode::Keyset { direction, cursor } = &self.mode else {
+            return Ok((rows, PageLinks::default()));
+        };
+        let keys = &self.keys;
+
+        let has_more = rows.len() as i64 > self.limit;
+        rows.truncate(self.limit as usize);
+
+        if *direction == Direction::Backward {
+            rows.reverse();
+        }
+
+        let (more_before, more_after) = match direction {
+            Direction::Forward => (cursor.is_some(), has_more),
+            Direction::Backward => (has_more, true),
+        };
+
+        let links = PageLinks {
+            next: match rows.last() {
+                Some(row) if more_after => Some(encode_cursor(keys, row)?),
+                _ => None,
+            },
+            prev: match rows.first() {
+                Some(row) if more_before => Some(encode_cursor(keys, row)?),
+                _ => None,
+            },
+        };
+
+        Ok((rows, links))
+    }
+}
+
+/// `Link` header pointing at the neighbouring pages of the listing requested at `uri`.
+pub fn link_header(uri: &Uri, links: &PageLinks) -> Result<Option<HeaderValue>, (StatusCode, String)> {
+    let query: Vec<(String, String)> = serde_urlencoded::from_str(uri.query().unwrap_or(""))
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let link = |parameter: &str, cursor: &str, rel: &str| -> Result<String, (StatusCode, String)> {
+        let mut pairs: Vec<(&str, &str)> = query
+            .iter()
+            .filter(|(key, _)| !matches!(key.as_str(), "after" | "before" | "offset"))
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .collect();
+        pairs.push((parameter, cursor));
+
+        let query = serde_urlencoded::to_string(pairs).map_err(internal_error)?;
+        Ok(format!("<{}?{}>; rel=\"{}\"", uri.path(), query, rel))
+    };
+
+    let mut values = vec![];
+    if let Some(next) = &links.next {
+        values.push(link("after", next, "next")?);
+    }
+    if let Some(prev) = &links.prev {
+        values.push(link("before", prev, "prev")?);
+    }
+
+    if values.is_empty() {
+        return Ok(None);
+    }
+
+    HeaderValue::from_str(&values.join(", ")).map(Some).map_err(internal_error)
+}
@@ -0,0 +1,420 @@
+use std::ops::Bound;
+
+use tokio_postgres::types::ToSql;
+
+use crate::modules::common::pagination::{order_by, Page, SortKey};
+
+pub type SqlValue = Box<dyn ToSql + Sync + Send>;
+
+enum Part {
+    Text(String),
+    Value(SqlValue),
+}
+
+/// A piece of SQL text with values bound in between. Placeholders (`$1`, `$2`, ...) are only
+/// numbered when the whole query is rendered, so pieces can be composed in any order.
+#[derive(Default)]
+pub struct Sql {
+    parts: Vec<Part>,
+}
+
+impl Sql {
+    pub fn new(text: &str) -> Self {
+        Sql::default().text(text)
+    }
+
+    pub fn text(mut self, text: &str) -> Self {
+        self.parts.push(Part::Text(text.to_string()));
+        self
+    }
+
+    pub fn bind<T: ToSql + Sync + Send + 'static>(mut self, value: T) -> Self {
+        self.parts.push(Part::Value(Box::new(value)));
+        self
+    }
+
+    fn render<'a>(&'a self, sql: &mut String, params: &mut Vec<&'a (dyn ToSql + Sync)>) {
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => *sql += text,
+                Part::Value(value) => {
+                    params.push(value.as_ref() as &(dyn ToSql + Sync));
+                    *sql += &format!("${}", params.len());
+                }
+            }
+        }
+    }
+}
+
+/// A `WHERE` condition. Leaves compare an SQL expression against a bound value; expressions
+/// are written by the handlers themselves and must never come from user input.
+pub enum Condition {
+    Sql(Sql),
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+    Not(Box<Condition>),
+}
+
+impl Condition {
+    /// A condition without values, e.g. `deleted_at IS NULL`.
+    pub fn raw(sql: &str) -> Self {
+        Condition::Sql(Sql::new(sql))
+    }
+
+    /// `expression <operator> value`, e.g. `compare("version", ">=", 2)`.
+    pub fn compare<T: ToSql + Sync + Send + 'static>(expression: &str, operator: &str, value: T) -> Self {
+        Condition::Sql(Sql::new(&format!("{expression} {operator} ")).bind(value))
+    }
+
+    pub fn eq<T: ToSql + Sync + Send + 'static>(expression: &str, value: T) -> Self {
+        Condition::compare(expression, "=", value)
+    }
+
+    /// `expression = ANY(values)`. An empty list matches nothing.
+    pub fn is_in<T>(expression: &str, values: Vec<T>) -> Self
+    where
+        Vec<T>: ToSql + Sync + Send + 'static,
+    {
+        Condition::Sql(Sql::new(&format!("{expression} = ANY(")).bind(values).text(")"))
+    }
+
+    /// Case insensitive `LIKE`; `pattern` keeps its wildcards, see `escape_like`.
+    pub fn ilike(expression: &str, pattern: String) -> Self {
+        Condition::compare(expression, "ILIKE", pattern)
+    }
+
+    /// Restricts `expression` to lie between the given bounds, either of which may be unbounded.
+    pub fn range<T: ToSql + Sync + Send + 'static>(expression: &str, lower: Bound<T>, upper: Bound<T>) -> Self {
+        let mut conditions = vec![];
+
+        match lower {
+            Bound::Included(value) => conditions.push(Condition::compare(expression, ">=", value)),
+            Bound::Excluded(value) => conditions.push(Condition::compare(expression, ">", value)),
+            Bound::Unbounded => {}
+        }
+
+        match upper {
+            Bound::Included(value) => conditions.push(Condition::compare(expression, "<=", value)),
+            Bound::Excluded(value) => conditions.push(Condition::compare(expression, "<", value)),
+            Bound::Unbounded => {}
+        }
+
+        Condition::And(conditions)
+    }
+
+    pub fn and(conditions: Vec<Condition>) -> Self {
+        Condition::And(conditions)
+    }
+
+    pub fn or(conditions: Vec<Condition>) -> Self {
+        Condition::Or(conditions)
+    }
+
+    pub fn negate(condition: Condition) -> Self {
+        Condition::Not(Box::new(condition))
+    }
+
+    fn render<'a>(&'a self, sql: &mut String, params: &mut Vec<&'a (dyn ToSql + Sync)>) {
+        let (conditions, separator, empty) = match self {
+            Condition::Sql(fragment) => return fragment.render(sql, params),
+            Condition::Not(condition) => {
+                *sql += "NOT ";
+                return condition.render_grouped(sql, params);
+            }
+            Condition::And(conditions) => (conditions, " AND ", "true"),
+            Condition::Or(conditions) => (conditions, " OR ", "false"),
+        };
+
+        match conditions.as_slice() {
+            [] => *sql += empty,
+            [condition] => condition.render(sql, params),
+            conditions => {
+                for (i, condition) in conditions.iter().enumerate() {
+                    if i > 0 { *sql += separator }
+                    condition.render_grouped(sql, params);
+                }
+            }
+        }
+    }
+
+    /// Renders in parentheses, so the condition binds as one term next to other operators.
+    fn render_grouped<'a>(&'a self, sql: &mut String, params: &mut Vec<&'a (dyn ToSql + Sync)>) {
+        *sql += "(";
+        self.render(sql, params);
+        *sql += ")";
+    }
+}
+
+/// The `FROM` and `WHERE` parts of a listing, shared by its select and its count query.
+///
+/// ```ignore
+/// let mut query = QueryBuilder::new(Sql::new("notes"));
+/// query.filter(Condition::eq("user_id", 1)).filter(Condition::raw("deleted_at IS NULL"));
+///
+/// let select = query.select("id, text").page(&page);
+/// let count = query.count();
+/// // SELECT id, text FROM notes WHERE (user_id = $1) AND (deleted_at IS NULL) ORDER BY ...
+/// ```
+pub struct QueryBuilder {
+    from: Sql,
+    conditions: Vec<Condition>,
+}
+
+impl QueryBuilder {
+    pub fn new(from: Sql) -> Self {
+        QueryBuilder { from, conditions: vec![] }
+    }
+
+    pub fn filter(&mut self, condition: Condition) -> &mut Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    /// `SELECT columns FROM ... WHERE ...`; `columns` is trusted SQL.
+    pub fn select(&self, columns: &str) -> SqlQuery<'_> {
+        self.render(columns)
+    }
+
+    pub fn count(&self) -> SqlQuery<'_> {
+        self.render("count(*)")
+    }
+
+    fn render(&self, columns: &str) -> SqlQuery<'_> {
+        let mut query = SqlQuery { sql: format!("SELECT {columns} FROM "), params: vec![] };
+
+        self.from.render(&mut query.sql, &mut query.params);
+
+        // always emit a WHERE clause, `Page::apply` appends to it
+        query.sql += " WHERE ";
+        match self.conditions.as_slice() {
+            [] => query.sql += "true",
+            conditions => {
+                for (i, condition) in conditions.iter().enumerate() {
+                    if i > 0 { query.sql += " AND " }
+                    condition.render_grouped(&mut query.sql, &mut query.params);
+                }
+            }
+        }
+
+        query
+    }
+}
+
+/// A rendered query with its placeholders numbered, ready for `query`/`query_one`.
+pub struct SqlQuery<'a> {
+    sql: String,
+    params: Vec<&'a (dyn ToSql + Sync)>,
+}
+
+impl<'a> SqlQuery<'a> {
+    pub fn sql(&self) -> &str {
+        &self.sql
+    }
+
+    pub fn params(&self) -> &[&'a (dyn ToSql + Sync)] {
+        &self.params
+    }
+
+    pub fn order_by(mut self, keys: &[SortKey]) -> Self {
+        self.sql += &format!(" ORDER BY {}", order_by(keys));
+        self
+    }
+
+    pub fn limit(mut self, limit: &'a i64) -> Self {
+        self.params.push(limit);
+        self.sql += &format!(" LIMIT ${}", self.params.len());
+        self
+    }
+
+    pub fn offset(mut self, offset: &'a i64) -> Self {
+        self.params.push(offset);
+        self.sql += &format!(" OFFSET ${}", self.params.len());
+        self
+    }
+
+    /// Orders and limits the query to `page`, see `Page::apply`.
+    pub fn page(mut self, page: &'a Page) -> Self {
+        page.apply(&mut self.sql, &mut self.params);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::common::escape_like;
+    use crate::modules::common::pagination::KeyKind;
+    use crate::types::{Pagination, Settings};
+
+    fn notes() -> QueryBuilder {
+        QueryBuilder::new(Sql::new("notes"))
+    }
+
+    fn debug_params(query: &SqlQuery) -> Vec<String> {
+        query.params().iter().map(|param| format!("{param:?}")).collect()
+    }
+
+    #[test]
+    fn without_filters_matches_everything() {
+        let query = notes();
+        let select = query.select("id");
+
+        assert_eq!(select.sql(), "SELECT id FROM notes WHERE true");
+        assert!(select.params().is_empty());
+    }
+
+    #[test]
+    fn nests_and_or_not_in_parentheses() {
+        let mut query = notes();
+        query
+            .filter(Condition::eq("user_id", 1))
+            .filter(Condition::and(vec![
+                Condition::raw("deleted_at IS NULL"),
+                Condition::or(vec![
+                    Condition::eq("pinned", true),
+                    Condition::negate(Condition::or(vec![
+                        Condition::eq("archived", true),
+                        Condition::compare("version", ">=", 2),
+                    ])),
+                ]),
+            ]));
+        let select = query.select("id");
+
+        assert_eq!(
+            select.sql(),
+            "SELECT id FROM notes WHERE (user_id = $1) AND ((deleted_at IS NULL) AND \
+             ((pinned = $2) OR (NOT ((archived = $3) OR (version >= $4)))))"
+        );
+        assert_eq!(debug_params(&select), ["1", "true", "true", "2"]);
+    }
+
+    #[test]
+    fn single_conditions_are_not_grouped_twice() {
+        let mut query = notes();
+        query.filter(Condition::and(vec![Condition::or(vec![Condition::eq("id", 7)])]));
+
+        assert_eq!(query.select("id").sql(), "SELECT id FROM notes WHERE (id = $1)");
+    }
+
+    #[test]
+    fn empty_and_is_true_and_empty_or_is_false() {
+        let mut query = notes();
+        query
+            .filter(Condition::and(vec![]))
+            .filter(Condition::or(vec![]))
+            .filter(Condition::negate(Condition::or(vec![])));
+
+        assert_eq!(
+            query.select("id").sql(),
+            "SELECT id FROM notes WHERE (true) AND (false) AND (NOT (false))"
+        );
+    }
+
+    #[test]
+    fn is_in_binds_the_list_as_one_array() {
+        let mut query = notes();
+        query.filter(Condition::is_in("id", vec![1, 2, 3]));
+        let select = query.select("id");
+
+        assert_eq!(select.sql(), "SELECT id FROM notes WHERE (id = ANY($1))");
+        assert_eq!(debug_params(&select), ["[1, 2, 3]"]);
+    }
+
+    #[test]
+    fn range_leaves_out_unbounded_ends() {
+        let mut query = notes();
+        query
+            .filter(Condition::range("version", Bound::Included(1), Bound::Excluded(5)))
+            .filter(Condition::range("id", Bound::Excluded(10), Bound::Unbounded))
+            .filter(Condition::range("id", Bound::Unbounded, Bound::Included(20)))
+            .filter(Condition::range::<i32>("id", Bound::Unbounded, Bound::Unbounded));
+        let select = query.select("id");
+
+        assert_eq!(
+            select.sql(),
+            "SELECT id FROM notes WHERE ((version >= $1) AND (version < $2)) AND (id > $3) \
+             AND (id <= $4) AND (true)"
+        );
+        assert_eq!(debug_params(&select), ["1", "5", "10", "20"]);
+    }
+
+    #[test]
+    fn ilike_binds_the_escaped_pattern() {
+        let mut query = notes();
+        query.filter(Condition::ilike("title", format!("%{}%", escape_like(r"50%_off\"))));
+        let select = query.select("id");
+
+        assert_eq!(select.sql(), "SELECT id FROM notes WHERE (title ILIKE $1)");
+        assert_eq!(debug_params(&select), [format!("{:?}", r"%50\%\_off\\%")]);
+    }
+
+    #[test]
+    fn select_and_count_share_the_filters() {
+        let mut query = QueryBuilder::new(Sql::new("notes JOIN note_tags ON note_tags.note_id = notes.id AND note_tags.tag_id = ").bind(4));
+        query.filter(Condition::eq("user_id", 1));
+        let (select, count) = (query.select("id, title"), query.count());
+
+        assert_eq!(
+            select.sql(),
+            "SELECT id, title FROM notes JOIN note_tags ON note_tags.note_id = notes.id \
+             AND note_tags.tag_id = $1 WHERE (user_id = $2)"
+        );
+        assert_eq!(
+            count.sql(),
+            "SELECT count(*) FROM notes JOIN note_tags ON note_tags.note_id = notes.id \
+             AND note_tags.tag_id = $1 WHERE (user_id = $2)"
+        );
+        assert_eq!(debug_params(&select), debug_params(&count));
+    }
+
+    #[test]
+    fn limit_and_offset_number_after_the_filters() {
+        let mut query = notes();
+        query.filter(Condition::eq("user_id", 1)).filter(Condition::eq("pinned", true));
+        let (limit, offset) = (20, 40);
+        let select = query
+            .select("id")
+            .order_by(&[SortKey::desc("created_at", KeyKind::Time), SortKey::asc("id", KeyKind::Int)])
+            .limit(&limit)
+            .offset(&offset);
+
+        assert_eq!(
+            select.sql(),
+            "SELECT id FROM notes WHERE (user_id = $1) AND (pinned = $2) \
+             ORDER BY created_at DESC, id LIMIT $3 OFFSET $4"
+        );
+        assert_eq!(debug_params(&select), ["1", "true", "20", "40"]);
+    }
+
+    #[test]
+    fn offset_page_numbers_after_the_filters() {
+        let pagination = Pagination { offset: Some(30), limit: Some(10), after: None, before: None, count: None };
+        let page = pagination.page(&Settings::from_env(), vec![SortKey::asc("id", KeyKind::Int)]).unwrap();
+
+        let mut query = notes();
+        query.filter(Condition::eq("user_id", 1));
+        let select = query.select("id").page(&page);
+
+        assert_eq!(select.sql(), "SELECT id FROM notes WHERE (user_id = $1) ORDER BY id LIMIT $2 OFFSET $3");
+        assert_eq!(debug_params(&select), ["1", "10", "30"]);
+    }
+
+    #[test]
+    fn first_keyset_page_fetches_one_extra_row() {
+        let pagination = Pagination { offset: None, limit: Some(10), after: None, before: None, count: None };
+        let keys = vec![SortKey::desc("created_at", KeyKind::Time), SortKey::asc("id", KeyKind::Int)];
+        let page = pagination.page(&Settings::from_env(), keys).unwrap();
+
+        let mut query = notes();
+        query.filter(Condition::eq("user_id", 1));
+        let columns = format!("id{}", page.key_columns());
+        let select = query.select(&columns).page(&page);
+
+        assert_eq!(
+            select.sql(),
+            "SELECT id, (created_at)::timestamptz AS sort_key_0, (id)::bigint AS sort_key_1 \
+             FROM notes WHERE (user_id = $1) ORDER BY created_at DESC, id LIMIT 11"
+        );
+        assert_eq!(select.params().len(), 1);
+    }
+}
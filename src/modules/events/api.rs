@@ -0,0 +1,161 @@
+use std::{collections::HashSet, convert::Infallible, time::Duration};
+
+use axum::{
+    Extension,
+    extract::{State, Query},
+    http::{StatusCode, HeaderMap},
+    response::sse::{Event, KeepAlive, Sse},
+};
+
+use futures_util::{stream, Stream, StreamExt};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_postgres::GenericClient;
+
+use crate::{
+    types::{internal_error, AppState},
+    modules::events::types::*,
+    modules::users::types::User,
+    NOTE_EVENTS_TABLE_NAME
+};
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Most events replayed to a resuming stream, past which its client is told to start over.
+const REPLAY_LIMIT: i64 = 1000;
+
+fn sse_event(event: &NoteEvent) -> Event {
+    Event::default()
+        .id(event.id.to_string())
+        .event(event.kind.name())
+        .data(serde_json::to_string(event).unwrap_or_default())
+}
+
+/// Tells a client the events it missed are gone, so it should refetch its notes.
+fn reset_event() -> Event {
+    Event::default().event("reset").data("{}")
+}
+
+/// Whether some of the note events recorded after event `after` are gone, purged or never
+/// recorded in this database. Events get their ids in commit order, so those after `after`
+/// are exactly the ones with a greater id; the newest event is never purged, so the table
+/// only empties when the events are all gone, and then the sequence tells the last one.
+pub async fn events_missing_after<C: GenericClient>(client: &C, after: i64) -> Result<bool, (StatusCode, String)> {
+    let row = client.query_one(
+        &format!("SELECT min(id), max(id), \
+            (SELECT CASE WHEN is_called THEN last_value ELSE 0 END FROM {NOTE_EVENTS_TABLE_NAME}_id_seq) \
+            FROM {NOTE_EVENTS_TABLE_NAME}"),
+        &[]
+    ).await.map_err(internal_error)?;
+    let (oldest, latest, sequence): (Option<i64>, Option<i64>, i64) = (row.get(0), row.get(1), row.get(2));
+
+    Ok(match (oldest, latest) {
+        (Some(oldest), Some(latest)) => after < oldest - 1 || after > latest,
+        _ => after != sequence,
+    })
+}
+
+/// The id of the last note event committed: the newest one kept, or the last the sequence
+/// handed out when every event is gone.
+pub async fn latest_event_id<C: GenericClient>(client: &C) -> Result<i64, (StatusCode, String)> {
+    let row = client.query_one(
+        &format!("SELECT coalesce(max(id), \
+            (SELECT CASE WHEN is_called THEN last_value ELSE 0 END FROM {NOTE_EVENTS_TABLE_NAME}_id_seq)) \
+            FROM {NOTE_EVENTS_TABLE_NAME}"),
+        &[]
+    ).await.map_err(internal_error)?;
+
+    Ok(row.get(0))
+}
+
+/// Where a stream resumes: the `Last-Event-ID` header browsers send when reconnecting, or
+/// the `last_event_id` parameter.
+fn last_event_id(headers: &HeaderMap, query: &EventStreamQuery) -> Result<Option<i64>, (StatusCode, String)> {
+    match headers.get("last-event-id") {
+        Some(value) => value.to_str().ok()
+            .and_then(|value| value.trim().parse().ok())
+            .map(Some)
+            .ok_or_else(|| (StatusCode::BAD_REQUEST, "Invalid Last-Event-ID".to_string())),
+        None => Ok(query.last_event_id),
+    }
+}
+
+/// Streams the created, updated and deleted events of the notes the current user can see, as
+/// server-sent events whose data is the `NoteEvent`. Events recorded after the one a client
+/// resumes from are replayed first; when they were purged already, or there are too many, a
+/// `reset` event comes instead.
+pub async fn stream_note_events(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    headers: HeaderMap,
+    query: Query<EventStreamQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    let after = last_event_id(&headers, &query)?;
+
+    // subscribe before reading the replay, so events recorded in between aren't lost
+    let receiver = state.events.subscribe();
+    let mut replay: Vec<Event> = vec![];
+    let mut replayed: HashSet<i64> = HashSet::new();
+
+    if let Some(after) = after {
+        let conn = state.pool.get().await.map_err(internal_error)?;
+        let missing = events_missing_after(&*conn, after).await?;
+
+        let rows = conn.query(
+            &format!("SELECT {NOTE_EVENT_COLUMNS} FROM {NOTE_EVENTS_TABLE_NAME} \
+                WHERE id > $1 AND $2 = ANY(user_ids) ORDER BY id LIMIT $3"),
+            &[&after, &user.id, &(REPLAY_LIMIT + 1)]
+        ).await.map_err(internal_error)?;
+
+        if missing || rows.len() as i64 > REPLAY_LIMIT {
+            replay.push(reset_event());
+        } else {
+            for event in rows.iter().map(NoteEvent::from) {
+                replay.push(sse_event(&event));
+                replayed.insert(event.id);
+            }
+        }
+    }
+
+    // a stream that fell behind ends, its client reconnects and resumes from the database
+    let live = stream::unfold((receiver, replayed), move |(mut receiver, replayed)| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) if event.user_ids.contains(&user.id) && !replayed.contains(&event.id) => {
+                    return Some((sse_event(&event), (receiver, replayed)));
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(_) | RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    let events = stream::iter(replay).chain(live).map(Ok);
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Deletes the note events older than the configured retention but the newest, which
+/// `events_missing_after` relies on, returning how many.
+pub async fn purge_note_events(state: &AppState) -> Result<u64, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    conn.execute(
+        &format!("DELETE FROM {NOTE_EVENTS_TABLE_NAME} WHERE created_at < now() - make_interval(hours => $1) \
+            AND id < (SELECT max(id) FROM {NOTE_EVENTS_TABLE_NAME})"),
+        &[&state.settings.event_retention_hours]
+    ).await.map_err(internal_error)
+}
+
+/// Runs `purge_note_events` forever, once per `PURGE_INTERVAL`. Meant to be spawned on startup.
+pub async fn purge_note_events_periodically(state: AppState) {
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        match purge_note_events(&state).await {
+            Ok(purged) => tracing::debug!("purged {} note events", purged),
+            Err((_, e)) => tracing::error!("purging note events failed: {}", e),
+        }
+    }
+}
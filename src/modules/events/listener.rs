@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use futures_util::{stream, StreamExt};
+use tokio::sync::{broadcast, mpsc};
+use tokio_postgres::{AsyncMessage, Config, NoTls};
+
+use crate::{
+    types::AppState,
+    modules::events::types::*,
+    NOTE_EVENTS_TABLE_NAME
+};
+
+/// Postgres channel the triggers recording note events notify, with the event id as payload.
+const CHANNEL: &str = "note_events";
+
+/// Events buffered for each stream. A stream falling further behind is closed, and its
+/// client resumes from the last event it got.
+const BUFFER: usize = 1024;
+
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// The sender `listen_for_note_events` publishes on, kept in `AppState` for streams to subscribe.
+pub fn event_channel() -> broadcast::Sender<NoteEvent> {
+    broadcast::channel(BUFFER).0
+}
+
+/// Publishes the note events of every server process sharing the database to the streams of
+/// this one, through `LISTEN` on a dedicated connection. Meant to be spawned on startup.
+pub async fn listen_for_note_events(config: Config, state: AppState) {
+    let mut last_id = None;
+
+    loop {
+        if let Err(e) = listen(&config, &state, &mut last_id).await {
+            tracing::error!("listening for note events failed: {}", e);
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Listens until the connection is lost. After a reconnect, the events recorded meanwhile are
+/// published first; ids follow commit order, so those are the ones past the last id published.
+async fn listen(config: &Config, state: &AppState, last_id: &mut Option<i64>) -> Result<(), tokio_postgres::Error> {
+    let (client, mut connection) = config.connect(NoTls).await?;
+
+    // the connection hands out notifications while it is polled, which also runs the queries below
+    let (sender, mut payloads) = mpsc::unbounded_channel();
+    let driver = tokio::spawn(async move {
+        let mut messages = stream::poll_fn(move |cx| connection.poll_message(cx));
+
+        while let Some(message) = messages.next().await {
+            if let AsyncMessage::Notification(notification) = message? {
+                let _ = sender.send(notification.payload().to_string());
+            }
+        }
+        Ok::<(), tokio_postgres::Error>(())
+    });
+
+    client.batch_execute(&format!("LISTEN {CHANNEL}")).await?;
+
+    if let Some(after) = *last_id {
+        let rows = client.query(
+            &format!("SELECT {NOTE_EVENT_COLUMNS} FROM {NOTE_EVENTS_TABLE_NAME} WHERE id > $1 ORDER BY id"),
+            &[&after]
+        ).await?;
+
+        for row in &rows {
+            publish(state, NoteEvent::from(row), last_id);
+        }
+    }
+
+    while let Some(payload) = payloads.recv().await {
+        let Ok(event_id) = payload.parse::<i64>() else {
+            continue;
+        };
+
+        // notified while the backlog above was read, and published with it
+        if last_id.is_some_and(|last_id| event_id <= last_id) {
+            continue;
+        }
+
+        let row = client.query_opt(
+            &format!("SELECT {NOTE_EVENT_COLUMNS} FROM {NOTE_EVENTS_TABLE_NAME} WHERE id=$1"),
+            &[&event_id]
+        ).await?;
+
+        if let Some(row) = row {
+            publish(state, NoteEvent::from(&row), last_id);
+        }
+    }
+
+    match driver.await {
+        Ok(result) => result,
+        Err(_) => Ok(()),
+    }
+}
+
+/// Publishes `event` unless an event at or past its id already was.
+fn publish(state: &AppState, event: NoteEvent, last_id: &mut Option<i64>) {
+    if last_id.is_some_and(|last_id| event.id <= last_id) {
+        return;
+    }
+    *last_id = Some(event.id);
+
+    // no stream being open is no error
+    let _ = state.events.send(event);
+}
@@ -0,0 +1,3 @@
+pub mod api;
+pub mod listener;
+pub mod types;
\ No newline at end of file
@@ -0,0 +1,67 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+/// What happened to a note, stored as `smallint` by the triggers recording events. A note
+/// moved to the trash or no longer shared is deleted for its users, a restored or newly
+/// shared one created.
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteEventKind {
+  Created = 0,
+  Updated = 1,
+  Deleted = 2
+}
+
+impl From<i16> for NoteEventKind {
+  fn from(number: i16) -> NoteEventKind {
+    match number {
+      0 => Self::Created,
+      2 => Self::Deleted,
+      _ => Self::Updated
+    }
+  }
+}
+
+impl NoteEventKind {
+  /// The server-sent event name, e.g. `updated`.
+  pub fn name(self) -> &'static str {
+    match self {
+      Self::Created => "created",
+      Self::Updated => "updated",
+      Self::Deleted => "deleted"
+    }
+  }
+}
+
+#[derive(Serialize, Clone)]
+pub struct NoteEvent {
+  pub id: i64,
+  pub kind: NoteEventKind,
+  pub note_id: i32,
+  /// The users who could see the note when it changed, those the event is sent to.
+  #[serde(skip)]
+  pub user_ids: Vec<i32>,
+  pub created_at: DateTime<Utc>
+}
+
+/// Columns expected by `NoteEvent::from(&Row)`, in order.
+pub const NOTE_EVENT_COLUMNS: &str = "id, kind, note_id, user_ids, created_at";
+
+impl From<&Row> for NoteEvent {
+  fn from(row: &Row) -> NoteEvent {
+    NoteEvent {
+      id: row.get(0),
+      kind: row.get::<usize, i16>(1).into(),
+      note_id: row.get(2),
+      user_ids: row.get(3),
+      created_at: row.get(4)
+    }
+  }
+}
+
+#[derive(Deserialize)]
+pub struct EventStreamQuery {
+  /// Resumes after this event, for clients that can't send the `Last-Event-ID` header.
+  pub last_event_id: Option<i64>
+}
@@ -0,0 +1,582 @@
+use std::{
+    collections::HashMap,
+    io::{Cursor, Read, Write},
+};
+
+use axum::{
+    Extension,
+    extract::{State, Query},
+    http::{StatusCode, HeaderMap, Response, header},
+    Json, response::IntoResponse,
+    body::{Body, Bytes}
+};
+
+use futures_util::StreamExt;
+use rand::{rngs::OsRng, RngCore};
+use tokio::sync::mpsc;
+use tokio_postgres::GenericClient;
+use tokio_util::io::ReaderStream;
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    types::{internal_error, AppState},
+    modules::attachments::api::{allowed_content_type, attach_file, stage, store_blob, validate_filename},
+    modules::import_export::types::*,
+    modules::notebooks::api::validate_name,
+    modules::notes::{api::{insert_note, write_note_changes}, types::{CreateNotePayload, NoteChanges, NoteFormat, TagChange}},
+    modules::users::types::User,
+    ATTACHMENTS_TABLE_NAME, NOTEBOOKS_TABLE_NAME, NOTES_TABLE_NAME
+};
+
+/// Room for the front matter of a Markdown file, on top of the note text limit.
+const FRONT_MATTER_MAX_BYTES: usize = 64 * 1024;
+
+/// Every note of the current user outside the trash, with tags, timestamps and attachments,
+/// as a download in the requested format.
+pub async fn export_notes(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    query: Query<ExportQuery>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+    let format = query.format.unwrap_or_default();
+
+    let rows = conn.query(
+        &format!("SELECT {EXPORTED_NOTE_COLUMNS} FROM {NOTES_TABLE_NAME} \
+            WHERE user_id=$1 AND deleted_at IS NULL ORDER BY id"),
+        &[&user.id]
+    ).await.map_err(internal_error)?;
+
+    let attachment_rows = conn.query(
+        &format!("SELECT a.note_id, a.id, a.filename, a.content_type, a.size, a.blob_hash FROM {ATTACHMENTS_TABLE_NAME} a \
+            JOIN {NOTES_TABLE_NAME} n ON n.id = a.note_id \
+            WHERE n.user_id=$1 AND n.deleted_at IS NULL ORDER BY a.note_id, a.created_at, a.id"),
+        &[&user.id]
+    ).await.map_err(internal_error)?;
+
+    let mut attachments: HashMap<i32, Vec<ExportedAttachment>> = HashMap::new();
+    for row in &attachment_rows {
+        let (note_id, attachment_id, filename): (i32, i32, String) = (row.get(0), row.get(1), row.get(2));
+
+        attachments.entry(note_id).or_default().push(ExportedAttachment {
+            path: (format == ExchangeFormat::Markdown).then(|| format!("attachments/{note_id}/{attachment_id}/{filename}")),
+            filename,
+            content_type: row.get(3),
+            size: row.get(4),
+            hash: row.get(5)
+        });
+    }
+
+    let notes: Vec<ExportedNote> = rows
+        .iter()
+        .map(|row| {
+            let mut note = ExportedNote::from(row);
+            note.attachments = attachments.remove(&note.id).unwrap_or_default();
+            note
+        })
+        .collect();
+
+    match format {
+        ExchangeFormat::Json => Ok(download(Json(notes).into_response(), "notes.json")),
+        ExchangeFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(vec![]);
+            for note in notes {
+                writer.serialize(CsvNote {
+                    id: Some(note.id),
+                    title: note.title,
+                    text: note.text,
+                    format: note.format,
+                    tags: note.tags.join(","),
+                    notebook: note.notebook,
+                    created_at: Some(note.created_at),
+                    attachments: note.attachments.iter().map(|a| a.filename.as_str()).collect::<Vec<_>>().join(";")
+                }).map_err(internal_error)?;
+            }
+            let csv = writer.into_inner().map_err(|e| internal_error(e.into_error()))?;
+
+            Ok(download(([(header::CONTENT_TYPE, "text/csv; charset=utf-8")], csv).into_response(), "notes.csv"))
+        }
+        ExchangeFormat::Markdown => {
+            let body = markdown_zip(&state, &notes).await?;
+            Ok(download(([(header::CONTENT_TYPE, "application/zip")], body).into_response(), "notes.zip"))
+        }
+    }
+}
+
+fn download(mut response: Response<Body>, filename: &str) -> Response<Body> {
+    let disposition = header::HeaderValue::from_str(&format!("attachment; filename=\"{filename}\""))
+        .expect("export file names are plain ASCII");
+    response.headers_mut().insert(header::CONTENT_DISPOSITION, disposition);
+    response
+}
+
+/// What `markdown_zip` hands its blocking zip writer: the start of the next file, or more of
+/// its content.
+enum ZipWrite {
+    File(String, SimpleFileOptions),
+    Data(Bytes),
+}
+
+/// Writes the notes as Markdown files, and their attachments, to a zip in a temporary file and
+/// streams it back. Attachments can be large, so the archive is never held in memory: their
+/// chunks go through a channel to the zip writer, which does its file IO on a blocking thread.
+async fn markdown_zip(state: &AppState, notes: &[ExportedNote]) -> Result<Body, (StatusCode, String)> {
+    let directory = std::env::temp_dir().join("axum-backend-exports");
+    tokio::fs::create_dir_all(&directory).await.map_err(internal_error)?;
+
+    let mut name = [0u8; 16];
+    OsRng.fill_bytes(&mut name);
+    let path = directory.join(hex::encode(name));
+
+    let (sender, mut receiver) = mpsc::channel::<ZipWrite>(16);
+    let zip_path = path.clone();
+    let writer = tokio::task::spawn_blocking(move || -> Result<(), (StatusCode, String)> {
+        let mut zip = ZipWriter::new(std::fs::File::create(&zip_path).map_err(internal_error)?);
+
+        while let Some(write) = receiver.blocking_recv() {
+            match write {
+                ZipWrite::File(name, options) => zip.start_file(name, options).map_err(internal_error)?,
+                ZipWrite::Data(data) => zip.write_all(&data).map_err(internal_error)?,
+            }
+        }
+
+        zip.finish().map_err(internal_error)?;
+        Ok(())
+    });
+
+    let sent = send_markdown_zip(state, notes, &sender).await;
+    drop(sender);
+
+    // a writer that failed hung up on the sender, its own error says why
+    let result = match writer.await.map_err(internal_error) {
+        Ok(Ok(())) => match sent {
+            Ok(()) => tokio::fs::File::open(&path).await.map_err(internal_error),
+            Err(e) => Err(e),
+        },
+        Ok(Err(e)) | Err(e) => Err(e),
+    };
+
+    // the open file keeps its contents readable until the response is sent
+    let _ = tokio::fs::remove_file(&path).await;
+
+    Ok(Body::from_stream(ReaderStream::new(result?)))
+}
+
+/// Feeds the files of `markdown_zip` to its writer.
+async fn send_markdown_zip(
+    state: &AppState,
+    notes: &[ExportedNote],
+    sender: &mpsc::Sender<ZipWrite>,
+) -> Result<(), (StatusCode, String)> {
+    let send = |write: ZipWrite| async move {
+        sender.send(write).await.map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "Zip writer stopped".to_string()))
+    };
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for note in notes {
+        send(ZipWrite::File(format!("{}-{}.md", note.id, slug(&note.title)), options)).await?;
+        send(ZipWrite::Data(Bytes::from(markdown_document(note)?))).await?;
+
+        for attachment in &note.attachments {
+            let Some(attachment_path) = &attachment.path else { continue };
+
+            // attachments are mostly compressed already
+            send(ZipWrite::File(attachment_path.clone(), options.compression_method(CompressionMethod::Stored))).await?;
+
+            let mut stream = state.blobs.get(&attachment.hash, None).await.map_err(internal_error)?;
+            while let Some(chunk) = stream.next().await {
+                send(ZipWrite::Data(chunk.map_err(internal_error)?)).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn markdown_document(note: &ExportedNote) -> Result<String, (StatusCode, String)> {
+    let front_matter = FrontMatter {
+        title: Some(note.title.clone()),
+        format: note.format,
+        tags: note.tags.clone(),
+        notebook: note.notebook.clone(),
+        created_at: Some(note.created_at),
+        attachments: note.attachments.iter().filter_map(|attachment| attachment.path.clone()).collect()
+    };
+
+    let yaml = serde_yaml::to_string(&front_matter).map_err(internal_error)?;
+    Ok(format!("---\n{yaml}---\n{}", note.text))
+}
+
+/// File name friendly version of a title, `untitled` when nothing is left of it.
+fn slug(title: &str) -> String {
+    let slug: String = title
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect::<String>()
+        .split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+        .chars()
+        .take(50)
+        .collect();
+
+    if slug.is_empty() { "untitled".to_string() } else { slug }
+}
+
+/// Imports notes from the request body, in the format given by `format` or `Content-Type`.
+/// Each note is imported on its own, so failures are reported per note and don't stop the
+/// others; a dry run goes through the same steps and rolls everything back.
+pub async fn import_notes(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    query: Query<ImportQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ImportReport>, (StatusCode, String)> {
+    let format = query.format.unwrap_or_else(|| {
+        let content_type = headers.get(header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or("");
+        match content_type.split(';').next().unwrap_or_default().trim() {
+            "application/zip" | "application/x-zip-compressed" => ExchangeFormat::Markdown,
+            "text/csv" => ExchangeFormat::Csv,
+            _ => ExchangeFormat::Json,
+        }
+    });
+    let dry_run = query.dry_run.unwrap_or(false);
+    let strategy = query.on_duplicate.unwrap_or_default();
+
+    let items = match format {
+        ExchangeFormat::Json => json_items(&body)?,
+        ExchangeFormat::Csv => csv_items(&body),
+        ExchangeFormat::Markdown => {
+            let limit = state.settings.note_max_bytes + FRONT_MATTER_MAX_BYTES;
+            tokio::task::spawn_blocking(move || zip_items(body, limit)).await.map_err(internal_error)??
+        }
+    };
+
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let mut tx = conn.transaction().await.map_err(internal_error)?;
+
+    let mut report = ImportReport { dry_run, created: 0, updated: 0, skipped: 0, failed: 0, items: vec![] };
+
+    for (index, item) in items.into_iter().enumerate() {
+        let title = item.note.as_ref().ok().map(|note| note.title.trim().to_string());
+
+        let outcome = match item.note {
+            Ok(note) => {
+                let savepoint = tx.transaction().await.map_err(internal_error)?;
+
+                match import_note(&state, &savepoint, &user, note, &item.attachments, strategy, dry_run).await {
+                    Ok(outcome) => {
+                        savepoint.commit().await.map_err(internal_error)?;
+                        Ok(outcome)
+                    }
+                    Err((_, message)) => {
+                        savepoint.rollback().await.map_err(internal_error)?;
+                        Err(message)
+                    }
+                }
+            }
+            Err(message) => Err(message),
+        };
+
+        let (status, note_id, error) = match outcome {
+            Ok((status, note_id)) => (status, note_id, None),
+            Err(message) => (ImportStatus::Failed, None, Some(message)),
+        };
+
+        match status {
+            ImportStatus::Created => report.created += 1,
+            ImportStatus::Updated => report.updated += 1,
+            ImportStatus::Skipped => report.skipped += 1,
+            ImportStatus::Failed => report.failed += 1,
+        }
+
+        report.items.push(ImportItemReport {
+            index,
+            source: item.source,
+            title,
+            status,
+            // notes created by a dry run are rolled back with it
+            note_id: note_id.filter(|_| !(dry_run && status == ImportStatus::Created)),
+            error
+        });
+    }
+
+    match dry_run {
+        true => tx.rollback().await.map_err(internal_error)?,
+        false => tx.commit().await.map_err(internal_error)?,
+    }
+
+    Ok(Json(report))
+}
+
+/// A note read from an import, or why it couldn't be read.
+struct ImportItem {
+    source: Option<String>,
+    note: Result<ImportedNote, String>,
+    /// The zip holding the files listed in `note`'s front matter, for Markdown imports.
+    attachments: Option<ZipAttachments>,
+}
+
+struct ZipAttachments {
+    archive: Bytes,
+    paths: Vec<String>,
+}
+
+fn json_items(body: &[u8]) -> Result<Vec<ImportItem>, (StatusCode, String)> {
+    let values: Vec<serde_json::Value> = serde_json::from_slice(body)
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Expected a JSON array of notes: {e}")))?;
+
+    Ok(values
+        .into_iter()
+        .map(|value| ImportItem {
+            source: None,
+            note: serde_json::from_value(value).map_err(|e| format!("Invalid note: {e}")),
+            attachments: None,
+        })
+        .collect())
+}
+
+fn csv_items(body: &[u8]) -> Vec<ImportItem> {
+    let mut reader = csv::Reader::from_reader(body);
+
+    reader
+        .deserialize::<CsvNote>()
+        .enumerate()
+        .map(|(index, row)| ImportItem {
+            // the header is row 1
+            source: Some(format!("row {}", index + 2)),
+            note: row
+                .map(|row| ImportedNote {
+                    title: row.title,
+                    text: row.text,
+                    format: row.format,
+                    tags: row.tags.split(',').map(str::trim).filter(|tag| !tag.is_empty()).map(str::to_string).collect(),
+                    notebook: row.notebook.filter(|notebook| !notebook.trim().is_empty()),
+                    created_at: row.created_at
+                })
+                .map_err(|e| format!("Invalid row: {e}")),
+            attachments: None,
+        })
+        .collect()
+}
+
+/// Reads every `.md` file of a zip, except the ones listed as attachments. The attachments
+/// themselves are only read when their note is imported.
+fn zip_items(archive_bytes: Bytes, limit: usize) -> Result<Vec<ImportItem>, (StatusCode, String)> {
+    let mut archive = ZipArchive::new(Cursor::new(archive_bytes.clone()))
+        .map_err(|e| (StatusCode::BAD_REQUEST, format!("Expected a zip archive: {e}")))?;
+
+    let names: Vec<String> = archive.file_names()
+        .filter(|name| name.to_lowercase().ends_with(".md"))
+        .map(str::to_string)
+        .collect();
+
+    let mut documents = vec![];
+    for name in names {
+        let document = archive.by_name(&name)
+            .map_err(|e| e.to_string())
+            .and_then(|entry| read_limited(entry, limit))
+            .and_then(|content| String::from_utf8(content).map_err(|_| "File is not valid UTF-8".to_string()))
+            .and_then(|content| parse_markdown_document(&name, &content));
+        documents.push((name, document));
+    }
+
+    let attached: Vec<String> = documents.iter()
+        .filter_map(|(_, document)| document.as_ref().ok())
+        .flat_map(|(_, paths)| paths.clone())
+        .collect();
+
+    let mut items: Vec<ImportItem> = documents
+        .into_iter()
+        .filter(|(name, _)| !attached.contains(name))
+        .map(|(name, document)| match document {
+            Ok((note, paths)) => ImportItem {
+                source: Some(name),
+                note: Ok(note),
+                attachments: Some(ZipAttachments { archive: archive_bytes.clone(), paths }),
+            },
+            Err(message) => ImportItem { source: Some(name), note: Err(message), attachments: None },
+        })
+        .collect();
+
+    items.sort_by(|a, b| a.source.cmp(&b.source));
+    Ok(items)
+}
+
+fn read_limited(entry: impl Read, limit: usize) -> Result<Vec<u8>, String> {
+    let mut content = vec![];
+    entry.take(limit as u64 + 1).read_to_end(&mut content).map_err(|e| e.to_string())?;
+
+    match content.len() > limit {
+        true => Err(format!("File is larger than {limit} bytes")),
+        false => Ok(content),
+    }
+}
+
+/// Splits a Markdown file into its note and the paths of its attachments. Files without
+/// front matter are plain Markdown notes titled after their name.
+fn parse_markdown_document(name: &str, content: &str) -> Result<(ImportedNote, Vec<String>), String> {
+    let (front_matter, text) = match content.strip_prefix("---\n").and_then(|rest| rest.split_once("\n---\n")) {
+        Some((yaml, text)) => {
+            let front_matter: FrontMatter = serde_yaml::from_str(yaml).map_err(|e| format!("Invalid front matter: {e}"))?;
+            (front_matter, text)
+        }
+        None => (FrontMatter { format: NoteFormat::Markdown, ..Default::default() }, content),
+    };
+
+    let stem = name.rsplit('/').next().unwrap_or(name);
+    let stem = &stem[..stem.len() - ".md".len()];
+
+    let note = ImportedNote {
+        title: front_matter.title.unwrap_or_else(|| stem.to_string()),
+        text: text.to_string(),
+        format: front_matter.format,
+        tags: front_matter.tags,
+        notebook: front_matter.notebook,
+        created_at: front_matter.created_at
+    };
+
+    Ok((note, front_matter.attachments))
+}
+
+/// Imports one note inside `client`'s savepoint, returning what happened to it.
+async fn import_note<C: GenericClient>(
+    state: &AppState,
+    client: &C,
+    user: &User,
+    note: ImportedNote,
+    attachments: &Option<ZipAttachments>,
+    strategy: DuplicateStrategy,
+    dry_run: bool,
+) -> Result<(ImportStatus, Option<i32>), (StatusCode, String)> {
+    let title = note.title.trim().to_string();
+
+    let notebook_id = match &note.notebook {
+        Some(name) => Some(find_or_create_notebook(client, user, name).await?),
+        None => None,
+    };
+
+    let duplicate = client.query_opt(
+        &format!("SELECT id FROM {NOTES_TABLE_NAME} WHERE user_id=$1 AND deleted_at IS NULL AND CASE \
+                WHEN $2 <> '' THEN lower(title) = lower($2) \
+                ELSE title = '' AND text = $3 \
+            END ORDER BY id LIMIT 1"),
+        &[&user.id, &title, &note.text]
+    ).await.map_err(internal_error)?
+    .map(|row| row.get::<usize, i32>(0));
+
+    let (status, note_id) = match (duplicate, strategy) {
+        (Some(note_id), DuplicateStrategy::Skip) => return Ok((ImportStatus::Skipped, Some(note_id))),
+        (Some(note_id), DuplicateStrategy::Overwrite) => {
+            let changes = NoteChanges {
+                title: Some(title),
+                text: Some(note.text),
+                format: Some(note.format),
+                tags: TagChange::Replace(note.tags),
+                notebook: Some(notebook_id),
+                ..Default::default()
+            };
+            let updated = write_note_changes(client, &state.settings, user, note_id, changes, None).await?;
+            (ImportStatus::Updated, updated.id)
+        }
+        _ => {
+            let payload = CreateNotePayload { title, text: note.text, format: note.format, tags: note.tags, notebook_id };
+            let created = insert_note(client, &state.settings, user, payload, note.created_at).await?;
+            (ImportStatus::Created, created.id)
+        }
+    };
+
+    if let Some(attachments) = attachments {
+        for path in &attachments.paths {
+            import_attachment(state, client, user, note_id, attachments.archive.clone(), path, dry_run).await?;
+        }
+    }
+
+    Ok((status, Some(note_id)))
+}
+
+/// Attaches the file at `path` in the zip to `note_id`, unless the note already has the same
+/// file under the same name. A dry run only checks the file is there and would be accepted.
+async fn import_attachment<C: GenericClient>(
+    state: &AppState,
+    client: &C,
+    user: &User,
+    note_id: i32,
+    archive: Bytes,
+    path: &str,
+    dry_run: bool,
+) -> Result<(), (StatusCode, String)> {
+    let filename = validate_filename(path)?;
+    let content_type = allowed_content_type(&state.settings, Some(guess_content_type(&filename)))?;
+
+    let max_bytes = state.settings.attachment_max_bytes;
+    let entry_path = path.to_string();
+    let content = tokio::task::spawn_blocking(move || {
+        let mut archive = ZipArchive::new(Cursor::new(archive)).map_err(|e| e.to_string())?;
+        let entry = archive.by_name(&entry_path).map_err(|_| format!("Attachment {entry_path} is missing from the zip"))?;
+        read_limited(entry, max_bytes as usize)
+    }).await.map_err(internal_error)?
+    .map_err(|message| (StatusCode::UNPROCESSABLE_ENTITY, message))?;
+
+    if dry_run {
+        return Ok(());
+    }
+
+    let staged = stage(futures_util::stream::once(async { Ok::<_, std::io::Error>(Bytes::from(content)) }), max_bytes).await?;
+
+    let existing = client.query_opt(
+        &format!("SELECT 1 FROM {ATTACHMENTS_TABLE_NAME} WHERE note_id=$1 AND blob_hash=$2 AND filename=$3"),
+        &[&note_id, &staged.hash, &filename]
+    ).await.map_err(internal_error)?;
+
+    if existing.is_none() {
+        store_blob(state, &staged).await?;
+        attach_file(client, note_id, &staged, &filename, &content_type, user.id).await?;
+    }
+
+    Ok(())
+}
+
+/// Zips carry no content types, so they are told from the file extension.
+fn guess_content_type(filename: &str) -> &'static str {
+    let extension = filename.rsplit_once('.').map(|(_, extension)| extension.to_lowercase()).unwrap_or_default();
+
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "pdf" => "application/pdf",
+        "txt" => "text/plain",
+        "md" => "text/markdown",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "zip" => "application/zip",
+        _ => "application/octet-stream",
+    }
+}
+
+/// The user's notebook called `name`, created at the top level when there is none.
+async fn find_or_create_notebook<C: GenericClient>(client: &C, user: &User, name: &str) -> Result<i32, (StatusCode, String)> {
+    let name = validate_name(name)?;
+
+    let existing = client.query_opt(
+        &format!("SELECT id FROM {NOTEBOOKS_TABLE_NAME} WHERE user_id=$1 AND name=$2 ORDER BY id LIMIT 1"),
+        &[&user.id, &name]
+    ).await.map_err(internal_error)?;
+
+    if let Some(row) = existing {
+        return Ok(row.get(0));
+    }
+
+    let row = client.query_one(
+        &format!("INSERT INTO {NOTEBOOKS_TABLE_NAME} (user_id, name) VALUES ($1, $2) RETURNING id"),
+        &[&user.id, &name]
+    ).await.map_err(internal_error)?;
+
+    Ok(row.get(0))
+}
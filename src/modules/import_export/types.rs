@@ -0,0 +1,177 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use crate::modules::notes::types::NoteFormat;
+
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExchangeFormat {
+  /// An array of notes.
+  #[default]
+  Json,
+  /// A zip of Markdown files with YAML front matter, and the attachments next to them.
+  Markdown,
+  /// One note per row, see `CsvNote`.
+  Csv
+}
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+  pub format: Option<ExchangeFormat>
+}
+
+#[derive(Serialize)]
+pub struct ExportedAttachment {
+  pub filename: String,
+  pub content_type: String,
+  pub size: i64,
+  pub hash: String,
+  /// Where the file is inside a Markdown export, `None` in other formats.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub path: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct ExportedNote {
+  pub id: i32,
+  pub title: String,
+  pub text: String,
+  pub format: NoteFormat,
+  pub tags: Vec<String>,
+  pub notebook: Option<String>,
+  pub created_at: DateTime<Utc>,
+  pub attachments: Vec<ExportedAttachment>
+}
+
+/// Columns expected by `ExportedNote::from(&Row)`, in order, with `notes` in scope.
+/// Attachments are read separately.
+pub const EXPORTED_NOTE_COLUMNS: &str = "notes.id, notes.title, notes.text, notes.format, \
+  ARRAY(SELECT t.name FROM note_tags nt JOIN tags t ON t.id = nt.tag_id WHERE nt.note_id = notes.id ORDER BY t.name), \
+  (SELECT nb.name FROM notebooks nb WHERE nb.id = notes.notebook_id), notes.created_at";
+
+impl From<&Row> for ExportedNote {
+  fn from(row: &Row) -> ExportedNote {
+    ExportedNote {
+      id: row.get(0),
+      title: row.get(1),
+      text: row.get(2),
+      format: row.get::<usize, i16>(3).into(),
+      tags: row.get(4),
+      notebook: row.get(5),
+      created_at: row.get(6),
+      attachments: vec![]
+    }
+  }
+}
+
+/// A note as written in an import, whatever its format. Unknown fields, like the `id` and
+/// `attachments` of an export, are ignored.
+#[derive(Deserialize)]
+pub struct ImportedNote {
+  #[serde(default)]
+  pub title: String,
+  pub text: String,
+  #[serde(default)]
+  pub format: NoteFormat,
+  #[serde(default)]
+  pub tags: Vec<String>,
+  pub notebook: Option<String>,
+  pub created_at: Option<DateTime<Utc>>
+}
+
+/// A CSV row. `tags` holds the tag names separated by commas, `attachments` the file names
+/// separated by semicolons; the latter is only informative and ignored on import.
+#[derive(Deserialize, Serialize)]
+pub struct CsvNote {
+  #[serde(default)]
+  pub id: Option<i32>,
+  #[serde(default)]
+  pub title: String,
+  pub text: String,
+  #[serde(default)]
+  pub format: NoteFormat,
+  #[serde(default)]
+  pub tags: String,
+  #[serde(default)]
+  pub notebook: Option<String>,
+  #[serde(default)]
+  pub created_at: Option<DateTime<Utc>>,
+  #[serde(default)]
+  pub attachments: String
+}
+
+/// Metadata heading each file of a Markdown export, between `---` lines.
+#[derive(Deserialize, Serialize, Default)]
+pub struct FrontMatter {
+  /// Files without one are titled after their name.
+  #[serde(default)]
+  pub title: Option<String>,
+  #[serde(default)]
+  pub format: NoteFormat,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub tags: Vec<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub notebook: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub created_at: Option<DateTime<Utc>>,
+  /// Paths of the attached files inside the zip.
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub attachments: Vec<String>
+}
+
+/// What an import does with a note matching one the user already has: same title ignoring
+/// case, or same text for untitled notes.
+#[derive(Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicateStrategy {
+  #[default]
+  Skip,
+  /// Replaces the text, format, tags and notebook of the existing note.
+  Overwrite,
+  /// Imports it as another note anyway.
+  KeepBoth
+}
+
+#[derive(Deserialize)]
+pub struct ImportQuery {
+  /// Defaults to what `Content-Type` says.
+  pub format: Option<ExchangeFormat>,
+  /// Validates everything and reports what would happen, without changing anything.
+  pub dry_run: Option<bool>,
+  pub on_duplicate: Option<DuplicateStrategy>
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStatus {
+  Created,
+  Updated,
+  Skipped,
+  Failed
+}
+
+#[derive(Serialize)]
+pub struct ImportItemReport {
+  /// Position of the note in the import, starting at 0.
+  pub index: usize,
+  /// File name inside a zip, or row number of a CSV file.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub source: Option<String>,
+  pub title: Option<String>,
+  pub status: ImportStatus,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub note_id: Option<i32>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct ImportReport {
+  pub dry_run: bool,
+  pub created: usize,
+  pub updated: usize,
+  pub skipped: usize,
+  pub failed: usize,
+  pub items: Vec<ImportItemReport>
+}
@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use tokio_postgres::GenericClient;
+
+use crate::{
+    types::{internal_error, AppState},
+    modules::jobs::types::*,
+    modules::notifications::api::deliver_notification,
+    modules::reminders::api::fire_reminder,
+    JOBS_TABLE_NAME
+};
+
+/// How long a claimed job stays hidden from other runners. A runner that dies mid-job
+/// leaves it to be retried once the lease runs out. Jobs are claimed one at a time, so the
+/// lease only has to outlast a single job, such as a webhook delivery and its timeout.
+const LEASE_SECS: f64 = 5.0 * 60.0;
+
+/// Failed jobs are retried with an exponential backoff, then kept aside as failed.
+const MAX_ATTEMPTS: i32 = 8;
+const BACKOFF_BASE_SECS: f64 = 30.0;
+const BACKOFF_MAX_SECS: f64 = 60.0 * 60.0;
+
+/// Schedules `job` to run at `run_at`, or right away when that is in the past. Meant to run
+/// inside the transaction making the change the job follows up on.
+pub async fn enqueue<C: GenericClient>(client: &C, job: &Job, run_at: DateTime<Utc>) -> Result<(), (StatusCode, String)> {
+    let payload = serde_json::to_value(job).map_err(internal_error)?;
+
+    client.execute(
+        &format!("INSERT INTO {JOBS_TABLE_NAME} (payload, run_at) VALUES ($1, $2)"),
+        &[&payload, &run_at]
+    ).await.map_err(internal_error)?;
+
+    Ok(())
+}
+
+/// Drops the pending jobs whose payload contains `matching`, e.g. the ones firing a reminder
+/// that was rescheduled.
+pub async fn cancel_jobs<C: GenericClient>(client: &C, matching: serde_json::Value) -> Result<(), (StatusCode, String)> {
+    client.execute(
+        &format!("DELETE FROM {JOBS_TABLE_NAME} WHERE payload @> $1 AND failed_at IS NULL"),
+        &[&matching]
+    ).await.map_err(internal_error)?;
+
+    Ok(())
+}
+
+/// Takes the next due job nobody else holds. `SKIP LOCKED` lets several server processes
+/// share the queue.
+async fn claim_job(state: &AppState) -> Result<Option<ClaimedJob>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let row = conn.query_opt(
+        &format!("UPDATE {JOBS_TABLE_NAME} SET locked_until = now() + make_interval(secs => $1), attempts = attempts + 1 \
+            WHERE id IN ( \
+                SELECT id FROM {JOBS_TABLE_NAME} \
+                WHERE failed_at IS NULL AND run_at <= now() AND (locked_until IS NULL OR locked_until < now()) \
+                ORDER BY run_at LIMIT 1 FOR UPDATE SKIP LOCKED \
+            ) RETURNING id, attempts, payload"),
+        &[&LEASE_SECS]
+    ).await.map_err(internal_error)?;
+
+    Ok(row.map(|row| ClaimedJob {
+        id: row.get(0),
+        attempts: row.get(1),
+        job: serde_json::from_value(row.get(2)).map_err(|e| format!("Unreadable job: {e}"))
+    }))
+}
+
+async fn run_job(state: &AppState, job: Job) -> Result<(), String> {
+    let result = match job {
+        Job::FireReminder { reminder_id, at } => fire_reminder(state, reminder_id, at).await,
+        Job::DeliverNotification { notification_id } => deliver_notification(state, notification_id).await,
+    };
+
+    result.map_err(|(_, message)| message)
+}
+
+/// Removes a job that ran, or schedules its retry.
+async fn finish_job(state: &AppState, job_id: i64, attempts: i32, outcome: Result<(), String>) -> Result<(), (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let error = match outcome {
+        Ok(()) => {
+            conn.execute(&format!("DELETE FROM {JOBS_TABLE_NAME} WHERE id=$1"), &[&job_id])
+                .await.map_err(internal_error)?;
+            return Ok(());
+        }
+        Err(error) => error,
+    };
+
+    if attempts >= MAX_ATTEMPTS {
+        tracing::error!("job {} failed for good after {} attempts: {}", job_id, attempts, error);
+        conn.execute(
+            &format!("UPDATE {JOBS_TABLE_NAME} SET failed_at=now(), locked_until=NULL, last_error=$2 WHERE id=$1"),
+            &[&job_id, &error]
+        ).await.map_err(internal_error)?;
+    } else {
+        let backoff = (BACKOFF_BASE_SECS * 2f64.powi(attempts - 1)).min(BACKOFF_MAX_SECS);
+        tracing::warn!("job {} failed, retrying in {}s: {}", job_id, backoff, error);
+        conn.execute(
+            &format!("UPDATE {JOBS_TABLE_NAME} SET run_at = now() + make_interval(secs => $2), locked_until=NULL, last_error=$3 \
+                WHERE id=$1"),
+            &[&job_id, &backoff, &error]
+        ).await.map_err(internal_error)?;
+    }
+
+    Ok(())
+}
+
+/// Runs jobs until none is due, returning how many ran.
+pub async fn run_due_jobs(state: &AppState) -> Result<usize, (StatusCode, String)> {
+    let mut ran = 0;
+
+    while let Some(claimed) = claim_job(state).await? {
+        let outcome = match claimed.job {
+            Ok(job) => run_job(state, job).await,
+            Err(error) => Err(error),
+        };
+
+        finish_job(state, claimed.id, claimed.attempts, outcome).await?;
+        ran += 1;
+    }
+
+    Ok(ran)
+}
+
+/// Runs `run_due_jobs` forever, once per `JOB_POLL_INTERVAL_SECS`. Meant to be spawned on
+/// startup; jobs left by a previous process are picked up on the first tick.
+pub async fn run_jobs_periodically(state: AppState) {
+    let mut interval = tokio::time::interval(Duration::from_secs(state.settings.job_poll_interval_secs));
+
+    loop {
+        interval.tick().await;
+
+        match run_due_jobs(&state).await {
+            Ok(0) => {}
+            Ok(ran) => tracing::debug!("ran {} jobs", ran),
+            Err((_, e)) => tracing::error!("running jobs failed: {}", e),
+        }
+    }
+}
@@ -0,0 +1,20 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Work for the job runner, stored as the `payload` of a job row.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Job {
+  /// Fires the occurrence of a reminder due `at`, then schedules the next one.
+  FireReminder { reminder_id: i32, at: DateTime<Utc> },
+  /// Sends a notification through the outbound channel.
+  DeliverNotification { notification_id: i32 }
+}
+
+/// A job taken by this process until its lease runs out. `job` is `Err` for a payload this
+/// version can't read.
+pub struct ClaimedJob {
+  pub id: i64,
+  pub attempts: i32,
+  pub job: Result<Job, String>
+}
@@ -0,0 +1,328 @@
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2
+};
+use axum::{
+    Extension,
+    extract::{State, Query, Path, Form},
+    http::{StatusCode, HeaderMap, Response, header},
+    Json, response::{Html, IntoResponse},
+    body::Body
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::Utc;
+use rand::{rngs::OsRng, RngCore};
+use sha256::digest;
+use subtle::ConstantTimeEq;
+
+use crate::{
+    types::{internal_error, AppState},
+    modules::links::types::*,
+    modules::common::markdown::{self, escape_html},
+    modules::notes::{api::ensure_note_access, types::{Access, Note, NoteFormat, NOTE_COLUMNS}},
+    modules::users::types::User,
+    NOTES_TABLE_NAME, NOTE_LINKS_TABLE_NAME
+};
+
+/// 24 random bytes, 32 characters once encoded.
+const TOKEN_BYTES: usize = 24;
+
+const PASSWORD_HEADER: &str = "x-link-password";
+
+/// Wrong passwords in a row a link takes before refusing any for `PASSWORD_LOCKOUT_MINUTES`.
+const MAX_PASSWORD_ATTEMPTS: i32 = 5;
+const PASSWORD_LOCKOUT_MINUTES: i32 = 15;
+
+pub async fn get_note_link(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+) -> Result<Json<NoteLink>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+    ensure_note_access(&*conn, note_id, &user, Access::Owner).await?;
+
+    let row = conn.query_opt(
+        &format!("SELECT {NOTE_LINK_COLUMNS} FROM {NOTE_LINKS_TABLE_NAME} WHERE note_id=$1"),
+        &[&note_id]
+    ).await.map_err(internal_error)?
+    .ok_or_else(link_not_found)?;
+
+    Ok(Json(NoteLink::from(&row)))
+}
+
+/// Publishes the note, or changes the expiry and password of its existing link keeping its token.
+pub async fn set_note_link(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    Json(body): Json<NoteLinkPayload>,
+) -> Result<Json<NoteLink>, (StatusCode, String)> {
+    if body.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "expires_at must be in the future".to_string()));
+    }
+
+    let password = match body.password {
+        Some(password) if password.is_empty() => return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "password can't be empty, send null to remove it".to_string()
+        )),
+        Some(password) => Some(
+            tokio::task::spawn_blocking(move || hash_password(&password)).await.map_err(internal_error)??
+        ),
+        None => None,
+    };
+
+    let conn = state.pool.get().await.map_err(internal_error)?;
+    ensure_note_access(&*conn, note_id, &user, Access::Owner).await?;
+
+    let row = conn.query_one(
+        &format!("INSERT INTO {NOTE_LINKS_TABLE_NAME} (note_id, token, password, expires_at) VALUES ($1, $2, $3, $4) \
+            ON CONFLICT (note_id) DO UPDATE SET password = EXCLUDED.password, expires_at = EXCLUDED.expires_at, \
+                failed_attempts = 0, locked_until = NULL \
+            RETURNING {NOTE_LINK_COLUMNS}"),
+        &[&note_id, &generate_token(), &password, &body.expires_at]
+    ).await.map_err(internal_error)?;
+
+    Ok(Json(NoteLink::from(&row)))
+}
+
+/// Replaces the token of the link, so the old URL stops working, and restarts its view count.
+pub async fn regenerate_note_link(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+) -> Result<Json<NoteLink>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+    ensure_note_access(&*conn, note_id, &user, Access::Owner).await?;
+
+    let row = conn.query_opt(
+        &format!("UPDATE {NOTE_LINKS_TABLE_NAME} SET token=$2, view_count=0, created_at=now(), \
+            failed_attempts=0, locked_until=NULL \
+            WHERE note_id=$1 RETURNING {NOTE_LINK_COLUMNS}"),
+        &[&note_id, &generate_token()]
+    ).await.map_err(internal_error)?
+    .ok_or_else(link_not_found)?;
+
+    Ok(Json(NoteLink::from(&row)))
+}
+
+pub async fn revoke_note_link(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+) -> Result<Json<NoteLink>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+    ensure_note_access(&*conn, note_id, &user, Access::Owner).await?;
+
+    let row = conn.query_opt(
+        &format!("DELETE FROM {NOTE_LINKS_TABLE_NAME} WHERE note_id=$1 RETURNING {NOTE_LINK_COLUMNS}"),
+        &[&note_id]
+    ).await.map_err(internal_error)?
+    .ok_or_else(link_not_found)?;
+
+    Ok(Json(NoteLink::from(&row)))
+}
+
+/// Read-only view of a published note for anyone holding its link, without signing in.
+/// Protected links take their password in `X-Link-Password`, see `open_public_note` for browsers.
+pub async fn get_public_note(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+    Query(query): Query<PublicNoteQuery>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let password = headers.get(PASSWORD_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    public_note(&state, &token, public_format(&headers, &query), password).await
+}
+
+/// The password form of `password_required`, posted back to the link so the password never
+/// ends up in a URL.
+pub async fn open_public_note(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+    Query(query): Query<PublicNoteQuery>,
+    Form(form): Form<LinkPasswordForm>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    public_note(&state, &token, public_format(&headers, &query), Some(form.password)).await
+}
+
+fn public_format(headers: &HeaderMap, query: &PublicNoteQuery) -> PublicFormat {
+    query.format.unwrap_or_else(|| {
+        let accept = headers.get(header::ACCEPT).and_then(|value| value.to_str().ok()).unwrap_or("");
+        if accept.contains("text/html") { PublicFormat::Html } else { PublicFormat::Json }
+    })
+}
+
+async fn public_note(
+    state: &AppState,
+    token: &str,
+    format: PublicFormat,
+    given: Option<String>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let link = conn.query_opt(
+        &format!("SELECT note_id, password, expires_at IS NOT NULL AND expires_at <= now(), \
+            CASE WHEN locked_until > now() THEN ceil(extract(epoch FROM locked_until - now()))::integer END \
+            FROM {NOTE_LINKS_TABLE_NAME} WHERE token=$1"),
+        &[&token]
+    ).await.map_err(internal_error)?
+    .ok_or_else(link_not_found)?;
+
+    let note_id: i32 = link.get(0);
+    let password: Option<String> = link.get(1);
+    let expired: bool = link.get(2);
+    let locked_for: Option<i32> = link.get(3);
+
+    if expired {
+        return Err((StatusCode::GONE, "This link has expired".to_string()));
+    }
+
+    // a legacy sha256 digest is replaced by an argon2 hash once the right password comes in
+    let mut rehashed: Option<String> = None;
+
+    if let Some(password) = password {
+        if let Some(seconds) = locked_for {
+            return Ok((
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, seconds.to_string())],
+                "Too many wrong passwords, try again later"
+            ).into_response());
+        }
+
+        let Some(given) = given else {
+            return Ok(password_required(format, false));
+        };
+
+        let salt = state.salt.clone();
+        let verified = tokio::task::spawn_blocking(move || verify_password(&given, &password, &salt))
+            .await.map_err(internal_error)??;
+
+        match verified {
+            Verified::Wrong => {
+                conn.execute(
+                    &format!("UPDATE {NOTE_LINKS_TABLE_NAME} SET \
+                        failed_attempts = CASE WHEN failed_attempts + 1 >= $2 THEN 0 ELSE failed_attempts + 1 END, \
+                        locked_until = CASE WHEN failed_attempts + 1 >= $2 THEN now() + make_interval(mins => $3) END \
+                        WHERE token=$1"),
+                    &[&token, &MAX_PASSWORD_ATTEMPTS, &PASSWORD_LOCKOUT_MINUTES]
+                ).await.map_err(internal_error)?;
+
+                return Ok(password_required(format, true));
+            }
+            Verified::Right { rehashed: hash } => rehashed = hash,
+        }
+    }
+
+    let note = conn.query_opt(
+        &format!("SELECT {NOTE_COLUMNS} FROM {NOTES_TABLE_NAME} WHERE id=$1 AND deleted_at IS NULL"),
+        &[&note_id]
+    ).await.map_err(internal_error)?
+    .map(|row| Note::from(&row))
+    .ok_or_else(link_not_found)?;
+
+    let view_count: i64 = conn.query_one(
+        &format!("UPDATE {NOTE_LINKS_TABLE_NAME} SET view_count = view_count + 1, failed_attempts = 0, \
+            password = coalesce($2, password) WHERE token=$1 RETURNING view_count"),
+        &[&token, &rehashed]
+    ).await.map_err(internal_error)?
+    .get(0);
+
+    let public_note = PublicNote { title: note.title, text: note.text, format: note.format, tags: note.tags, created_at: note.created_at, view_count };
+
+    let mut response = match format {
+        PublicFormat::Json => Json(public_note).into_response(),
+        PublicFormat::Html => Html(render_html(&public_note)).into_response(),
+    };
+    // every request counts as a view, and protected notes must not linger in shared caches
+    response.headers_mut().insert(header::CACHE_CONTROL, header::HeaderValue::from_static("no-store"));
+
+    Ok(response)
+}
+
+fn password_required(format: PublicFormat, attempted: bool) -> Response<Body> {
+    let message = if attempted { "Wrong password" } else { "This note is password protected" };
+
+    let body = match format {
+        PublicFormat::Json => message.to_string(),
+        PublicFormat::Html => format!("<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>Protected note</title></head>\
+            <body><p>{message}</p><form method=\"post\"><input type=\"password\" name=\"password\" autofocus> \
+            <button>Open</button></form></body></html>"),
+    };
+
+    let content_type = match format {
+        PublicFormat::Json => "text/plain; charset=utf-8",
+        PublicFormat::Html => "text/html; charset=utf-8",
+    };
+
+    (StatusCode::UNAUTHORIZED, [(header::CONTENT_TYPE, content_type)], body).into_response()
+}
+
+fn render_html(note: &PublicNote) -> String {
+    let heading = match note.title.is_empty() {
+        true => String::new(),
+        false => format!("<h1>{}</h1>", escape_html(&note.title)),
+    };
+    let title = Some(note.title.as_str())
+        .filter(|title| !title.is_empty())
+        .or_else(|| note.text.lines().next().map(str::trim).filter(|line| !line.is_empty()))
+        .unwrap_or("Shared note");
+    let body = match note.format {
+        NoteFormat::Markdown => markdown::render(&note.text),
+        NoteFormat::Plain => format!("<pre style=\"white-space: pre-wrap\">{}</pre>", escape_html(&note.text)),
+    };
+    let tags: String = note.tags.iter().map(|tag| format!("<li>{}</li>", escape_html(tag))).collect();
+
+    format!("<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta name=\"robots\" content=\"noindex\">\
+        <title>{}</title></head><body><article>{heading}{body}<ul>{tags}</ul></article></body></html>", escape_html(title))
+}
+
+/// Argon2 with a fresh salt per link, in PHC string format. Slow on purpose, keep it off the
+/// async runtime.
+fn hash_password(password: &str) -> Result<String, (StatusCode, String)> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(internal_error)
+}
+
+enum Verified {
+    Wrong,
+    /// With the argon2 hash to store when `hash` was a legacy digest.
+    Right { rehashed: Option<String> },
+}
+
+/// Checks `given` against a stored hash, comparing in constant time. Links protected before
+/// argon2 hold `sha256(password + salt)` with the server wide salt.
+fn verify_password(given: &str, hash: &str, salt: &str) -> Result<Verified, (StatusCode, String)> {
+    if hash.starts_with("$argon2") {
+        let hash = PasswordHash::new(hash).map_err(internal_error)?;
+
+        return Ok(match Argon2::default().verify_password(given.as_bytes(), &hash) {
+            Ok(()) => Verified::Right { rehashed: None },
+            Err(_) => Verified::Wrong,
+        });
+    }
+
+    match bool::from(digest(given.to_string() + salt).as_bytes().ct_eq(hash.as_bytes())) {
+        true => Ok(Verified::Right { rehashed: Some(hash_password(given)?) }),
+        false => Ok(Verified::Wrong),
+    }
+}
+
+fn generate_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn link_not_found() -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, "Link not found".to_string())
+}
@@ -0,0 +1,74 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use crate::modules::notes::types::NoteFormat;
+
+/// The public link of a note, as its owner sees it.
+#[derive(Serialize)]
+pub struct NoteLink {
+  pub note_id: i32,
+  pub token: String,
+  pub url: String,
+  pub expires_at: Option<DateTime<Utc>>,
+  pub has_password: bool,
+  pub view_count: i64,
+  pub created_at: DateTime<Utc>
+}
+
+/// Columns expected by `NoteLink::from(&Row)`, in order.
+pub const NOTE_LINK_COLUMNS: &str = "note_id, token, expires_at, password IS NOT NULL, view_count, created_at";
+
+impl From<&Row> for NoteLink {
+  fn from(row: &Row) -> NoteLink {
+    let token: String = row.get(1);
+
+    NoteLink {
+      note_id: row.get(0),
+      url: format!("/public/notes/{token}"),
+      token,
+      expires_at: row.get(2),
+      has_password: row.get(3),
+      view_count: row.get(4),
+      created_at: row.get(5)
+    }
+  }
+}
+
+/// Creates the link or changes its settings. `expires_at` and `password` left out or `null`
+/// mean the link never expires and is open to anyone holding it.
+#[derive(Deserialize)]
+pub struct NoteLinkPayload {
+  pub expires_at: Option<DateTime<Utc>>,
+  pub password: Option<String>
+}
+
+/// What anyone with the link gets to see.
+#[derive(Serialize)]
+pub struct PublicNote {
+  pub title: String,
+  pub text: String,
+  pub format: NoteFormat,
+  pub tags: Vec<String>,
+  pub created_at: DateTime<Utc>,
+  pub view_count: i64
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PublicFormat {
+  Json,
+  Html
+}
+
+#[derive(Deserialize)]
+pub struct PublicNoteQuery {
+  /// Defaults to what the `Accept` header asks for.
+  pub format: Option<PublicFormat>
+}
+
+/// The form of the password page, posted back to the link.
+#[derive(Deserialize)]
+pub struct LinkPasswordForm {
+  pub password: String
+}
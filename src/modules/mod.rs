@@ -2,4 +2,21 @@ pub mod users;
 pub mod testable;
 pub mod auth;
 pub mod notes;
-pub mod common;
\ No newline at end of file
+pub mod common;
+pub mod revisions;
+pub mod trash;
+pub mod tags;
+pub mod notebooks;
+pub mod shares;
+pub mod links;
+pub mod attachments;
+pub mod references;
+pub mod import_export;
+pub mod bulk;
+pub mod jobs;
+pub mod notifications;
+pub mod reminders;
+pub mod templates;
+pub mod comments;
+pub mod events;
+pub mod sync;
\ No newline at end of file
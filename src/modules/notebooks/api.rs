@@ -0,0 +1,229 @@
+use axum::{
+    Extension,
+    extract::{State, Query, Path},
+    http::{StatusCode, HeaderMap},
+    Json,
+};
+
+use tokio_postgres::GenericClient;
+
+use crate::{
+    types::{internal_error, AppState},
+    modules::notes::{api::apply_note_update, types::{Note, NoteChanges}},
+    modules::notebooks::types::*,
+    modules::users::types::User,
+    NOTES_TABLE_NAME, NOTEBOOKS_TABLE_NAME
+};
+
+const MAX_NOTEBOOK_NAME_LENGTH: usize = 100;
+
+pub async fn get_notebooks(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<Notebook>>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let rows = conn.query(
+        &format!("{} WHERE b.user_id=$1 GROUP BY b.id ORDER BY b.name, b.id", notebooks_with_counts()),
+        &[&user.id]
+    ).await.map_err(internal_error)?;
+
+    let notebooks = rows.iter().map(|row| Notebook {
+        id: row.get(0),
+        name: row.get(1),
+        parent_id: row.get(2),
+        note_count: row.get(3)
+    }).collect();
+
+    Ok(Json(notebooks))
+}
+
+pub async fn create_notebook(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(body): Json<CreateNotebookPayload>,
+) -> Result<Json<Notebook>, (StatusCode, String)> {
+    let name = validate_name(&body.name)?;
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    if let Some(parent_id) = body.parent_id {
+        ensure_notebook_owner(&*conn, parent_id, &user).await?;
+    }
+
+    let row = conn.query_one(
+        &format!("INSERT INTO {NOTEBOOKS_TABLE_NAME} (user_id, parent_id, name) VALUES ($1, $2, $3) RETURNING id"),
+        &[&user.id, &body.parent_id, &name]
+    ).await.map_err(internal_error)?;
+
+    find_notebook(&*conn, row.get(0), &user).await.map(Json)
+}
+
+pub async fn rename_notebook(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(notebook_id): Path<i32>,
+    Json(body): Json<RenameNotebookPayload>,
+) -> Result<Json<Notebook>, (StatusCode, String)> {
+    let name = validate_name(&body.name)?;
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let renamed = conn.execute(
+        &format!("UPDATE {NOTEBOOKS_TABLE_NAME} SET name=$1 WHERE id=$2 AND user_id=$3"),
+        &[&name, &notebook_id, &user.id]
+    ).await.map_err(internal_error)?;
+
+    if renamed == 0 {
+        return Err(notebook_not_found());
+    }
+
+    find_notebook(&*conn, notebook_id, &user).await.map(Json)
+}
+
+pub async fn move_notebook(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(notebook_id): Path<i32>,
+    Json(body): Json<MoveNotebookPayload>,
+) -> Result<Json<Notebook>, (StatusCode, String)> {
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    lock_notebooks(&tx, &user).await?;
+    ensure_notebook_owner(&tx, notebook_id, &user).await?;
+
+    if let Some(parent_id) = body.parent_id {
+        ensure_notebook_owner(&tx, parent_id, &user).await?;
+
+        if notebook_subtree(&tx, notebook_id).await?.contains(&parent_id) {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "Cannot move a notebook into itself or one of its descendants".to_string()
+            ));
+        }
+    }
+
+    tx.execute(
+        &format!("UPDATE {NOTEBOOKS_TABLE_NAME} SET parent_id=$1 WHERE id=$2"),
+        &[&body.parent_id, &notebook_id]
+    ).await.map_err(internal_error)?;
+
+    let notebook = find_notebook(&tx, notebook_id, &user).await?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(notebook))
+}
+
+/// Deletes a notebook together with its descendants, handling their notes as asked in `?notes=`.
+pub async fn delete_notebook(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(notebook_id): Path<i32>,
+    query: Query<DeleteNotebookQuery>,
+) -> Result<Json<Notebook>, (StatusCode, String)> {
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    lock_notebooks(&tx, &user).await?;
+    let notebook = find_notebook(&tx, notebook_id, &user).await?;
+    let subtree = notebook_subtree(&tx, notebook_id).await?;
+
+    let (destination, trash) = match query.notes {
+        ContainedNotes::MoveToParent => (notebook.parent_id, false),
+        ContainedNotes::Unfile => (None, false),
+        ContainedNotes::Trash => (None, true),
+    };
+
+    tx.execute(
+        &format!("UPDATE {NOTES_TABLE_NAME} SET notebook_id=$1, version=version+1, \
+            deleted_at = CASE WHEN $2 THEN COALESCE(deleted_at, now()) ELSE deleted_at END \
+            WHERE notebook_id = ANY($3)"),
+        &[&destination, &trash, &subtree]
+    ).await.map_err(internal_error)?;
+
+    tx.execute(&format!("DELETE FROM {NOTEBOOKS_TABLE_NAME} WHERE id=$1"), &[&notebook_id])
+        .await.map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(notebook))
+}
+
+pub async fn move_note(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    headers: HeaderMap,
+    Json(body): Json<MoveNotePayload>,
+) -> Result<Json<Note>, (StatusCode, String)> {
+    let changes = NoteChanges { notebook: Some(body.notebook_id), ..Default::default() };
+    apply_note_update(&state, &user, note_id, changes, &headers).await.map(Json)
+}
+
+/// Answers `404 Not Found` unless `notebook_id` belongs to `user`.
+pub async fn ensure_notebook_owner<C: GenericClient>(client: &C, notebook_id: i32, user: &User) -> Result<(), (StatusCode, String)> {
+    client.query_opt(
+        &format!("SELECT 1 FROM {NOTEBOOKS_TABLE_NAME} WHERE id=$1 AND user_id=$2"),
+        &[&notebook_id, &user.id]
+    ).await.map_err(internal_error)?
+    .map(|_| ())
+    .ok_or_else(notebook_not_found)
+}
+
+/// Ids of `notebook_id` and all of its descendants.
+pub async fn notebook_subtree<C: GenericClient>(client: &C, notebook_id: i32) -> Result<Vec<i32>, (StatusCode, String)> {
+    let rows = client.query(
+        &format!("WITH RECURSIVE subtree AS ( \
+                SELECT id FROM {NOTEBOOKS_TABLE_NAME} WHERE id=$1 \
+                UNION SELECT b.id FROM {NOTEBOOKS_TABLE_NAME} b JOIN subtree s ON b.parent_id = s.id \
+            ) SELECT id FROM subtree"),
+        &[&notebook_id]
+    ).await.map_err(internal_error)?;
+
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+/// Serializes changes to the notebook tree of `user`, so concurrent moves can't build a cycle.
+async fn lock_notebooks<C: GenericClient>(client: &C, user: &User) -> Result<(), (StatusCode, String)> {
+    client.execute(
+        &format!("SELECT id FROM {NOTEBOOKS_TABLE_NAME} WHERE user_id=$1 FOR UPDATE"),
+        &[&user.id]
+    ).await.map_err(internal_error)?;
+
+    Ok(())
+}
+
+fn notebooks_with_counts() -> String {
+    format!("SELECT b.id, b.name, b.parent_id, count(n.id) FROM {NOTEBOOKS_TABLE_NAME} b \
+        LEFT JOIN {NOTES_TABLE_NAME} n ON n.notebook_id = b.id AND n.deleted_at IS NULL")
+}
+
+async fn find_notebook<C: GenericClient>(client: &C, notebook_id: i32, user: &User) -> Result<Notebook, (StatusCode, String)> {
+    let row = client.query_opt(
+        &format!("{} WHERE b.id=$1 AND b.user_id=$2 GROUP BY b.id", notebooks_with_counts()),
+        &[&notebook_id, &user.id]
+    ).await.map_err(internal_error)?
+    .ok_or_else(notebook_not_found)?;
+
+    Ok(Notebook {
+        id: row.get(0),
+        name: row.get(1),
+        parent_id: row.get(2),
+        note_count: row.get(3)
+    })
+}
+
+pub fn validate_name(name: &str) -> Result<String, (StatusCode, String)> {
+    let name = name.trim();
+
+    if name.is_empty() || name.chars().count() > MAX_NOTEBOOK_NAME_LENGTH {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Notebook names must be between 1 and {MAX_NOTEBOOK_NAME_LENGTH} characters")
+        ));
+    }
+
+    Ok(name.to_string())
+}
+
+fn notebook_not_found() -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, "Notebook not found".to_string())
+}
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize)]
+pub struct Notebook {
+  pub id: i32,
+  pub name: String,
+  pub parent_id: Option<i32>,
+  pub note_count: i64
+}
+
+#[derive(Deserialize)]
+pub struct CreateNotebookPayload {
+  pub name: String,
+  pub parent_id: Option<i32>
+}
+
+#[derive(Deserialize)]
+pub struct RenameNotebookPayload {
+  pub name: String
+}
+
+/// `parent_id: null` moves the notebook to the top level.
+#[derive(Deserialize)]
+pub struct MoveNotebookPayload {
+  pub parent_id: Option<i32>
+}
+
+/// `notebook_id: null` takes the note out of any notebook.
+#[derive(Deserialize)]
+pub struct MoveNotePayload {
+  pub notebook_id: Option<i32>
+}
+
+/// What happens to the notes of a deleted notebook and of all its descendants.
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ContainedNotes {
+  /// Notes end up in the parent of the deleted notebook, or unfiled at the top level.
+  #[default]
+  MoveToParent,
+  /// Notes are kept outside of any notebook.
+  Unfile,
+  /// Notes are moved to the trash.
+  Trash
+}
+
+#[derive(Deserialize)]
+pub struct DeleteNotebookQuery {
+  #[serde(default)]
+  pub notes: ContainedNotes
+}
+
+/// `?notebook_id=3&recursive=true`
+#[derive(Deserialize)]
+pub struct NotebookFilter {
+  pub notebook_id: Option<i32>,
+  pub recursive: Option<bool>
+}
@@ -1,112 +1,373 @@
+use std::ops::Bound;
+
 use axum::{
     Extension,
-    extract::{State, Query},
-    http::{StatusCode, HeaderValue, Response},
+    extract::{State, Query, Path, OriginalUri},
+    http::{StatusCode, HeaderValue, HeaderMap, Response, header},
     Json, response::IntoResponse,
     body::Body
 };
 
-use tokio_postgres::types::ToSql;
+use chrono::{DateTime, Utc};
+use tokio_postgres::GenericClient;
 
 use crate::{
-    types::{internal_error, AppState, Pagination},
+    types::{internal_error, AppState, Pagination, Settings},
     modules::notes::types::*,
     modules::users::types::User,
-    modules::common::{Search, SqlParams},
-    NOTES_TABLE_NAME
+    modules::common::{Search, ListQuery, SortField, parse_sort, etag},
+    modules::common::markdown::{self, Outline},
+    modules::common::query::{Condition, QueryBuilder, Sql},
+    modules::common::fields::{FieldsQuery, SelectedRow},
+    modules::common::pagination::{link_header, KeyKind, SortKey},
+    modules::revisions::api::record_revision,
+    modules::references::api::{resolve_dangling_references, retarget_references, sync_references},
+    modules::tags::{api::{apply_tag_change, tag_condition}, types::TagFilter},
+    modules::notebooks::{api::{ensure_notebook_owner, notebook_subtree}, types::NotebookFilter},
+    modules::shares::types::Permission,
+    NOTES_TABLE_NAME, NOTE_SHARES_TABLE_NAME, NOTE_SETTINGS_TABLE_NAME
 };
 
+/// Titles are a single line, the body of a note goes in its text.
+const TITLE_MAX_CHARS: usize = 200;
+
+const NOTE_SORT_FIELDS: &[SortField] = &[
+    ("id", "id", KeyKind::Int),
+    ("title", "title", KeyKind::Text),
+    ("created_at", "created_at", KeyKind::Time),
+    ("text", "coalesce(text, '')", KeyKind::Text),
+    ("version", "version", KeyKind::Int),
+];
+
+/// Leads every note listing, see `get_notes`.
+const PINNED_SORT_KEY: &str = "pinned::integer";
+
+/// Only sortable while searching, it needs the `query` bound by the search.
+const RANK_SORT_FIELD: SortField = ("rank", "ts_rank(search_vector, query)", KeyKind::Float);
+
+#[allow(clippy::too_many_arguments)]
 pub async fn get_notes(
     State(state): State<AppState>,
     Extension(user): Extension<User>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
     pagination: Query<Pagination>,
-    mut search_option: Query<Search>,
+    search_option: Query<Search>,
+    trashed_option: Query<Trashed>,
+    flag_filter: Query<FlagFilter>,
+    due_filter: Query<DueFilter>,
+    tag_filter: axum_extra::extract::Query<TagFilter>,
+    notebook_filter: Query<NotebookFilter>,
+    list: Query<ListQuery>,
+    fields: Query<FieldsQuery>,
 ) -> Result<Response<Body>, (StatusCode, String)> {
-    let mut query_notes = format!("SELECT id, text FROM {NOTES_TABLE_NAME} WHERE ");
-    let mut query_count = format!("SELECT count(*) FROM {NOTES_TABLE_NAME} WHERE ");
-
-    let mut for_notes_query: SqlParams = vec![("user_id = ", Box::new(user.id))];
-    let mut for_count_query: SqlParams = vec![("user_id = ", Box::new(user.id))];
-
-    if let Some(search) = search_option.search.take() {
-        let search_ = format!("%{search}%");
-        for_notes_query.push(("text LIKE ", Box::new(search_.clone())));
-        for_count_query.push(("text LIKE ", Box::new(search_.clone())));
-    }
-
-    let mut parameters_count = 0;
-
-    let mut params_notes = for_notes_query
-        .iter()
-        .enumerate()
-        .map(|(i, (key, value))| {
-            if i > 0 { query_notes += " AND " }
-            let parameters_number = i + 1;
-            query_notes += &format!("{}${}", key, parameters_number);
-            parameters_count = parameters_number;
-            value.as_ref() as &(dyn ToSql + Sync)
-        })
-        .collect::<Vec<&(dyn ToSql + Sync)>>();
-
-    params_notes.push(&pagination.limit);
-    params_notes.push(&pagination.offset);
-
-    if parameters_count != 0 {
-        query_notes += &format!(" ORDER BY id LIMIT ${} OFFSET ${}", parameters_count + 1, parameters_count + 2);
-    } else {
-        query_notes += " ORDER BY id LIMIT $1 OFFSET $2";
-    }
-
-    let params_count =  for_count_query
-        .iter()
-        .enumerate()
-        .map(|(i, (key, value))| {
-            if i > 0 { query_count += " AND " }
-            query_count += &format!("{}${}", key, i+1);
-            value.as_ref() as &(dyn ToSql + Sync)
-        })
-        .collect::<Vec<&(dyn ToSql + Sync)>>();
-
     let conn = state.pool.get().await.map_err(internal_error)?;
 
-    let (rows, row_count) = tokio::try_join!(
-        conn.query(&query_notes, &params_notes),
-        conn.query_one(&query_count, &params_count)
+    let filter = NoteFilter {
+        search: search_option.0,
+        trashed: trashed_option.0,
+        tags: tag_filter.0,
+        notebook: notebook_filter.0,
+        flags: flag_filter.0,
+        due: due_filter.0,
+        list: list.0
+    };
+    let search = filter.tsquery();
+
+    let mut keys = match (&filter.list.sort, &search) {
+        (Some(sort), Some(_)) => parse_sort(sort, &[NOTE_SORT_FIELDS, &[RANK_SORT_FIELD]].concat())?,
+        (Some(sort), None) => parse_sort(sort, NOTE_SORT_FIELDS)?,
+        (None, Some(_)) => vec![SortKey::desc(RANK_SORT_FIELD.1, RANK_SORT_FIELD.2), SortKey::asc("id", KeyKind::Int)],
+        (None, None) => vec![SortKey::asc("id", KeyKind::Int)],
+    };
+    // pinned notes come first whatever the order asked for
+    keys.insert(0, SortKey::desc(PINNED_SORT_KEY, KeyKind::Int));
+    let page = pagination.page(&state.settings, keys)?;
+
+    let mut available = NOTE_FIELDS.to_vec();
+    if filter.trashed.include_trashed.unwrap_or(false) {
+        available.extend_from_slice(TRASHED_FIELDS);
+    }
+    if search.is_some() {
+        available.extend_from_slice(SEARCH_HIT_FIELDS);
+    }
+    let selection = fields.selection(&available, NOTE_INCLUDES)?;
+
+    let query = filter_notes(&*conn, &state.settings, &user, &filter).await?;
+
+    let query_notes = query.select(&format!("{}{}", selection.columns(), page.key_columns())).page(&page);
+    let query_count = query.count();
+
+    let (rows, count) = tokio::try_join!(
+        conn.query(query_notes.sql(), query_notes.params()),
+        async {
+            match pagination.with_count() {
+                true => conn.query_one(query_count.sql(), query_count.params()).await.map(|row| Some(row.get::<usize, i64>(0))),
+                false => Ok(None),
+            }
+        }
     ).map_err(internal_error)?;
 
-    let notes: Vec<Note> = rows.iter().map(|row| Note {
-        id: row.get(0),
-        text: row.get(1)
-    }).collect();
+    let (rows, links) = page.finish(rows)?;
+    let link_value = link_header(&uri, &links)?;
 
-    let count: i64 = row_count.get(0);
-    let header_count_value = HeaderValue::from_str(&count.to_string()).map_err(internal_error)?;
+    let notes: Vec<SelectedRow> = rows.iter().map(|row| selection.read(row)).collect();
+    let body = serde_json::to_string(&notes).map_err(internal_error)?;
 
-    let mut response = Json(notes).into_response();
-    response.headers_mut().insert("x-total-count", header_count_value);
+    let list_etag = etag::digest_etag(&format!("{count:?}:{link_value:?}:{body}"));
+
+    if etag::if_none_match(&headers, &list_etag) {
+        return etag::not_modified(&list_etag);
+    }
+
+    let mut response = ([(header::CONTENT_TYPE, "application/json")], body).into_response();
+    if let Some(count) = count {
+        let header_count_value = HeaderValue::from_str(&count.to_string()).map_err(internal_error)?;
+        response.headers_mut().insert("x-total-count", header_count_value);
+    }
+    if let Some(link_value) = link_value {
+        response.headers_mut().insert(header::LINK, link_value);
+    }
+    response.headers_mut().insert(header::ETAG, etag::etag_header_value(&list_etag)?);
 
     Ok(response)
 }
 
+/// The notes of `user` matching `filter`. A search puts its tsquery in scope as `query`, for
+/// the rank and snippet columns.
+pub async fn filter_notes<C: GenericClient>(
+    client: &C,
+    settings: &Settings,
+    user: &User,
+    filter: &NoteFilter,
+) -> Result<QueryBuilder, (StatusCode, String)> {
+    let mut query = match filter.tsquery() {
+        Some(search) => {
+            let mut query = QueryBuilder::new(Sql::new(&format!("{NOTES_TABLE_NAME}, to_tsquery("))
+                .bind(settings.search_language.clone())
+                .text("::text::regconfig, ")
+                .bind(search)
+                .text(") query"));
+            query.filter(Condition::raw("search_vector @@ query"));
+            query
+        }
+        None => QueryBuilder::new(Sql::new(NOTES_TABLE_NAME)),
+    };
+
+    query.filter(Condition::eq("user_id", user.id));
+
+    if !filter.trashed.include_trashed.unwrap_or(false) {
+        query.filter(Condition::raw("deleted_at IS NULL"));
+    }
+
+    match (filter.flags.archived, filter.flags.include_archived.unwrap_or(false)) {
+        (Some(archived), _) => { query.filter(Condition::eq("archived", archived)); }
+        (None, false) => { query.filter(Condition::raw("NOT archived")); }
+        (None, true) => {}
+    }
+
+    if let Some(pinned) = filter.flags.pinned {
+        query.filter(Condition::eq("pinned", pinned));
+    }
+
+    if let Some(favorite) = filter.flags.favorite {
+        query.filter(Condition::eq("favorite", favorite));
+    }
+
+    if filter.due.due_after.is_some() || filter.due.due_before.is_some() {
+        let lower = filter.due.due_after.map_or(Bound::Unbounded, Bound::Excluded);
+        let upper = filter.due.due_before.map_or(Bound::Unbounded, Bound::Excluded);
+        query.filter(Condition::range("due_at", lower, upper));
+    }
+
+    filter.list.push_filters(&mut query)?;
+
+    if !filter.tags.tag.is_empty() {
+        query.filter(tag_condition(filter.tags.tag_mode, filter.tags.tag.clone()));
+    }
+
+    if let Some(notebook_id) = filter.notebook.notebook_id {
+        ensure_notebook_owner(client, notebook_id, user).await?;
+
+        let notebook_ids = if filter.notebook.recursive.unwrap_or(false) {
+            notebook_subtree(client, notebook_id).await?
+        } else {
+            vec![notebook_id]
+        };
+
+        query.filter(Condition::is_in("notebook_id", notebook_ids));
+    }
+
+    Ok(query)
+}
+
+pub async fn get_note(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    ensure_note_access(&*conn, note_id, &user, Access::Viewer).await?;
+    let note = find_note(&*conn, note_id).await?;
+    let note_etag = etag::version_etag(note.version);
+
+    if etag::if_none_match(&headers, &note_etag) {
+        return etag::not_modified(&note_etag);
+    }
+
+    with_etag(note)
+}
+
+pub async fn update_note(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    headers: HeaderMap,
+    Json(body): Json<UpdateNotePayload>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let changes = NoteChanges {
+        title: body.title,
+        text: body.text,
+        format: body.format,
+        tags: body.tags.map_or(TagChange::Keep, TagChange::Replace),
+        ..Default::default()
+    };
+
+    let note = apply_note_update(&state, &user, note_id, changes, &headers).await?;
+    with_etag(note)
+}
+
+/// Applies `changes` to a note, honoring `If-Match`, and records a new revision when the title or text changes.
+pub async fn apply_note_update(
+    state: &AppState,
+    user: &User,
+    note_id: i32,
+    changes: NoteChanges,
+    headers: &HeaderMap,
+) -> Result<Note, (StatusCode, String)> {
+    let versions = etag::if_match_versions(headers, state.settings.require_if_match)?;
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    let note = write_note_changes(&tx, &state.settings, user, note_id, changes, versions).await?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(note)
+}
+
+/// Does the work of `apply_note_update` inside the caller's transaction, `versions` being the
+/// ones `If-Match` accepts.
+pub async fn write_note_changes<C: GenericClient>(
+    client: &C,
+    settings: &Settings,
+    user: &User,
+    note_id: i32,
+    changes: NoteChanges,
+    versions: Option<Vec<i32>>,
+) -> Result<Note, (StatusCode, String)> {
+    let title = changes.title.as_deref().map(str::trim);
+    validate_note(settings, title, changes.text.as_deref())?;
+
+    // editors may change text and tags, filing and flagging the note stays with its owner
+    let required = if changes.notebook.is_some() || !changes.flags.is_empty() { Access::Owner } else { Access::Editor };
+    let note_access = ensure_note_access(client, note_id, user, required).await?;
+
+    if let Some(Some(notebook_id)) = changes.notebook {
+        ensure_notebook_owner(client, notebook_id, user).await?;
+    }
+
+    // titles are unique among the owner's notes when they opted in
+    if let Some(title) = title {
+        ensure_unique_title(client, note_access.owner_id, Some(note_id), title).await?;
+    }
+
+    let row = client.query_opt(
+        &format!("UPDATE {NOTES_TABLE_NAME} SET text=COALESCE($1, text), title=COALESCE($6, title), \
+            format=COALESCE($7, format), version=version+1, \
+            notebook_id = CASE WHEN $4 THEN $5 ELSE notebook_id END, \
+            pinned=COALESCE($8, pinned), archived=COALESCE($9, archived), favorite=COALESCE($10, favorite), \
+            due_at = CASE WHEN $11 THEN $12 ELSE due_at END \
+            WHERE id=$2 AND deleted_at IS NULL AND ($3::integer[] IS NULL OR version = ANY($3)) \
+            RETURNING id"),
+        &[&changes.text, &note_id, &versions, &changes.notebook.is_some(), &changes.notebook.flatten(), &title,
+            &changes.format.map(|format| format as i16), &changes.flags.pinned, &changes.flags.archived, &changes.flags.favorite,
+            &changes.due.is_some(), &changes.due.flatten()]
+    ).await.map_err(internal_error)?;
+
+    if row.is_none() {
+        return Err(missing_or_modified(client, note_id, user, required).await);
+    }
+
+    // tags of shared notes live with the owner's tags
+    apply_tag_change(client, note_id, note_access.owner_id, &changes.tags).await?;
+
+    if let Some(text) = &changes.text {
+        sync_references(client, note_id, note_access.owner_id, text).await?;
+    }
+    if let Some(title) = title {
+        retarget_references(client, note_id, note_access.owner_id).await?;
+        resolve_dangling_references(client, note_id, note_access.owner_id, title).await?;
+    }
+
+    let note = find_note(client, note_id).await?;
+    if changes.text.is_some() || title.is_some() {
+        record_revision(client, &note, user.id).await?;
+    }
+
+    Ok(note)
+}
+
 pub async fn delete_note(
     State(state): State<AppState>,
     Extension(user): Extension<User>,
+    headers: HeaderMap,
     payload: Query<DeleteNotePayload>,
 ) -> Result<Json<Note>, (StatusCode, String)> {
+    remove_note(&state, &user, payload.0.id, &headers).await.map(Json)
+}
+
+pub async fn delete_note_by_id(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    headers: HeaderMap,
+) -> Result<Json<Note>, (StatusCode, String)> {
+    remove_note(&state, &user, note_id, &headers).await.map(Json)
+}
+
+/// Moves a note to the trash, honoring `If-Match`.
+async fn remove_note(
+    state: &AppState,
+    user: &User,
+    note_id: i32,
+    headers: &HeaderMap,
+) -> Result<Note, (StatusCode, String)> {
+    let versions = etag::if_match_versions(headers, state.settings.require_if_match)?;
     let conn = state.pool.get().await.map_err(internal_error)?;
-    let note_id = payload.0.id;
 
-    let row = conn.query_one(
-        &format!("DELETE FROM {NOTES_TABLE_NAME} WHERE id=$1 AND user_id=$2 RETURNING id, text"),
-        &[&note_id, &user.id]
-    ).await.map_err(internal_error)?;
+    trash_note(&*conn, user, note_id, versions).await
+}
 
-    let deleted_note = Note {
-        id: row.get(0),
-        text: row.get(1)
-    };
+/// Does the work of `remove_note` with `client`, `versions` being the ones `If-Match` accepts.
+pub async fn trash_note<C: GenericClient>(
+    client: &C,
+    user: &User,
+    note_id: i32,
+    versions: Option<Vec<i32>>,
+) -> Result<Note, (StatusCode, String)> {
+    let row = client.query_opt(
+        &format!("UPDATE {NOTES_TABLE_NAME} SET deleted_at=now() \
+            WHERE id=$1 AND user_id=$2 AND deleted_at IS NULL AND ($3::integer[] IS NULL OR version = ANY($3)) \
+            RETURNING {NOTE_COLUMNS}"),
+        &[&note_id, &user.id, &versions]
+    ).await.map_err(internal_error)?;
 
-    Ok(Json(deleted_note))
+    match row {
+        Some(row) => Ok(Note::from(&row)),
+        None => Err(missing_or_modified(client, note_id, user, Access::Owner).await),
+    }
 }
 
 pub async fn create_note(
@@ -114,17 +375,367 @@ pub async fn create_note(
     Extension(user): Extension<User>,
     Json(body): Json<CreateNotePayload>,
 ) -> Result<Json<Note>, (StatusCode, String)> {
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    let created_note = insert_note(&tx, &state.settings, &user, body, None).await?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(created_note))
+}
+
+/// Creates a note of `user` inside the caller's transaction, dated `created_at` or now.
+pub async fn insert_note<C: GenericClient>(
+    client: &C,
+    settings: &Settings,
+    user: &User,
+    body: CreateNotePayload,
+    created_at: Option<DateTime<Utc>>,
+) -> Result<Note, (StatusCode, String)> {
+    let title = body.title.trim();
+    validate_note(settings, Some(title), Some(&body.text))?;
+
+    if let Some(notebook_id) = body.notebook_id {
+        ensure_notebook_owner(client, notebook_id, user).await?;
+    }
+
+    ensure_unique_title(client, user.id, None, title).await?;
+
+    let row = client.query_one(
+        &format!("INSERT INTO {NOTES_TABLE_NAME} (title, text, format, user_id, notebook_id, search_language, created_at) \
+            VALUES ($1, $2, $3, $4, $5, $6::text::regconfig, COALESCE($7, now())) RETURNING id"),
+        &[&title, &body.text, &(body.format as i16), &user.id, &body.notebook_id, &settings.search_language, &created_at]
+    ).await.map_err(internal_error)?;
+
+    let note_id: i32 = row.get(0);
+    apply_tag_change(client, note_id, user.id, &TagChange::Replace(body.tags)).await?;
+    sync_references(client, note_id, user.id, &body.text).await?;
+    resolve_dangling_references(client, note_id, user.id, title).await?;
+
+    let created_note = find_note(client, note_id).await?;
+    record_revision(client, &created_note, user.id).await?;
+
+    Ok(created_note)
+}
+
+/// The note as sanitized HTML, along with the headings, links and tasks of Markdown notes.
+pub async fn get_rendered_note(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
     let conn = state.pool.get().await.map_err(internal_error)?;
 
-    let row = conn.query_one(
-        &format!("INSERT INTO {NOTES_TABLE_NAME} (text, user_id) VALUES ($1, $2) RETURNING id, text"),
-        &[&body.text, &user.id]
+    ensure_note_access(&*conn, note_id, &user, Access::Viewer).await?;
+    let note = find_note(&*conn, note_id).await?;
+    let note_etag = etag::version_etag(note.version);
+
+    if etag::if_none_match(&headers, &note_etag) {
+        return etag::not_modified(&note_etag);
+    }
+
+    let (html, outline) = match note.format {
+        NoteFormat::Markdown => (markdown::render(&note.text), markdown::outline(&note.text)),
+        NoteFormat::Plain => (markdown::render_plain(&note.text), Outline::default()),
+    };
+    let rendered = RenderedNote { id: note.id, version: note.version, format: note.format, html, outline };
+
+    let mut response = Json(rendered).into_response();
+    response.headers_mut().insert(header::ETAG, etag::etag_header_value(&note_etag)?);
+
+    Ok(response)
+}
+
+/// Checks or unchecks one task of a Markdown note, `index` counting its tasks from 0.
+/// Without `If-Match`, the write is conditional on the version the task was found in.
+pub async fn set_note_task(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path((note_id, index)): Path<(i32, usize)>,
+    headers: HeaderMap,
+    Json(body): Json<TaskPayload>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let note = {
+        let conn = state.pool.get().await.map_err(internal_error)?;
+        ensure_note_access(&*conn, note_id, &user, Access::Editor).await?;
+        find_note(&*conn, note_id).await?
+    };
+
+    if note.format != NoteFormat::Markdown {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "Only Markdown notes have tasks".to_string()));
+    }
+
+    let text = markdown::set_task(&note.text, index, body.checked)
+        .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Task {index} not found")))?;
+
+    let mut headers = headers;
+    if !headers.contains_key(header::IF_MATCH) {
+        headers.insert(header::IF_MATCH, etag::etag_header_value(&etag::version_etag(note.version))?);
+    }
+
+    let changes = NoteChanges { text: Some(text), ..Default::default() };
+    let note = apply_note_update(&state, &user, note_id, changes, &headers).await?;
+    with_etag(note)
+}
+
+pub async fn pin_note(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    set_note_flags(&state, &user, note_id, NoteFlags { pinned: Some(true), ..Default::default() }, &headers).await
+}
+
+pub async fn unpin_note(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    set_note_flags(&state, &user, note_id, NoteFlags { pinned: Some(false), ..Default::default() }, &headers).await
+}
+
+pub async fn archive_note(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    set_note_flags(&state, &user, note_id, NoteFlags { archived: Some(true), ..Default::default() }, &headers).await
+}
+
+pub async fn unarchive_note(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    set_note_flags(&state, &user, note_id, NoteFlags { archived: Some(false), ..Default::default() }, &headers).await
+}
+
+pub async fn favorite_note(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    set_note_flags(&state, &user, note_id, NoteFlags { favorite: Some(true), ..Default::default() }, &headers).await
+}
+
+pub async fn unfavorite_note(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    set_note_flags(&state, &user, note_id, NoteFlags { favorite: Some(false), ..Default::default() }, &headers).await
+}
+
+/// Due dates are part of the note, editors may set them like they set its text.
+pub async fn set_note_due(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    headers: HeaderMap,
+    Json(body): Json<DuePayload>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let changes = NoteChanges { due: Some(Some(body.due_at)), ..Default::default() };
+    let note = apply_note_update(&state, &user, note_id, changes, &headers).await?;
+    with_etag(note)
+}
+
+pub async fn clear_note_due(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    headers: HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let changes = NoteChanges { due: Some(None), ..Default::default() };
+    let note = apply_note_update(&state, &user, note_id, changes, &headers).await?;
+    with_etag(note)
+}
+
+async fn set_note_flags(
+    state: &AppState,
+    user: &User,
+    note_id: i32,
+    flags: NoteFlags,
+    headers: &HeaderMap,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let changes = NoteChanges { flags, ..Default::default() };
+    let note = apply_note_update(state, user, note_id, changes, headers).await?;
+    with_etag(note)
+}
+
+pub async fn get_note_settings(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+) -> Result<Json<NoteSettings>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let row = conn.query_opt(
+        &format!("SELECT unique_titles FROM {NOTE_SETTINGS_TABLE_NAME} WHERE user_id=$1"),
+        &[&user.id]
     ).await.map_err(internal_error)?;
 
-    let created_note = Note {
-        id: row.get(0),
-        text: row.get(1)
+    let settings = row
+        .map(|row| NoteSettings { unique_titles: row.get(0) })
+        .unwrap_or_default();
+
+    Ok(Json(settings))
+}
+
+/// Turning `unique_titles` on fails with `409 Conflict` while the user still has notes sharing a title.
+pub async fn set_note_settings(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(body): Json<NoteSettings>,
+) -> Result<Json<NoteSettings>, (StatusCode, String)> {
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    tx.execute(
+        &format!("INSERT INTO {NOTE_SETTINGS_TABLE_NAME} (user_id, unique_titles) VALUES ($1, $2) \
+            ON CONFLICT (user_id) DO UPDATE SET unique_titles = EXCLUDED.unique_titles"),
+        &[&user.id, &body.unique_titles]
+    ).await.map_err(internal_error)?;
+
+    if body.unique_titles {
+        let duplicate = tx.query_opt(
+            &format!("SELECT min(title) FROM {NOTES_TABLE_NAME} \
+                WHERE user_id=$1 AND deleted_at IS NULL AND title <> '' \
+                GROUP BY lower(title) HAVING count(*) > 1 LIMIT 1"),
+            &[&user.id]
+        ).await.map_err(internal_error)?;
+
+        if let Some(row) = duplicate {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("Several notes are titled \"{}\", rename them first", row.get::<usize, String>(0))
+            ));
+        }
+    }
+
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(body))
+}
+
+/// Answers `422 Unprocessable Entity` for a title that is too long or spans several lines, and
+/// `413 Payload Too Large` for a text over the configured size.
+pub fn validate_note(settings: &Settings, title: Option<&str>, text: Option<&str>) -> Result<(), (StatusCode, String)> {
+    if let Some(title) = title {
+        if title.chars().count() > TITLE_MAX_CHARS {
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, format!("Titles can't be longer than {TITLE_MAX_CHARS} characters")));
+        }
+        if title.contains(['\n', '\r']) {
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, "Titles can't contain line breaks".to_string()));
+        }
+    }
+
+    if text.is_some_and(|text| text.len() > settings.note_max_bytes) {
+        return Err((StatusCode::PAYLOAD_TOO_LARGE, format!("Note text can't be larger than {} bytes", settings.note_max_bytes)));
+    }
+
+    Ok(())
+}
+
+/// Answers `409 Conflict` when `owner_id` opted into unique titles and another of their notes,
+/// besides `note_id`, already has `title`. Meant to run inside the transaction writing the note:
+/// it locks the owner's settings so concurrent writes can't both take the same title.
+pub async fn ensure_unique_title<C: GenericClient>(
+    client: &C,
+    owner_id: i32,
+    note_id: Option<i32>,
+    title: &str,
+) -> Result<(), (StatusCode, String)> {
+    if title.is_empty() {
+        return Ok(());
+    }
+
+    let enabled = client.query_opt(
+        &format!("SELECT 1 FROM {NOTE_SETTINGS_TABLE_NAME} WHERE user_id=$1 AND unique_titles FOR UPDATE"),
+        &[&owner_id]
+    ).await.map_err(internal_error)?;
+
+    if enabled.is_none() {
+        return Ok(());
+    }
+
+    let taken = client.query_opt(
+        &format!("SELECT 1 FROM {NOTES_TABLE_NAME} \
+            WHERE user_id=$1 AND deleted_at IS NULL AND title <> '' AND lower(title) = lower($2) AND id IS DISTINCT FROM $3"),
+        &[&owner_id, &title, &note_id]
+    ).await.map_err(internal_error)?;
+
+    match taken {
+        Some(_) => Err((StatusCode::CONFLICT, format!("A note titled \"{title}\" already exists"))),
+        None => Ok(()),
+    }
+}
+
+fn with_etag(note: Note) -> Result<Response<Body>, (StatusCode, String)> {
+    let etag_value = etag::etag_header_value(&etag::version_etag(note.version))?;
+
+    let mut response = Json(note).into_response();
+    response.headers_mut().insert(header::ETAG, etag_value);
+
+    Ok(response)
+}
+
+async fn find_note<C: GenericClient>(client: &C, note_id: i32) -> Result<Note, (StatusCode, String)> {
+    let row = client.query_one(
+        &format!("SELECT {NOTE_COLUMNS} FROM {NOTES_TABLE_NAME} WHERE id=$1"),
+        &[&note_id]
+    ).await.map_err(internal_error)?;
+
+    Ok(Note::from(&row))
+}
+
+fn note_not_found() -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, "Note not found".to_string())
+}
+
+/// Answers `404 Not Found` unless `note_id` is not in the trash and either belongs to `user`
+/// or is shared with them, and `403 Forbidden` when their access is below `required`.
+pub async fn ensure_note_access<C: GenericClient>(
+    client: &C,
+    note_id: i32,
+    user: &User,
+    required: Access,
+) -> Result<NoteAccess, (StatusCode, String)> {
+    let row = client.query_opt(
+        &format!("SELECT n.user_id, s.permission FROM {NOTES_TABLE_NAME} n \
+            LEFT JOIN {NOTE_SHARES_TABLE_NAME} s ON s.note_id = n.id AND s.user_id = $2 \
+            WHERE n.id=$1 AND n.deleted_at IS NULL AND (n.user_id = $2 OR s.user_id IS NOT NULL)"),
+        &[&note_id, &user.id]
+    ).await.map_err(internal_error)?
+    .ok_or_else(note_not_found)?;
+
+    let owner_id: i32 = row.get(0);
+    let access = match owner_id == user.id {
+        true => Access::Owner,
+        false => Permission::from(row.get::<usize, i16>(1)).into(),
     };
 
-    Ok(Json(created_note))
-}
\ No newline at end of file
+    if access < required {
+        let message = match required {
+            Access::Owner => "Only the owner of this note can do that",
+            _ => "This note is shared with you as a viewer",
+        };
+        return Err((StatusCode::FORBIDDEN, message.to_string()));
+    }
+
+    Ok(NoteAccess { owner_id, access })
+}
+
+/// Tells apart a conditional write that matched no row because the note is gone
+/// from one that lost the race against a newer version.
+async fn missing_or_modified<C: GenericClient>(client: &C, note_id: i32, user: &User, required: Access) -> (StatusCode, String) {
+    match ensure_note_access(client, note_id, user, required).await {
+        Ok(_) => (StatusCode::PRECONDITION_FAILED, "Note was modified, refetch it and retry".to_string()),
+        Err(e) => e,
+    }
+}
@@ -1,9 +1,193 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use crate::modules::common::{fields::{Field, FieldKind}, fulltext::to_tsquery_syntax, markdown::Outline, ListQuery, Search};
+use crate::modules::notebooks::types::NotebookFilter;
+use crate::modules::tags::types::TagFilter;
 
 #[derive(Deserialize, Serialize)]
 pub struct Note {
   pub id: i32,
-  pub text: String
+  pub title: String,
+  pub text: String,
+  pub format: NoteFormat,
+  pub version: i32,
+  pub notebook_id: Option<i32>,
+  pub created_at: DateTime<Utc>,
+  pub tags: Vec<String>,
+  pub pinned: bool,
+  pub archived: bool,
+  pub favorite: bool,
+  pub due_at: Option<DateTime<Utc>>
+}
+
+/// Columns expected by `Note::from(&Row)`, in order. Only valid where `notes` is in scope
+/// under its own name.
+pub const NOTE_COLUMNS: &str = "id, title, text, format, version, notebook_id, created_at, \
+  ARRAY(SELECT t.name FROM note_tags nt JOIN tags t ON t.id = nt.tag_id WHERE nt.note_id = notes.id ORDER BY t.name) AS tags, \
+  pinned, archived, favorite, due_at";
+
+impl From<&Row> for Note {
+  fn from(row: &Row) -> Note {
+    Note {
+      id: row.get(0),
+      title: row.get(1),
+      text: row.get(2),
+      format: row.get::<usize, i16>(3).into(),
+      version: row.get(4),
+      notebook_id: row.get(5),
+      created_at: row.get(6),
+      tags: row.get(7),
+      pinned: row.get(8),
+      archived: row.get(9),
+      favorite: row.get(10),
+      due_at: row.get(11)
+    }
+  }
+}
+
+/// Fields of a note listing, see `FieldsQuery`. They select the same columns as `NOTE_COLUMNS`.
+pub const NOTE_FIELDS: &[Field] = &[
+  ("id", "id", FieldKind::Int),
+  ("title", "title", FieldKind::Text),
+  ("text", "text", FieldKind::Text),
+  ("format", "format", FieldKind::NoteFormat),
+  ("version", "version", FieldKind::Int),
+  ("notebook_id", "notebook_id", FieldKind::Int),
+  ("created_at", "created_at", FieldKind::Time),
+  ("tags", "ARRAY(SELECT t.name FROM note_tags nt JOIN tags t ON t.id = nt.tag_id WHERE nt.note_id = notes.id ORDER BY t.name)", FieldKind::TextArray),
+  ("pinned", "pinned", FieldKind::Bool),
+  ("archived", "archived", FieldKind::Bool),
+  ("favorite", "favorite", FieldKind::Bool),
+  ("due_at", "due_at", FieldKind::Time),
+];
+
+/// Extra field of a listing that includes trashed notes, unset for the others.
+pub const TRASHED_FIELDS: &[Field] = &[
+  ("deleted_at", "deleted_at", FieldKind::Time),
+];
+
+/// Extra fields of notes matching a full-text search: their relevance and a highlighted
+/// excerpt, given a `query` tsquery in scope. The excerpt is HTML: the text is escaped
+/// before the matches are wrapped in `<mark>`.
+pub const SEARCH_HIT_FIELDS: &[Field] = &[
+  ("rank", "ts_rank(search_vector, query)", FieldKind::Float),
+  ("snippet", "ts_headline(search_language, \
+    replace(replace(replace(replace(replace(coalesce(text, ''), '&', '&amp;'), '<', '&lt;'), '>', '&gt;'), '\"', '&quot;'), '''', '&#39;'), \
+    query, 'StartSel=<mark>, StopSel=</mark>, MaxFragments=2')", FieldKind::Text),
+];
+
+/// Related resources a note listing can embed with `include=`.
+pub const NOTE_INCLUDES: &[Field] = &[
+  ("owner", "(SELECT json_build_object('id', u.id, 'username', u.username) FROM users u WHERE u.id = notes.user_id)", FieldKind::Json),
+  ("notebook", "(SELECT json_build_object('id', nb.id, 'name', nb.name, 'parent_id', nb.parent_id) FROM notebooks nb WHERE nb.id = notes.notebook_id)", FieldKind::Json),
+  ("attachments", "(SELECT coalesce(json_agg(json_build_object('id', a.id, 'filename', a.filename, 'content_type', a.content_type, 'size', a.size) ORDER BY a.id), '[]') FROM attachments a WHERE a.note_id = notes.id)", FieldKind::Json),
+];
+
+/// How the text of a note is meant to be read.
+#[derive(Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteFormat {
+  #[default]
+  Plain = 0,
+  Markdown = 1
+}
+
+impl From<i16> for NoteFormat {
+  fn from(number: i16) -> NoteFormat {
+    match number {
+      1 => Self::Markdown,
+      _ => Self::Plain
+    }
+  }
+}
+
+/// A note rendered to sanitized HTML, with the outline of Markdown notes.
+#[derive(Serialize)]
+pub struct RenderedNote {
+  pub id: i32,
+  pub version: i32,
+  pub format: NoteFormat,
+  pub html: String,
+  #[serde(flatten)]
+  pub outline: Outline
+}
+
+#[derive(Deserialize)]
+pub struct TaskPayload {
+  pub checked: bool
+}
+
+#[derive(Deserialize)]
+pub struct Trashed {
+  pub include_trashed: Option<bool>
+}
+
+/// `?pinned=true&favorite=true`. Archived notes are left out unless `archived` or
+/// `include_archived` asks for them.
+#[derive(Deserialize)]
+pub struct FlagFilter {
+  pub pinned: Option<bool>,
+  pub favorite: Option<bool>,
+  pub archived: Option<bool>,
+  pub include_archived: Option<bool>
+}
+
+/// Notes due strictly before or after the given times; notes without a due date never match.
+#[derive(Deserialize)]
+pub struct DueFilter {
+  pub due_before: Option<DateTime<Utc>>,
+  pub due_after: Option<DateTime<Utc>>
+}
+
+#[derive(Deserialize)]
+pub struct DuePayload {
+  pub due_at: DateTime<Utc>
+}
+
+/// Flags to set on a note, the ones left out are kept as they are.
+#[derive(Deserialize, Default, Clone, Copy)]
+pub struct NoteFlags {
+  pub pinned: Option<bool>,
+  pub archived: Option<bool>,
+  pub favorite: Option<bool>
+}
+
+impl NoteFlags {
+  pub fn is_empty(&self) -> bool {
+    self.pinned.is_none() && self.archived.is_none() && self.favorite.is_none()
+  }
+}
+
+/// Every filter of the note listing, see `filter_notes`. Bulk operations take the same ones
+/// as a JSON object.
+#[derive(Deserialize)]
+pub struct NoteFilter {
+  #[serde(flatten)]
+  pub search: Search,
+  #[serde(flatten)]
+  pub trashed: Trashed,
+  #[serde(flatten)]
+  pub tags: TagFilter,
+  #[serde(flatten)]
+  pub notebook: NotebookFilter,
+  #[serde(flatten)]
+  pub flags: FlagFilter,
+  #[serde(flatten)]
+  pub due: DueFilter,
+  #[serde(flatten)]
+  pub list: ListQuery
+}
+
+impl NoteFilter {
+  /// The search in tsquery syntax, `None` when there's nothing to search for.
+  pub fn tsquery(&self) -> Option<String> {
+    self.search.search
+      .as_deref()
+      .map(to_tsquery_syntax)
+      .filter(|search| !search.is_empty())
+  }
 }
 
 #[derive(Deserialize, Serialize)]
@@ -13,5 +197,66 @@ pub struct DeleteNotePayload {
 
 #[derive(Deserialize, Serialize)]
 pub struct CreateNotePayload {
-  pub text: String
+  #[serde(default)]
+  pub title: String,
+  pub text: String,
+  #[serde(default)]
+  pub format: NoteFormat,
+  #[serde(default)]
+  pub tags: Vec<String>,
+  pub notebook_id: Option<i32>
+}
+
+/// Fields left out are kept as they are; `tags` replaces the whole set.
+#[derive(Deserialize, Serialize)]
+pub struct UpdateNotePayload {
+  pub title: Option<String>,
+  pub text: Option<String>,
+  pub format: Option<NoteFormat>,
+  pub tags: Option<Vec<String>>
+}
+
+/// A single write to a note, applied by `apply_note_update`.
+#[derive(Default)]
+pub struct NoteChanges {
+  pub title: Option<String>,
+  pub text: Option<String>,
+  pub format: Option<NoteFormat>,
+  pub tags: TagChange,
+  /// `Some(None)` takes the note out of its notebook.
+  pub notebook: Option<Option<i32>>,
+  pub flags: NoteFlags,
+  /// `Some(None)` clears the due date.
+  pub due: Option<Option<DateTime<Utc>>>
+}
+
+#[derive(Default)]
+pub enum TagChange {
+  #[default]
+  Keep,
+  Replace(Vec<String>),
+  Attach(Vec<String>),
+  Detach(Vec<String>)
+}
+
+/// What a user may do with a note, from least to most.
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum Access {
+  Viewer,
+  Editor,
+  Owner
+}
+
+/// The access `ensure_note_access` found, with the owner whose tags and notebooks the note uses.
+pub struct NoteAccess {
+  pub owner_id: i32,
+  pub access: Access
+}
+
+/// Per-user note preferences.
+#[derive(Deserialize, Serialize, Default)]
+pub struct NoteSettings {
+  /// Refuse a second note with the same title, ignoring case. Notes without a title and
+  /// notes in the trash don't count.
+  pub unique_titles: bool
 }
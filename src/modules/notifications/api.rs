@@ -0,0 +1,153 @@
+use axum::{
+    Extension,
+    extract::{State, Query, Path, OriginalUri},
+    http::{StatusCode, HeaderValue, Response, header},
+    Json, response::IntoResponse,
+    body::Body
+};
+
+use tokio_postgres::GenericClient;
+
+use crate::{
+    types::{internal_error, AppState, Pagination},
+    modules::common::pagination::{link_header, KeyKind, SortKey},
+    modules::common::query::{Condition, QueryBuilder, Sql},
+    modules::jobs::{queue::enqueue, types::Job},
+    modules::notifications::types::*,
+    modules::users::types::User,
+    NOTIFICATIONS_TABLE_NAME, USER_TABLE_NAME
+};
+
+/// Notifies `user_id` in the app, and through the outbound channel when one is configured.
+/// Meant to run inside the transaction making the change notified about: the outbound
+/// delivery is a job, so it only happens once that commits.
+pub async fn notify<C: GenericClient>(
+    client: &C,
+    state: &AppState,
+    user_id: i32,
+    kind: NotificationKind,
+    note_id: Option<i32>,
+    message: &str,
+) -> Result<i32, (StatusCode, String)> {
+    let row = client.query_one(
+        &format!("INSERT INTO {NOTIFICATIONS_TABLE_NAME} (user_id, kind, note_id, message) VALUES ($1, $2, $3, $4) RETURNING id"),
+        &[&user_id, &(kind as i16), &note_id, &message]
+    ).await.map_err(internal_error)?;
+
+    let notification_id: i32 = row.get(0);
+    if state.notifier.is_some() {
+        enqueue(client, &Job::DeliverNotification { notification_id }, chrono::Utc::now()).await?;
+    }
+
+    Ok(notification_id)
+}
+
+/// Sends a notification through the outbound channel. Notifications deleted since, along
+/// with their note or user, are dropped.
+pub async fn deliver_notification(state: &AppState, notification_id: i32) -> Result<(), (StatusCode, String)> {
+    let Some(notifier) = &state.notifier else {
+        return Ok(());
+    };
+
+    let row = {
+        let conn = state.pool.get().await.map_err(internal_error)?;
+        conn.query_opt(
+            &format!("SELECT n.id, n.kind, n.user_id, u.username, n.note_id, n.message, n.created_at \
+                FROM {NOTIFICATIONS_TABLE_NAME} n JOIN {USER_TABLE_NAME} u ON u.id = n.user_id WHERE n.id=$1"),
+            &[&notification_id]
+        ).await.map_err(internal_error)?
+    };
+
+    let Some(row) = row else {
+        return Ok(());
+    };
+
+    let notification = OutboundNotification {
+        id: row.get(0),
+        kind: row.get::<usize, i16>(1).into(),
+        user: Recipient { id: row.get(2), username: row.get(3) },
+        note_id: row.get(4),
+        message: row.get(5),
+        created_at: row.get(6)
+    };
+
+    notifier.send(&notification).await.map_err(|e| (StatusCode::BAD_GATEWAY, e))
+}
+
+/// The current user's notifications, newest first.
+pub async fn get_notifications(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    OriginalUri(uri): OriginalUri,
+    pagination: Query<Pagination>,
+    filter: Query<NotificationFilter>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let page = pagination.page(&state.settings, vec![SortKey::desc("id", KeyKind::Int)])?;
+
+    let mut query = QueryBuilder::new(Sql::new(NOTIFICATIONS_TABLE_NAME));
+    query.filter(Condition::eq("user_id", user.id));
+    if filter.unread.unwrap_or(false) {
+        query.filter(Condition::raw("read_at IS NULL"));
+    }
+
+    let query_notifications = query.select(&format!("{NOTIFICATION_COLUMNS}{}", page.key_columns())).page(&page);
+    let query_count = query.count();
+
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let (rows, count) = tokio::try_join!(
+        conn.query(query_notifications.sql(), query_notifications.params()),
+        async {
+            match pagination.with_count() {
+                true => conn.query_one(query_count.sql(), query_count.params()).await.map(|row| Some(row.get::<usize, i64>(0))),
+                false => Ok(None),
+            }
+        }
+    ).map_err(internal_error)?;
+
+    let (rows, links) = page.finish(rows)?;
+    let notifications: Vec<Notification> = rows.iter().map(Notification::from).collect();
+
+    let mut response = Json(notifications).into_response();
+    if let Some(count) = count {
+        let header_count_value = HeaderValue::from_str(&count.to_string()).map_err(internal_error)?;
+        response.headers_mut().insert("x-total-count", header_count_value);
+    }
+    if let Some(link_value) = link_header(&uri, &links)? {
+        response.headers_mut().insert(header::LINK, link_value);
+    }
+
+    Ok(response)
+}
+
+pub async fn read_notification(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(notification_id): Path<i32>,
+) -> Result<Json<Notification>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let row = conn.query_opt(
+        &format!("UPDATE {NOTIFICATIONS_TABLE_NAME} SET read_at=COALESCE(read_at, now()) \
+            WHERE id=$1 AND user_id=$2 RETURNING {NOTIFICATION_COLUMNS}"),
+        &[&notification_id, &user.id]
+    ).await.map_err(internal_error)?
+    .ok_or_else(|| (StatusCode::NOT_FOUND, "Notification not found".to_string()))?;
+
+    Ok(Json(Notification::from(&row)))
+}
+
+/// Marks every unread notification of the current user as read.
+pub async fn read_all_notifications(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+) -> Result<Json<ReadCount>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let read = conn.execute(
+        &format!("UPDATE {NOTIFICATIONS_TABLE_NAME} SET read_at=now() WHERE user_id=$1 AND read_at IS NULL"),
+        &[&user.id]
+    ).await.map_err(internal_error)?;
+
+    Ok(Json(ReadCount { read }))
+}
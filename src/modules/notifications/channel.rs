@@ -0,0 +1,82 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::async_trait;
+use hmac::{Hmac, Mac};
+use reqwest::Client;
+use sha2::Sha256;
+
+use crate::{modules::notifications::types::OutboundNotification, types::env_or};
+
+/// Gives up on a webhook well before the lease of the job sending it runs out, so a hanging
+/// receiver fails the attempt instead of having the job run twice.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Where notifications go besides the app itself, e.g. a webhook relaying them to email or
+/// chat. Sending may be retried, so receivers should deduplicate on the notification `id`.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    async fn send(&self, notification: &OutboundNotification) -> Result<(), String>;
+}
+
+/// Picks the channel from `NOTIFY_CHANNEL` (`none`, `log` or `webhook`), see `.env.example`
+/// for their settings. `None` keeps notifications in the app.
+pub fn notifier_from_env() -> Option<Arc<dyn Notifier>> {
+    match env_or("NOTIFY_CHANNEL", "none".to_string()).as_str() {
+        "log" => Some(Arc::new(LogNotifier)),
+        "webhook" => Some(Arc::new(WebhookNotifier::from_env())),
+        _ => None,
+    }
+}
+
+/// Writes notifications to the server log, for development.
+pub struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+    async fn send(&self, notification: &OutboundNotification) -> Result<(), String> {
+        tracing::info!("notification {} for {}: {}", notification.id, notification.user.username, notification.message);
+        Ok(())
+    }
+}
+
+/// Posts notifications as JSON to `url`. With a secret, the body is signed in an
+/// `x-signature: sha256=<hex HMAC-SHA256>` header so receivers can check where it came from.
+pub struct WebhookNotifier {
+    client: Client,
+    url: String,
+    secret: Option<String>,
+}
+
+impl WebhookNotifier {
+    pub fn from_env() -> Self {
+        WebhookNotifier {
+            client: Client::builder().timeout(WEBHOOK_TIMEOUT).build().expect("the webhook client must build"),
+            url: std::env::var("NOTIFY_WEBHOOK_URL").expect("NOTIFY_WEBHOOK_URL must be set."),
+            secret: std::env::var("NOTIFY_WEBHOOK_SECRET").ok().filter(|secret| !secret.is_empty()),
+        }
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    async fn send(&self, notification: &OutboundNotification) -> Result<(), String> {
+        let body = serde_json::to_vec(notification).map_err(|e| e.to_string())?;
+
+        let mut request = self.client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+
+        if let Some(secret) = &self.secret {
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+            mac.update(&body);
+            request = request.header("x-signature", format!("sha256={}", hex::encode(mac.finalize().into_bytes())));
+        }
+
+        let response = request.body(body).send().await.map_err(|e| e.to_string())?;
+
+        match response.status().is_success() {
+            true => Ok(()),
+            false => Err(format!("Webhook answered {}", response.status())),
+        }
+    }
+}
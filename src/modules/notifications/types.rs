@@ -0,0 +1,75 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+/// What a notification is about.
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationKind {
+  Reminder = 0,
+  /// Someone mentioned the user in a comment.
+  Mention = 1
+}
+
+impl From<i16> for NotificationKind {
+  fn from(number: i16) -> NotificationKind {
+    match number {
+      1 => Self::Mention,
+      _ => Self::Reminder
+    }
+  }
+}
+
+#[derive(Serialize)]
+pub struct Notification {
+  pub id: i32,
+  pub kind: NotificationKind,
+  pub note_id: Option<i32>,
+  pub message: String,
+  pub created_at: DateTime<Utc>,
+  pub read_at: Option<DateTime<Utc>>
+}
+
+/// Columns expected by `Notification::from(&Row)`, in order.
+pub const NOTIFICATION_COLUMNS: &str = "id, kind, note_id, message, created_at, read_at";
+
+impl From<&Row> for Notification {
+  fn from(row: &Row) -> Notification {
+    Notification {
+      id: row.get(0),
+      kind: row.get::<usize, i16>(1).into(),
+      note_id: row.get(2),
+      message: row.get(3),
+      created_at: row.get(4),
+      read_at: row.get(5)
+    }
+  }
+}
+
+#[derive(Deserialize)]
+pub struct NotificationFilter {
+  /// Only the notifications not read yet.
+  pub unread: Option<bool>
+}
+
+#[derive(Serialize)]
+pub struct Recipient {
+  pub id: i32,
+  pub username: String
+}
+
+/// A notification as sent through the outbound channel.
+#[derive(Serialize)]
+pub struct OutboundNotification {
+  pub id: i32,
+  pub kind: NotificationKind,
+  pub user: Recipient,
+  pub note_id: Option<i32>,
+  pub message: String,
+  pub created_at: DateTime<Utc>
+}
+
+#[derive(Serialize)]
+pub struct ReadCount {
+  pub read: u64
+}
@@ -0,0 +1,214 @@
+use axum::{
+    Extension,
+    extract::{State, Path},
+    http::StatusCode,
+    Json
+};
+
+use tokio_postgres::{types::ToSql, GenericClient};
+
+use crate::{
+    types::{internal_error, AppState},
+    modules::notes::{api::ensure_note_access, types::Access},
+    modules::references::types::*,
+    modules::users::types::User,
+    NOTES_TABLE_NAME, NOTE_REFERENCES_TABLE_NAME, NOTE_SHARES_TABLE_NAME
+};
+
+/// Targets of the `[[target]]` and `[[target|label]]` links in `text`, deduplicated ignoring case.
+/// Kept in step with the backfill of the `V23` migration.
+pub fn parse_references(text: &str) -> Vec<String> {
+    let mut targets: Vec<String> = vec![];
+    let mut rest = text;
+
+    while let Some(start) = rest.find("[[") {
+        let after = &rest[start + 2..];
+
+        match after.find("]]") {
+            Some(end) if !after[..end].contains(['[', ']', '\n']) => {
+                let target = after[..end].split('|').next().unwrap_or_default().trim();
+                if !target.is_empty() && !targets.iter().any(|known| known.to_lowercase() == target.to_lowercase()) {
+                    targets.push(target.to_string());
+                }
+                rest = &after[end + 2..];
+            }
+            _ => rest = &rest[start + 1..],
+        }
+    }
+
+    targets
+}
+
+/// The note the link target `target` resolves to among the notes of `owner` not in the trash:
+/// `#42` names a note by id, anything else by title ignoring case, the oldest note winning.
+fn target_note(target: &str, owner: &str) -> String {
+    format!("(SELECT n.id FROM {NOTES_TABLE_NAME} n \
+        WHERE n.user_id={owner} AND n.deleted_at IS NULL AND CASE \
+            WHEN {target} ~ '^#[0-9]{{1,9}}$' THEN n.id = substr({target}, 2)::integer \
+            ELSE lower(n.title) = lower({target}) \
+        END \
+        ORDER BY n.id LIMIT 1)")
+}
+
+/// Replaces the links of `note_id` with the ones written in `text`, resolving each target among
+/// the notes of `owner_id`, see `target_note`. Meant to run inside the transaction that wrote the note.
+pub async fn sync_references<C: GenericClient>(
+    client: &C,
+    note_id: i32,
+    owner_id: i32,
+    text: &str,
+) -> Result<(), (StatusCode, String)> {
+    client.execute(
+        &format!("DELETE FROM {NOTE_REFERENCES_TABLE_NAME} WHERE source_id=$1"),
+        &[&note_id]
+    ).await.map_err(internal_error)?;
+
+    let targets = parse_references(text);
+    if targets.is_empty() {
+        return Ok(());
+    }
+
+    client.execute(
+        &format!("INSERT INTO {NOTE_REFERENCES_TABLE_NAME} (source_id, target, target_id) \
+            SELECT $1, r.target, {} FROM unnest($3::text[]) AS r(target)", target_note("r.target", "$2")),
+        &[&note_id, &owner_id, &targets]
+    ).await.map_err(internal_error)?;
+
+    Ok(())
+}
+
+/// Resolves again the links of `owner_id`'s notes naming `note_id` by title, once its title
+/// changed: they move to another note with the old title, or dangle.
+pub async fn retarget_references<C: GenericClient>(
+    client: &C,
+    note_id: i32,
+    owner_id: i32,
+) -> Result<(), (StatusCode, String)> {
+    client.execute(
+        &format!("UPDATE {NOTE_REFERENCES_TABLE_NAME} r SET target_id = {} FROM {NOTES_TABLE_NAME} s \
+            WHERE s.id = r.source_id AND s.user_id=$2 AND r.target_id=$1 AND r.target !~ '^#[0-9]{{1,9}}$'",
+            target_note("r.target", "$2")),
+        &[&note_id, &owner_id]
+    ).await.map_err(internal_error)?;
+
+    Ok(())
+}
+
+/// Points the dangling links of `owner_id`'s notes that name `title` at `note_id`, once a note
+/// gets that title. Links to a note in the trash count as dangling.
+pub async fn resolve_dangling_references<C: GenericClient>(
+    client: &C,
+    note_id: i32,
+    owner_id: i32,
+    title: &str,
+) -> Result<(), (StatusCode, String)> {
+    if title.is_empty() {
+        return Ok(());
+    }
+
+    client.execute(
+        &format!("UPDATE {NOTE_REFERENCES_TABLE_NAME} r SET target_id=$1 FROM {NOTES_TABLE_NAME} s \
+            WHERE s.id = r.source_id AND s.user_id=$2 AND lower(r.target) = lower($3) AND (r.target_id IS NULL \
+                OR EXISTS (SELECT 1 FROM {NOTES_TABLE_NAME} t WHERE t.id = r.target_id AND t.deleted_at IS NOT NULL))"),
+        &[&note_id, &owner_id, &title]
+    ).await.map_err(internal_error)?;
+
+    Ok(())
+}
+
+/// Links written in `note_id` and the notes linking to it. Notes the current user can't see,
+/// including trashed ones, are left out of backlinks and count as dangling targets.
+pub async fn get_note_references(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+) -> Result<Json<NoteReferences>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+    ensure_note_access(&*conn, note_id, &user, Access::Viewer).await?;
+
+    let visible = format!("n.deleted_at IS NULL AND (n.user_id = $2 OR EXISTS ( \
+        SELECT 1 FROM {NOTE_SHARES_TABLE_NAME} s WHERE s.note_id = n.id AND s.user_id = $2))");
+
+    let query_outgoing = format!("SELECT r.target, n.id, n.title FROM {NOTE_REFERENCES_TABLE_NAME} r \
+        LEFT JOIN {NOTES_TABLE_NAME} n ON n.id = r.target_id AND {visible} \
+        WHERE r.source_id=$1 ORDER BY lower(r.target)");
+    let query_backlinks = format!("SELECT n.id, n.title FROM {NOTE_REFERENCES_TABLE_NAME} r \
+        JOIN {NOTES_TABLE_NAME} n ON n.id = r.source_id \
+        WHERE r.target_id=$1 AND {visible} ORDER BY n.id");
+    let params: Vec<&(dyn ToSql + Sync)> = vec![&note_id, &user.id];
+
+    let (outgoing, backlinks) = tokio::try_join!(
+        conn.query(&query_outgoing, &params),
+        conn.query(&query_backlinks, &params)
+    ).map_err(internal_error)?;
+
+    let outgoing = outgoing
+        .iter()
+        .map(|row| OutgoingLink {
+            target: row.get(0),
+            note: row.get::<usize, Option<i32>>(1).map(|id| LinkedNote { id, title: row.get(2) })
+        })
+        .collect();
+
+    Ok(Json(NoteReferences { outgoing, backlinks: backlinks.iter().map(LinkedNote::from).collect() }))
+}
+
+/// The link graph of the current user's notes, not counting trashed ones.
+pub async fn get_note_graph(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+) -> Result<Json<LinkGraph>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let query_nodes = format!("SELECT id, title FROM {NOTES_TABLE_NAME} WHERE user_id=$1 AND deleted_at IS NULL ORDER BY id");
+    let query_edges = format!("SELECT r.source_id, t.id, r.target FROM {NOTE_REFERENCES_TABLE_NAME} r \
+        JOIN {NOTES_TABLE_NAME} s ON s.id = r.source_id \
+        LEFT JOIN {NOTES_TABLE_NAME} t ON t.id = r.target_id AND t.deleted_at IS NULL \
+        WHERE s.user_id=$1 AND s.deleted_at IS NULL ORDER BY r.source_id, lower(r.target)");
+    let params: Vec<&(dyn ToSql + Sync)> = vec![&user.id];
+
+    let (nodes, edges) = tokio::try_join!(
+        conn.query(&query_nodes, &params),
+        conn.query(&query_edges, &params)
+    ).map_err(internal_error)?;
+
+    let mut graph = LinkGraph { nodes: nodes.iter().map(LinkedNote::from).collect(), edges: vec![], dangling: vec![] };
+
+    for row in &edges {
+        match row.get::<usize, Option<i32>>(1) {
+            Some(target) => graph.edges.push(GraphEdge { source: row.get(0), target }),
+            None => graph.dangling.push(DanglingLink { source: row.get(0), target: row.get(2) }),
+        }
+    }
+
+    Ok(Json(graph))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_targets_with_or_without_label() {
+        assert_eq!(parse_references("see [[Groceries]] and [[ #12 | the plan ]]"), vec!["Groceries", "#12"]);
+        assert_eq!(parse_references("[[a|b|c]]"), vec!["a"]);
+    }
+
+    #[test]
+    fn keeps_the_first_spelling_of_each_target() {
+        assert_eq!(parse_references("[[Todo]] [[todo|again]] [[TODO]] [[done]]"), vec!["Todo", "done"]);
+    }
+
+    #[test]
+    fn skips_what_is_not_a_link() {
+        for text in ["", "[[]]", "[[ | label]]", "[[open", "[[half]", "[single]", "[[two\nlines]]", "[[a[b]]", "[[a]b]]"] {
+            assert!(parse_references(text).is_empty(), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn finds_links_inside_extra_brackets() {
+        assert_eq!(parse_references("[[[inner]]]"), vec!["inner"]);
+        assert_eq!(parse_references("[[open [[closed]]"), vec!["closed"]);
+    }
+}
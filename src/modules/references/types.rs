@@ -0,0 +1,51 @@
+use serde::Serialize;
+use tokio_postgres::Row;
+
+#[derive(Serialize)]
+pub struct LinkedNote {
+  pub id: i32,
+  pub title: String
+}
+
+impl From<&Row> for LinkedNote {
+  fn from(row: &Row) -> LinkedNote {
+    LinkedNote {
+      id: row.get(0),
+      title: row.get(1)
+    }
+  }
+}
+
+/// A `[[target]]` written in a note. `note` is `None` while the link is dangling: no note
+/// the reader can see matches `target`.
+#[derive(Serialize)]
+pub struct OutgoingLink {
+  pub target: String,
+  pub note: Option<LinkedNote>
+}
+
+#[derive(Serialize)]
+pub struct NoteReferences {
+  pub outgoing: Vec<OutgoingLink>,
+  pub backlinks: Vec<LinkedNote>
+}
+
+#[derive(Serialize)]
+pub struct GraphEdge {
+  pub source: i32,
+  pub target: i32
+}
+
+#[derive(Serialize)]
+pub struct DanglingLink {
+  pub source: i32,
+  pub target: String
+}
+
+/// Every note of a user as a node, and their links between each other as edges.
+#[derive(Serialize)]
+pub struct LinkGraph {
+  pub nodes: Vec<LinkedNote>,
+  pub edges: Vec<GraphEdge>,
+  pub dangling: Vec<DanglingLink>
+}
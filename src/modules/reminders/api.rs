@@ -0,0 +1,282 @@
+use axum::{
+    Extension,
+    extract::{State, Query, Path, OriginalUri},
+    http::{StatusCode, HeaderValue, Response, header},
+    Json, response::IntoResponse,
+    body::Body
+};
+
+use chrono::{DateTime, Months, SubsecRound, Utc};
+use serde_json::json;
+
+use crate::{
+    types::{internal_error, AppState, Pagination},
+    modules::common::pagination::{link_header, KeyKind, SortKey},
+    modules::common::query::{Condition, QueryBuilder, Sql},
+    modules::jobs::{queue::{cancel_jobs, enqueue}, types::Job},
+    modules::notes::{api::ensure_note_access, types::Access},
+    modules::notifications::{api::notify, types::NotificationKind},
+    modules::reminders::{recurrence::Recurrence, types::*},
+    modules::users::types::User,
+    NOTES_TABLE_NAME, NOTE_SHARES_TABLE_NAME, REMINDERS_TABLE_NAME
+};
+
+/// How far back a recurring reminder may start, well within what `timestamptz` holds.
+const MAX_PAST_YEARS: u32 = 100;
+
+/// The first occurrence from number `occurrence` on that is still to come, with its number.
+/// Numbers past what the `occurrence` column holds count as past the end of the series.
+fn upcoming(
+    remind_at: DateTime<Utc>,
+    recurrence: Option<&Recurrence>,
+    occurrence: u32,
+    now: DateTime<Utc>,
+) -> Option<(i32, DateTime<Utc>)> {
+    // jump over the occurrences long gone, however far back `remind_at` lies
+    let mut occurrence = match recurrence {
+        Some(recurrence) => occurrence.max(recurrence.skip_past(remind_at, now)),
+        None => occurrence,
+    };
+
+    loop {
+        let at = match recurrence {
+            Some(recurrence) => recurrence.nth(remind_at, occurrence)?,
+            None if occurrence == 0 => remind_at,
+            None => return None,
+        };
+
+        if at > now {
+            return Some((i32::try_from(occurrence).ok()?, at));
+        }
+        occurrence = occurrence.checked_add(1)?;
+    }
+}
+
+/// When a new or changed reminder fires first, with its recurrence in canonical form.
+struct Schedule {
+    remind_at: DateTime<Utc>,
+    recurrence: Option<String>,
+    occurrence: i32,
+    next_at: DateTime<Utc>,
+}
+
+/// Checks the payload, answering `422 Unprocessable Entity` for an invalid recurrence, a start
+/// more than `MAX_PAST_YEARS` ago or a reminder that would never fire.
+fn schedule(body: &ReminderPayload) -> Result<Schedule, (StatusCode, String)> {
+    // timestamptz keeps microseconds, and `fire_reminder` compares `next_at` with the job's time
+    let remind_at = body.remind_at.trunc_subsecs(6);
+
+    let now = Utc::now();
+    if now.checked_sub_months(Months::new(MAX_PAST_YEARS * 12)).is_some_and(|earliest| remind_at < earliest) {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, format!("remind_at can't be more than {MAX_PAST_YEARS} years ago")));
+    }
+
+    let recurrence = body.recurrence
+        .as_deref()
+        .filter(|rule| !rule.trim().is_empty())
+        .map(str::parse::<Recurrence>)
+        .transpose()
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    let (occurrence, next_at) = upcoming(remind_at, recurrence.as_ref(), 0, now)
+        .ok_or_else(|| (StatusCode::UNPROCESSABLE_ENTITY, "Reminders must fire at least once in the future".to_string()))?;
+
+    Ok(Schedule { remind_at, recurrence: recurrence.map(|recurrence| recurrence.to_string()), occurrence, next_at })
+}
+
+fn reminder_job_matching(reminder_id: i32) -> serde_json::Value {
+    json!({ "kind": "fire_reminder", "reminder_id": reminder_id })
+}
+
+fn reminder_not_found() -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, "Reminder not found".to_string())
+}
+
+/// Notifies the owner of a reminder of its occurrence due `at`, then schedules the next one,
+/// skipping those missed while no server was running. Jobs for an occurrence the reminder
+/// has moved past since, because it fired or was changed, do nothing.
+pub async fn fire_reminder(state: &AppState, reminder_id: i32, at: DateTime<Utc>) -> Result<(), (StatusCode, String)> {
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    let row = tx.query_opt(
+        &format!("SELECT r.user_id, r.note_id, r.remind_at, r.recurrence, r.occurrence, r.next_at, n.title, \
+                n.deleted_at IS NULL AND (n.user_id = r.user_id OR EXISTS ( \
+                    SELECT 1 FROM {NOTE_SHARES_TABLE_NAME} s WHERE s.note_id = n.id AND s.user_id = r.user_id)) \
+            FROM {REMINDERS_TABLE_NAME} r JOIN {NOTES_TABLE_NAME} n ON n.id = r.note_id \
+            WHERE r.id=$1 FOR UPDATE OF r"),
+        &[&reminder_id]
+    ).await.map_err(internal_error)?;
+
+    let Some(row) = row else {
+        return Ok(());
+    };
+    if row.get::<usize, Option<DateTime<Utc>>>(5) != Some(at) {
+        return Ok(());
+    }
+
+    let (user_id, note_id): (i32, i32) = (row.get(0), row.get(1));
+    let title: String = row.get(6);
+
+    // reminders on notes in the trash, or no longer shared with their owner, stay silent
+    if row.get::<usize, bool>(7) {
+        let message = match title.is_empty() {
+            true => "Reminder: untitled note".to_string(),
+            false => format!("Reminder: {title}"),
+        };
+        notify(&tx, state, user_id, NotificationKind::Reminder, Some(note_id), &message).await?;
+    }
+
+    let recurrence = row.get::<usize, Option<String>>(3).and_then(|rule| rule.parse::<Recurrence>().ok());
+    let fired = row.get::<usize, i32>(4);
+    let next = upcoming(row.get(2), recurrence.as_ref(), fired as u32 + 1, Utc::now());
+
+    let occurrence = next.map_or(fired.saturating_add(1), |(occurrence, _)| occurrence);
+    let next_at = next.map(|(_, next_at)| next_at);
+
+    tx.execute(
+        &format!("UPDATE {REMINDERS_TABLE_NAME} SET occurrence=$2, next_at=$3 WHERE id=$1"),
+        &[&reminder_id, &occurrence, &next_at]
+    ).await.map_err(internal_error)?;
+
+    if let Some(next_at) = next_at {
+        enqueue(&tx, &Job::FireReminder { reminder_id, at: next_at }, next_at).await?;
+    }
+
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(())
+}
+
+/// The current user's reminders on a note. Anyone who can see a note may set reminders on it.
+pub async fn get_note_reminders(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+) -> Result<Json<Vec<Reminder>>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+    ensure_note_access(&*conn, note_id, &user, Access::Viewer).await?;
+
+    let rows = conn.query(
+        &format!("SELECT {REMINDER_COLUMNS} FROM {REMINDERS_TABLE_NAME} WHERE note_id=$1 AND user_id=$2 ORDER BY id"),
+        &[&note_id, &user.id]
+    ).await.map_err(internal_error)?;
+
+    Ok(Json(rows.iter().map(Reminder::from).collect()))
+}
+
+pub async fn create_reminder(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    Json(body): Json<ReminderPayload>,
+) -> Result<Json<Reminder>, (StatusCode, String)> {
+    let Schedule { remind_at, recurrence, occurrence, next_at } = schedule(&body)?;
+
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    ensure_note_access(&tx, note_id, &user, Access::Viewer).await?;
+
+    let row = tx.query_one(
+        &format!("INSERT INTO {REMINDERS_TABLE_NAME} (note_id, user_id, remind_at, recurrence, occurrence, next_at) \
+            VALUES ($1, $2, $3, $4, $5, $6) RETURNING {REMINDER_COLUMNS}"),
+        &[&note_id, &user.id, &remind_at, &recurrence, &occurrence, &next_at]
+    ).await.map_err(internal_error)?;
+
+    let reminder = Reminder::from(&row);
+    enqueue(&tx, &Job::FireReminder { reminder_id: reminder.id, at: next_at }, next_at).await?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(reminder))
+}
+
+/// The current user's reminders still to fire, soonest first.
+pub async fn get_reminders(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    OriginalUri(uri): OriginalUri,
+    pagination: Query<Pagination>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let keys = vec![SortKey::asc("next_at", KeyKind::Time), SortKey::asc("id", KeyKind::Int)];
+    let page = pagination.page(&state.settings, keys)?;
+
+    let mut query = QueryBuilder::new(Sql::new(REMINDERS_TABLE_NAME));
+    query.filter(Condition::eq("user_id", user.id));
+    query.filter(Condition::raw("next_at IS NOT NULL"));
+
+    let query_reminders = query.select(&format!("{REMINDER_COLUMNS}{}", page.key_columns())).page(&page);
+    let query_count = query.count();
+
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let (rows, count) = tokio::try_join!(
+        conn.query(query_reminders.sql(), query_reminders.params()),
+        async {
+            match pagination.with_count() {
+                true => conn.query_one(query_count.sql(), query_count.params()).await.map(|row| Some(row.get::<usize, i64>(0))),
+                false => Ok(None),
+            }
+        }
+    ).map_err(internal_error)?;
+
+    let (rows, links) = page.finish(rows)?;
+    let reminders: Vec<Reminder> = rows.iter().map(Reminder::from).collect();
+
+    let mut response = Json(reminders).into_response();
+    if let Some(count) = count {
+        let header_count_value = HeaderValue::from_str(&count.to_string()).map_err(internal_error)?;
+        response.headers_mut().insert("x-total-count", header_count_value);
+    }
+    if let Some(link_value) = link_header(&uri, &links)? {
+        response.headers_mut().insert(header::LINK, link_value);
+    }
+
+    Ok(response)
+}
+
+/// Reschedules a reminder from scratch, its recurrence starting over at `remind_at`.
+pub async fn update_reminder(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(reminder_id): Path<i32>,
+    Json(body): Json<ReminderPayload>,
+) -> Result<Json<Reminder>, (StatusCode, String)> {
+    let Schedule { remind_at, recurrence, occurrence, next_at } = schedule(&body)?;
+
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    let row = tx.query_opt(
+        &format!("UPDATE {REMINDERS_TABLE_NAME} SET remind_at=$3, recurrence=$4, occurrence=$5, next_at=$6 \
+            WHERE id=$1 AND user_id=$2 RETURNING {REMINDER_COLUMNS}"),
+        &[&reminder_id, &user.id, &remind_at, &recurrence, &occurrence, &next_at]
+    ).await.map_err(internal_error)?
+    .ok_or_else(reminder_not_found)?;
+
+    cancel_jobs(&tx, reminder_job_matching(reminder_id)).await?;
+    enqueue(&tx, &Job::FireReminder { reminder_id, at: next_at }, next_at).await?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(Reminder::from(&row)))
+}
+
+pub async fn delete_reminder(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(reminder_id): Path<i32>,
+) -> Result<Json<Reminder>, (StatusCode, String)> {
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    let row = tx.query_opt(
+        &format!("DELETE FROM {REMINDERS_TABLE_NAME} WHERE id=$1 AND user_id=$2 RETURNING {REMINDER_COLUMNS}"),
+        &[&reminder_id, &user.id]
+    ).await.map_err(internal_error)?
+    .ok_or_else(reminder_not_found)?;
+
+    cancel_jobs(&tx, reminder_job_matching(reminder_id)).await?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(Reminder::from(&row)))
+}
@@ -0,0 +1,3 @@
+pub mod api;
+pub mod types;
+pub mod recurrence;
\ No newline at end of file
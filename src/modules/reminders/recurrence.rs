@@ -0,0 +1,279 @@
+use std::{fmt, str::FromStr};
+
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDateTime, Utc};
+
+/// Longest step between two occurrences, in units of the frequency.
+const MAX_INTERVAL: u32 = 1000;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Frequency {
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// The subset of iCalendar recurrence rules (RFC 5545 `RRULE`) reminders understand:
+/// `FREQ` with an optional `INTERVAL`, ended by either `COUNT` or `UNTIL`, e.g.
+/// `FREQ=WEEKLY;INTERVAL=2;COUNT=10`. Occurrences are counted from the first reminder time,
+/// monthly ones falling on the last day of shorter months.
+#[derive(Clone, PartialEq)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl Recurrence {
+    /// Occurrence `n` of the series starting at `start`, `0` being `start` itself. `None` past
+    /// the end of the series.
+    pub fn nth(&self, start: DateTime<Utc>, n: u32) -> Option<DateTime<Utc>> {
+        if self.count.is_some_and(|count| n >= count) {
+            return None;
+        }
+
+        let steps = n.checked_mul(self.interval)?;
+        let at = match self.frequency {
+            Frequency::Hourly => start.checked_add_signed(Duration::hours(steps.into())),
+            Frequency::Daily => start.checked_add_signed(Duration::days(steps.into())),
+            Frequency::Weekly => start.checked_add_signed(Duration::weeks(steps.into())),
+            Frequency::Monthly => start.checked_add_months(Months::new(steps)),
+            Frequency::Yearly => start.checked_add_months(Months::new(steps.checked_mul(12)?)),
+        }?;
+
+        match self.until {
+            Some(until) if at > until => None,
+            _ => Some(at),
+        }
+    }
+
+    /// The number of an occurrence at or before `now`, to look for the next one from without
+    /// walking the whole series. It is the last one at or before `now` for the fixed-length
+    /// frequencies; months vary in length, so monthly and yearly ones may land a step or two
+    /// earlier.
+    pub fn skip_past(&self, start: DateTime<Utc>, now: DateTime<Utc>) -> u32 {
+        if now <= start {
+            return 0;
+        }
+
+        let interval = i64::from(self.interval);
+        let steps = match self.frequency {
+            Frequency::Hourly => (now - start).num_hours() / interval,
+            Frequency::Daily => (now - start).num_days() / interval,
+            Frequency::Weekly => (now - start).num_weeks() / interval,
+            Frequency::Monthly | Frequency::Yearly => {
+                let months = i64::from(now.year() - start.year()) * 12 + i64::from(now.month()) - i64::from(start.month());
+                let step = if self.frequency == Frequency::Yearly { interval * 12 } else { interval };
+                // occurrence n falls in month n * step of the series, so those up to month `months - 1` are past
+                (months - 1).max(0) / step
+            }
+        };
+
+        u32::try_from(steps).unwrap_or(u32::MAX)
+    }
+}
+
+fn parse_until(value: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ")
+        .map(|until| until.and_utc())
+        .or_else(|_| DateTime::parse_from_rfc3339(value).map(|until| until.with_timezone(&Utc)))
+        .ok()
+}
+
+impl FromStr for Recurrence {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let rule = rule.trim();
+        let rule = rule.strip_prefix("RRULE:").unwrap_or(rule);
+
+        let mut frequency = None;
+        let mut recurrence = Recurrence { frequency: Frequency::Daily, interval: 1, count: None, until: None };
+
+        for part in rule.split(';').map(str::trim).filter(|part| !part.is_empty()) {
+            let (name, value) = part.split_once('=').ok_or_else(|| format!("Invalid recurrence rule part \"{part}\""))?;
+
+            match name.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    frequency = Some(match value.to_ascii_uppercase().as_str() {
+                        "HOURLY" => Frequency::Hourly,
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        "YEARLY" => Frequency::Yearly,
+                        _ => return Err(format!("Unsupported recurrence frequency \"{value}\"")),
+                    });
+                }
+                "INTERVAL" => {
+                    recurrence.interval = value.parse().ok()
+                        .filter(|interval| (1..=MAX_INTERVAL).contains(interval))
+                        .ok_or_else(|| format!("INTERVAL must be between 1 and {MAX_INTERVAL}"))?;
+                }
+                "COUNT" => {
+                    recurrence.count = Some(value.parse().ok()
+                        .filter(|count| *count > 0)
+                        .ok_or_else(|| "COUNT must be a positive number".to_string())?);
+                }
+                "UNTIL" => {
+                    recurrence.until = Some(parse_until(value)
+                        .ok_or_else(|| "UNTIL must be a UTC time such as 20261231T235959Z".to_string())?);
+                }
+                _ => return Err(format!("Unsupported recurrence rule part \"{name}\"")),
+            }
+        }
+
+        if recurrence.count.is_some() && recurrence.until.is_some() {
+            return Err("A recurrence rule can't have both COUNT and UNTIL".to_string());
+        }
+
+        recurrence.frequency = frequency.ok_or_else(|| "A recurrence rule needs a FREQ".to_string())?;
+        Ok(recurrence)
+    }
+}
+
+/// Writes the rule back in its canonical form, leaving out defaults.
+impl fmt::Display for Recurrence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let frequency = match self.frequency {
+            Frequency::Hourly => "HOURLY",
+            Frequency::Daily => "DAILY",
+            Frequency::Weekly => "WEEKLY",
+            Frequency::Monthly => "MONTHLY",
+            Frequency::Yearly => "YEARLY",
+        };
+        write!(f, "FREQ={frequency}")?;
+
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={count}")?;
+        }
+        if let Some(until) = self.until {
+            write!(f, ";UNTIL={}", until.format("%Y%m%dT%H%M%SZ"))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(time).unwrap().with_timezone(&Utc)
+    }
+
+    fn rule(rule: &str) -> Recurrence {
+        rule.parse().unwrap()
+    }
+
+    fn occurrences(recurrence: &Recurrence, start: &str, n: u32) -> Vec<Option<String>> {
+        (0..n)
+            .map(|i| recurrence.nth(at(start), i).map(|at| at.to_rfc3339()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_every_part() {
+        let recurrence = rule("RRULE:freq=weekly; INTERVAL=2;COUNT=10");
+        assert!(recurrence.frequency == Frequency::Weekly);
+        assert_eq!((recurrence.interval, recurrence.count, recurrence.until), (2, Some(10), None));
+
+        assert_eq!(rule("FREQ=DAILY;UNTIL=20261231T235959Z").until, Some(at("2026-12-31T23:59:59Z")));
+        assert_eq!(rule("FREQ=DAILY;UNTIL=2026-12-31T23:59:59+01:00").until, Some(at("2026-12-31T22:59:59Z")));
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for invalid in [
+            "",
+            "INTERVAL=2",
+            "FREQ=SECONDLY",
+            "FREQ=DAILY;BYDAY=MO",
+            "FREQ=DAILY;COUNT",
+            "FREQ=DAILY;COUNT=0",
+            "FREQ=DAILY;COUNT=-1",
+            "FREQ=DAILY;INTERVAL=0",
+            "FREQ=DAILY;INTERVAL=1001",
+            "FREQ=DAILY;UNTIL=tomorrow",
+            "FREQ=DAILY;COUNT=3;UNTIL=20261231T235959Z",
+        ] {
+            assert!(invalid.parse::<Recurrence>().is_err(), "{invalid} should be rejected");
+        }
+
+        assert!("FREQ=DAILY;INTERVAL=1000".parse::<Recurrence>().is_ok());
+    }
+
+    #[test]
+    fn writes_rules_back_in_canonical_form() {
+        for (written, canonical) in [
+            ("freq=daily;interval=1", "FREQ=DAILY"),
+            ("RRULE:FREQ=MONTHLY;COUNT=3;INTERVAL=2", "FREQ=MONTHLY;INTERVAL=2;COUNT=3"),
+            ("FREQ=YEARLY;UNTIL=2030-01-01T00:00:00Z", "FREQ=YEARLY;UNTIL=20300101T000000Z"),
+        ] {
+            let recurrence = rule(written);
+            assert_eq!(recurrence.to_string(), canonical);
+            assert!(rule(canonical) == recurrence);
+        }
+    }
+
+    #[test]
+    fn monthly_occurrences_fall_on_the_last_day_of_shorter_months() {
+        let some = |time: &str| Some(at(time).to_rfc3339());
+
+        assert_eq!(
+            occurrences(&rule("FREQ=MONTHLY"), "2024-01-31T09:00:00Z", 4),
+            [some("2024-01-31T09:00:00Z"), some("2024-02-29T09:00:00Z"), some("2024-03-31T09:00:00Z"), some("2024-04-30T09:00:00Z")]
+        );
+        // counted from the start, so the day comes back after a short month
+        assert_eq!(
+            occurrences(&rule("FREQ=YEARLY"), "2024-02-29T09:00:00Z", 5)[4],
+            some("2028-02-29T09:00:00Z")
+        );
+    }
+
+    #[test]
+    fn ends_after_count_or_until() {
+        let counted = occurrences(&rule("FREQ=HOURLY;INTERVAL=3;COUNT=2"), "2026-01-01T00:00:00Z", 3);
+        assert_eq!(counted, [Some(at("2026-01-01T00:00:00Z").to_rfc3339()), Some(at("2026-01-01T03:00:00Z").to_rfc3339()), None]);
+
+        // UNTIL itself is the last possible occurrence
+        let until = rule("FREQ=WEEKLY;UNTIL=20260115T000000Z");
+        assert_eq!(until.nth(at("2026-01-01T00:00:00Z"), 2), Some(at("2026-01-15T00:00:00Z")));
+        assert_eq!(until.nth(at("2026-01-01T00:00:00Z"), 3), None);
+    }
+
+    #[test]
+    fn occurrences_out_of_range_end_the_series() {
+        let start = at("2026-01-01T00:00:00Z");
+
+        assert_eq!(rule("FREQ=YEARLY;INTERVAL=1000").nth(start, u32::MAX), None);
+        assert_eq!(rule("FREQ=MONTHLY;INTERVAL=1000").nth(start, 1_000_000), None);
+        assert!(rule("FREQ=DAILY;INTERVAL=1000").nth(start, 1000).is_some());
+    }
+
+    #[test]
+    fn skip_past_lands_at_or_before_now() {
+        let start = at("2000-01-31T10:00:00Z");
+        let now = at("2026-10-19T12:00:00Z");
+
+        for rule in ["FREQ=HOURLY", "FREQ=DAILY;INTERVAL=3", "FREQ=WEEKLY", "FREQ=MONTHLY", "FREQ=MONTHLY;INTERVAL=5", "FREQ=YEARLY"] {
+            let recurrence = self::rule(rule);
+            let skipped = recurrence.skip_past(start, now);
+
+            assert!(recurrence.nth(start, skipped).unwrap() <= now, "{rule}");
+            assert!(recurrence.nth(start, skipped + 3).unwrap() > now, "{rule}");
+        }
+
+        // exact for fixed-length frequencies
+        let hourly = rule("FREQ=HOURLY;INTERVAL=7");
+        let skipped = hourly.skip_past(start, now);
+        assert!(hourly.nth(start, skipped + 1).unwrap() > now);
+
+        assert_eq!(rule("FREQ=DAILY").skip_past(start, at("1999-01-01T00:00:00Z")), 0);
+    }
+}
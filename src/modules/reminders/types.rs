@@ -0,0 +1,39 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+/// A reminder of the current user on a note. `next_at` is when it fires next, `None` once a
+/// one-off reminder fired or its recurrence ended.
+#[derive(Serialize)]
+pub struct Reminder {
+  pub id: i32,
+  pub note_id: i32,
+  pub remind_at: DateTime<Utc>,
+  pub recurrence: Option<String>,
+  pub next_at: Option<DateTime<Utc>>,
+  pub created_at: DateTime<Utc>
+}
+
+/// Columns expected by `Reminder::from(&Row)`, in order.
+pub const REMINDER_COLUMNS: &str = "id, note_id, remind_at, recurrence, next_at, created_at";
+
+impl From<&Row> for Reminder {
+  fn from(row: &Row) -> Reminder {
+    Reminder {
+      id: row.get(0),
+      note_id: row.get(1),
+      remind_at: row.get(2),
+      recurrence: row.get(3),
+      next_at: row.get(4),
+      created_at: row.get(5)
+    }
+  }
+}
+
+/// `remind_at` is the first occurrence, `recurrence` a rule such as `FREQ=WEEKLY;COUNT=4`,
+/// see `Recurrence`.
+#[derive(Deserialize)]
+pub struct ReminderPayload {
+  pub remind_at: DateTime<Utc>,
+  pub recurrence: Option<String>
+}
@@ -0,0 +1,212 @@
+use axum::{
+    Extension,
+    extract::{State, Query, Path},
+    http::{StatusCode, HeaderValue, HeaderMap, Response},
+    Json, response::IntoResponse,
+    body::Body
+};
+
+use similar::{ChangeTag, TextDiff};
+use tokio_postgres::{types::ToSql, GenericClient};
+
+use crate::{
+    types::{internal_error, AppState, Pagination},
+    modules::notes::{api::{apply_note_update, ensure_note_access}, types::{Access, Note, NoteChanges}},
+    modules::revisions::types::*,
+    modules::users::types::User,
+    NOTES_TABLE_NAME, NOTE_REVISIONS_TABLE_NAME, REVISION_RETENTION_TABLE_NAME
+};
+
+/// Stores `note` as a revision authored by `author_id` and applies the owner's retention policy.
+/// Meant to run inside the transaction that wrote the note.
+pub async fn record_revision<C: GenericClient>(
+    client: &C,
+    note: &Note,
+    author_id: i32,
+) -> Result<(), (StatusCode, String)> {
+    client.execute(
+        &format!("INSERT INTO {NOTE_REVISIONS_TABLE_NAME} (note_id, version, title, text, author_id) VALUES ($1, $2, $3, $4, $5)"),
+        &[&note.id, &note.version, &note.title, &note.text, &author_id]
+    ).await.map_err(internal_error)?;
+
+    prune_revisions(client, "n.id", note.id).await
+}
+
+/// Drops revisions falling outside the owner's retention policy for every note matching
+/// `scope = id`. The newest revision of each note, which mirrors its current text, is never dropped.
+async fn prune_revisions<C: GenericClient>(
+    client: &C,
+    scope: &str,
+    id: i32,
+) -> Result<(), (StatusCode, String)> {
+    client.execute(
+        &format!("DELETE FROM {NOTE_REVISIONS_TABLE_NAME} WHERE id IN ( \
+            SELECT ranked.id FROM ( \
+                SELECT r.id, r.created_at, n.user_id, \
+                    row_number() OVER (PARTITION BY r.note_id ORDER BY r.version DESC) AS position \
+                FROM {NOTE_REVISIONS_TABLE_NAME} r JOIN {NOTES_TABLE_NAME} n ON n.id = r.note_id \
+                WHERE {scope} = $1 \
+            ) ranked \
+            JOIN {REVISION_RETENTION_TABLE_NAME} p ON p.user_id = ranked.user_id \
+            WHERE ranked.position > 1 AND ( \
+                ranked.position > p.max_revisions \
+                OR ranked.created_at < now() - make_interval(days => p.max_age_days) \
+            ) \
+        )"),
+        &[&id]
+    ).await.map_err(internal_error)?;
+
+    Ok(())
+}
+
+pub async fn get_revisions(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    pagination: Query<Pagination>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+    ensure_note_access(&*conn, note_id, &user, Access::Viewer).await?;
+
+    let query_revisions = format!("SELECT {REVISION_COLUMNS} FROM {NOTE_REVISIONS_TABLE_NAME} \
+        WHERE note_id=$1 ORDER BY version DESC LIMIT $2 OFFSET $3");
+    let query_count = format!("SELECT count(*) FROM {NOTE_REVISIONS_TABLE_NAME} WHERE note_id=$1");
+
+    let (limit, offset) = (pagination.limit(&state.settings)?, pagination.offset());
+    let params_revisions: Vec<&(dyn ToSql + Sync)> = vec![&note_id, &limit, &offset];
+    let params_count: Vec<&(dyn ToSql + Sync)> = vec![&note_id];
+
+    let (rows, row_count) = tokio::try_join!(
+        conn.query(&query_revisions, &params_revisions),
+        conn.query_one(&query_count, &params_count)
+    ).map_err(internal_error)?;
+
+    let revisions: Vec<Revision> = rows.iter().map(Revision::from).collect();
+
+    let count: i64 = row_count.get(0);
+    let header_count_value = HeaderValue::from_str(&count.to_string()).map_err(internal_error)?;
+
+    let mut response = Json(revisions).into_response();
+    response.headers_mut().insert("x-total-count", header_count_value);
+
+    Ok(response)
+}
+
+pub async fn get_revision(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path((note_id, version)): Path<(i32, i32)>,
+) -> Result<Json<Revision>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+    ensure_note_access(&*conn, note_id, &user, Access::Viewer).await?;
+
+    find_revision(&*conn, note_id, version).await.map(Json)
+}
+
+pub async fn diff_revisions(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    query: Query<DiffQuery>,
+) -> Result<Json<RevisionDiff>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+    ensure_note_access(&*conn, note_id, &user, Access::Viewer).await?;
+
+    let (from, to) = tokio::try_join!(
+        find_revision(&*conn, note_id, query.from),
+        find_revision(&*conn, note_id, query.to)
+    )?;
+
+    let diff = TextDiff::from_lines(&from.text, &to.text);
+
+    let changes = diff
+        .iter_all_changes()
+        .map(|change| DiffChange {
+            kind: match change.tag() {
+                ChangeTag::Equal => ChangeKind::Equal,
+                ChangeTag::Insert => ChangeKind::Insert,
+                ChangeTag::Delete => ChangeKind::Delete,
+            },
+            text: change.value().to_string()
+        })
+        .collect();
+
+    let unified = diff
+        .unified_diff()
+        .header(&format!("version {}", from.version), &format!("version {}", to.version))
+        .to_string();
+
+    Ok(Json(RevisionDiff { from: from.version, to: to.version, unified, changes }))
+}
+
+pub async fn restore_revision(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path((note_id, version)): Path<(i32, i32)>,
+    headers: HeaderMap,
+) -> Result<Json<Note>, (StatusCode, String)> {
+    let revision = {
+        let conn = state.pool.get().await.map_err(internal_error)?;
+        ensure_note_access(&*conn, note_id, &user, Access::Viewer).await?;
+        find_revision(&*conn, note_id, version).await?
+    };
+
+    let changes = NoteChanges { title: Some(revision.title), text: Some(revision.text), ..Default::default() };
+    apply_note_update(&state, &user, note_id, changes, &headers).await.map(Json)
+}
+
+pub async fn get_retention_policy(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+) -> Result<Json<RetentionPolicy>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let row = conn.query_opt(
+        &format!("SELECT max_revisions, max_age_days FROM {REVISION_RETENTION_TABLE_NAME} WHERE user_id=$1"),
+        &[&user.id]
+    ).await.map_err(internal_error)?;
+
+    let policy = row
+        .map(|row| RetentionPolicy { max_revisions: row.get(0), max_age_days: row.get(1) })
+        .unwrap_or_default();
+
+    Ok(Json(policy))
+}
+
+pub async fn set_retention_policy(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(body): Json<RetentionPolicy>,
+) -> Result<Json<RetentionPolicy>, (StatusCode, String)> {
+    if body.max_revisions.is_some_and(|n| n < 1) || body.max_age_days.is_some_and(|n| n < 1) {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "Retention limits must be positive".to_string()));
+    }
+
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    tx.execute(
+        &format!("INSERT INTO {REVISION_RETENTION_TABLE_NAME} (user_id, max_revisions, max_age_days) VALUES ($1, $2, $3) \
+            ON CONFLICT (user_id) DO UPDATE SET max_revisions = EXCLUDED.max_revisions, max_age_days = EXCLUDED.max_age_days"),
+        &[&user.id, &body.max_revisions, &body.max_age_days]
+    ).await.map_err(internal_error)?;
+
+    prune_revisions(&tx, "n.user_id", user.id).await?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(body))
+}
+
+async fn find_revision<C: GenericClient>(
+    client: &C,
+    note_id: i32,
+    version: i32,
+) -> Result<Revision, (StatusCode, String)> {
+    let row = client.query_opt(
+        &format!("SELECT {REVISION_COLUMNS} FROM {NOTE_REVISIONS_TABLE_NAME} WHERE note_id=$1 AND version=$2"),
+        &[&note_id, &version]
+    ).await.map_err(internal_error)?
+    .ok_or_else(|| (StatusCode::NOT_FOUND, format!("Revision {version} not found")))?;
+
+    Ok(Revision::from(&row))
+}
@@ -0,0 +1,67 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+#[derive(Deserialize, Serialize)]
+pub struct Revision {
+  pub id: i32,
+  pub note_id: i32,
+  pub version: i32,
+  pub title: String,
+  pub text: String,
+  pub author_id: Option<i32>,
+  pub created_at: DateTime<Utc>
+}
+
+/// Columns expected by `Revision::from(&Row)`, in order.
+pub const REVISION_COLUMNS: &str = "id, note_id, version, title, text, author_id, created_at";
+
+impl From<&Row> for Revision {
+  fn from(row: &Row) -> Revision {
+    Revision {
+      id: row.get(0),
+      note_id: row.get(1),
+      version: row.get(2),
+      title: row.get(3),
+      text: row.get(4),
+      author_id: row.get(5),
+      created_at: row.get(6)
+    }
+  }
+}
+
+#[derive(Deserialize)]
+pub struct DiffQuery {
+  pub from: i32,
+  pub to: i32
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+  Equal,
+  Insert,
+  Delete
+}
+
+#[derive(Serialize)]
+pub struct DiffChange {
+  pub kind: ChangeKind,
+  pub text: String
+}
+
+#[derive(Serialize)]
+pub struct RevisionDiff {
+  pub from: i32,
+  pub to: i32,
+  pub unified: String,
+  pub changes: Vec<DiffChange>
+}
+
+/// Per-user limits on how much history is kept for each note. `None` means unlimited.
+/// The current version of a note is always kept.
+#[derive(Deserialize, Serialize, Default)]
+pub struct RetentionPolicy {
+  pub max_revisions: Option<i32>,
+  pub max_age_days: Option<i32>
+}
@@ -0,0 +1,148 @@
+use axum::{
+    Extension,
+    extract::{State, Query, Path, OriginalUri},
+    http::{StatusCode, HeaderValue, Response, header},
+    Json, response::IntoResponse,
+    body::Body
+};
+
+use tokio_postgres::{error::SqlState, GenericClient};
+
+use crate::{
+    types::{internal_error, AppState, Pagination},
+    modules::common::pagination::{link_header, KeyKind, SortKey},
+    modules::common::query::{Condition, QueryBuilder, Sql},
+    modules::notes::{api::ensure_note_access, types::{Access, NOTE_COLUMNS}},
+    modules::shares::types::*,
+    modules::users::types::User,
+    NOTES_TABLE_NAME, NOTE_SHARES_TABLE_NAME, USER_TABLE_NAME
+};
+
+/// Everyone `note_id` is shared with, for its owner.
+pub async fn get_shares(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+) -> Result<Json<Vec<Share>>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+    ensure_note_access(&*conn, note_id, &user, Access::Owner).await?;
+
+    let rows = conn.query(
+        &format!("SELECT {SHARE_COLUMNS} FROM {NOTE_SHARES_TABLE_NAME} s JOIN {USER_TABLE_NAME} u ON u.id = s.user_id \
+            WHERE s.note_id=$1 ORDER BY s.created_at, s.user_id"),
+        &[&note_id]
+    ).await.map_err(internal_error)?;
+
+    Ok(Json(rows.iter().map(Share::from).collect()))
+}
+
+/// Shares `note_id` with `user_id`, or changes the permission of an existing share.
+pub async fn share_note(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path((note_id, user_id)): Path<(i32, i32)>,
+    Json(body): Json<SharePayload>,
+) -> Result<Json<Share>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+    ensure_note_access(&*conn, note_id, &user, Access::Owner).await?;
+
+    if user_id == user.id {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "Notes can't be shared with their owner".to_string()));
+    }
+
+    conn.execute(
+        &format!("INSERT INTO {NOTE_SHARES_TABLE_NAME} (note_id, user_id, permission) VALUES ($1, $2, $3) \
+            ON CONFLICT (note_id, user_id) DO UPDATE SET permission = EXCLUDED.permission"),
+        &[&note_id, &user_id, &(body.permission as i16)]
+    ).await.map_err(|e| {
+        if e.code() == Some(&SqlState::FOREIGN_KEY_VIOLATION) {
+            (StatusCode::NOT_FOUND, "User not found".to_string())
+        } else {
+            internal_error(e)
+        }
+    })?;
+
+    find_share(&*conn, note_id, user_id).await.map(Json)
+}
+
+/// Revokes a share. Besides the owner, recipients may remove themselves from a note.
+pub async fn revoke_share(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path((note_id, user_id)): Path<(i32, i32)>,
+) -> Result<Json<Share>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let required = if user_id == user.id { Access::Viewer } else { Access::Owner };
+    ensure_note_access(&*conn, note_id, &user, required).await?;
+
+    let row = conn.query_opt(
+        &format!("DELETE FROM {NOTE_SHARES_TABLE_NAME} s USING {USER_TABLE_NAME} u \
+            WHERE s.note_id=$1 AND s.user_id=$2 AND u.id = s.user_id RETURNING {SHARE_COLUMNS}"),
+        &[&note_id, &user_id]
+    ).await.map_err(internal_error)?
+    .ok_or_else(share_not_found)?;
+
+    Ok(Json(Share::from(&row)))
+}
+
+/// Notes other users shared with the current user, most recently shared first.
+pub async fn get_shared_notes(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    OriginalUri(uri): OriginalUri,
+    pagination: Query<Pagination>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let keys = vec![SortKey::desc("share.shared_at", KeyKind::Time), SortKey::asc("notes.id", KeyKind::Int)];
+    let page = pagination.page(&state.settings, keys)?;
+
+    let mut query = QueryBuilder::new(Sql::new(&format!("{NOTES_TABLE_NAME} JOIN (SELECT note_id, permission, \
+            created_at AS shared_at FROM {NOTE_SHARES_TABLE_NAME} WHERE user_id = "))
+        .bind(user.id)
+        .text(") share ON share.note_id = notes.id"));
+    query.filter(Condition::raw("deleted_at IS NULL"));
+
+    let query_notes = query.select(&format!("{NOTE_COLUMNS}, {SHARED_NOTE_COLUMNS}{}", page.key_columns())).page(&page);
+    let query_count = query.count();
+
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let (rows, count) = tokio::try_join!(
+        conn.query(query_notes.sql(), query_notes.params()),
+        async {
+            match pagination.with_count() {
+                true => conn.query_one(query_count.sql(), query_count.params()).await.map(|row| Some(row.get::<usize, i64>(0))),
+                false => Ok(None),
+            }
+        }
+    ).map_err(internal_error)?;
+
+    let (rows, links) = page.finish(rows)?;
+    let notes: Vec<SharedNote> = rows.iter().map(SharedNote::from).collect();
+
+    let mut response = Json(notes).into_response();
+    if let Some(count) = count {
+        let header_count_value = HeaderValue::from_str(&count.to_string()).map_err(internal_error)?;
+        response.headers_mut().insert("x-total-count", header_count_value);
+    }
+    if let Some(link_value) = link_header(&uri, &links)? {
+        response.headers_mut().insert(header::LINK, link_value);
+    }
+
+    Ok(response)
+}
+
+async fn find_share<C: GenericClient>(client: &C, note_id: i32, user_id: i32) -> Result<Share, (StatusCode, String)> {
+    let row = client.query_opt(
+        &format!("SELECT {SHARE_COLUMNS} FROM {NOTE_SHARES_TABLE_NAME} s JOIN {USER_TABLE_NAME} u ON u.id = s.user_id \
+            WHERE s.note_id=$1 AND s.user_id=$2"),
+        &[&note_id, &user_id]
+    ).await.map_err(internal_error)?
+    .ok_or_else(share_not_found)?;
+
+    Ok(Share::from(&row))
+}
+
+fn share_not_found() -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, "Share not found".to_string())
+}
@@ -0,0 +1,95 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use crate::modules::notes::types::{Access, Note};
+
+/// What a share lets its recipient do with the note, stored as `smallint`.
+#[derive(Deserialize, Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+  /// Read the note and its revisions.
+  Viewer = 0,
+  /// Also change its text and tags, and restore revisions.
+  Editor = 1
+}
+
+impl From<i16> for Permission {
+  fn from(number: i16) -> Permission {
+    match number {
+      1 => Self::Editor,
+      _ => Self::Viewer
+    }
+  }
+}
+
+impl From<Permission> for Access {
+  fn from(permission: Permission) -> Access {
+    match permission {
+      Permission::Viewer => Access::Viewer,
+      Permission::Editor => Access::Editor
+    }
+  }
+}
+
+#[derive(Serialize)]
+pub struct Share {
+  pub user_id: i32,
+  pub username: String,
+  pub permission: Permission,
+  pub created_at: DateTime<Utc>
+}
+
+/// Columns expected by `Share::from(&Row)`, in order, given `note_shares s` joined with `users u`.
+pub const SHARE_COLUMNS: &str = "s.user_id, u.username, s.permission, s.created_at";
+
+impl From<&Row> for Share {
+  fn from(row: &Row) -> Share {
+    Share {
+      user_id: row.get(0),
+      username: row.get(1),
+      permission: row.get::<usize, i16>(2).into(),
+      created_at: row.get(3)
+    }
+  }
+}
+
+#[derive(Deserialize)]
+pub struct SharePayload {
+  pub permission: Permission
+}
+
+#[derive(Serialize)]
+pub struct NoteOwner {
+  pub id: i32,
+  pub username: String
+}
+
+/// A note someone else shared with the current user.
+#[derive(Serialize)]
+pub struct SharedNote {
+  #[serde(flatten)]
+  pub note: Note,
+  pub permission: Permission,
+  pub shared_at: DateTime<Utc>,
+  pub owner: NoteOwner
+}
+
+/// Extra columns selected along `NOTE_COLUMNS` for `SharedNote`s, given the current user's
+/// share in scope as `share`. They are read by name, whatever comes before them.
+pub const SHARED_NOTE_COLUMNS: &str = "share.permission AS share_permission, share.shared_at AS share_shared_at, \
+  notes.user_id AS owner_id, (SELECT u.username FROM users u WHERE u.id = notes.user_id) AS owner_username";
+
+impl From<&Row> for SharedNote {
+  fn from(row: &Row) -> SharedNote {
+    SharedNote {
+      note: Note::from(row),
+      permission: row.get::<&str, i16>("share_permission").into(),
+      shared_at: row.get("share_shared_at"),
+      owner: NoteOwner {
+        id: row.get("owner_id"),
+        username: row.get("owner_username")
+      }
+    }
+  }
+}
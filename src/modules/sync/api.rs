@@ -0,0 +1,358 @@
+use std::collections::HashSet;
+
+use axum::{
+    Extension,
+    extract::State,
+    http::StatusCode,
+    Json
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::{GenericClient, Transaction};
+
+use crate::{
+    types::{internal_error, AppState, Settings},
+    modules::events::api::{events_missing_after, latest_event_id},
+    modules::notes::{
+        api::{ensure_note_access, insert_note, trash_note, write_note_changes},
+        types::{Access, CreateNotePayload, Note, NoteChanges, TagChange, UpdateNotePayload, NOTE_COLUMNS}
+    },
+    modules::sync::types::*,
+    modules::tags::api::normalize_tags,
+    modules::users::types::User,
+    NOTES_TABLE_NAME, NOTE_EVENTS_TABLE_NAME, NOTE_REVISIONS_TABLE_NAME, NOTE_SHARES_TABLE_NAME, SYNC_CLIENT_IDS_TABLE_NAME
+};
+
+const MAX_CLIENT_ID_LENGTH: usize = 100;
+
+/// Wire format of a sync token, before base64. `e` is the last note event the client has seen,
+/// `n` the last note sent while a snapshot is still being paged through.
+#[derive(Serialize, Deserialize)]
+struct SyncToken {
+    e: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    n: Option<i32>,
+}
+
+fn encode_token(token: &SyncToken) -> Result<String, (StatusCode, String)> {
+    let json = serde_json::to_vec(token).map_err(internal_error)?;
+    Ok(URL_SAFE_NO_PAD.encode(json))
+}
+
+fn decode_token(token: &str) -> Result<SyncToken, (StatusCode, String)> {
+    URL_SAFE_NO_PAD.decode(token)
+        .ok()
+        .and_then(|json| serde_json::from_slice(&json).ok())
+        .ok_or_else(|| (StatusCode::BAD_REQUEST, "Invalid sync token".to_string()))
+}
+
+/// Which side's value a field changed by the client keeps.
+enum FieldMerge {
+    /// The client sent the server's value, or left the field as it was.
+    Server,
+    /// Only the client changed the field since `base_version`.
+    Client,
+    /// Both sides changed it, differently. The server's value stays.
+    Conflict,
+}
+
+/// Three-way merge of a field against its value at the client's `base_version`, when the
+/// revision is still kept. Without it, any difference is a conflict.
+fn merge_field(sent: &str, server: &str, base: Option<&str>) -> FieldMerge {
+    match base {
+        _ if sent == server => FieldMerge::Server,
+        Some(base) if sent == base => FieldMerge::Server,
+        Some(base) if server == base => FieldMerge::Client,
+        _ => FieldMerge::Conflict,
+    }
+}
+
+/// Applies the changes a client made offline, then hands it the server changes since its
+/// last sync along with a new token. Each change is applied in its own savepoint and
+/// reported in `results`; a change to a note also changed on the server is settled as
+/// described by `SyncConflict`. The server changes come from the note events, so a token
+/// older than `EVENT_RETENTION_HOURS` starts over with a snapshot of every note.
+pub async fn sync_notes(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(body): Json<SyncPayload>,
+) -> Result<Json<SyncResponse>, (StatusCode, String)> {
+    if body.changes.len() > state.settings.bulk_max_notes {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Syncs are limited to {} changes, send the rest in the next one", state.settings.bulk_max_notes)
+        ));
+    }
+
+    let token = body.token.as_deref().map(decode_token).transpose()?;
+
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let mut tx = conn.transaction().await.map_err(internal_error)?;
+
+    let mut results: Vec<SyncResult> = vec![];
+    for (index, change) in body.changes.into_iter().enumerate() {
+        let mut savepoint = tx.transaction().await.map_err(internal_error)?;
+
+        let result = match apply_change(&mut savepoint, &state.settings, &user, index, change).await {
+            Err((status, message)) if status != StatusCode::INTERNAL_SERVER_ERROR => {
+                SyncResult { error: Some(message), ..SyncResult::new(index, SyncStatus::Rejected) }
+            }
+            result => result?,
+        };
+
+        match result.status {
+            SyncStatus::Rejected => savepoint.rollback().await.map_err(internal_error)?,
+            _ => savepoint.commit().await.map_err(internal_error)?,
+        }
+        results.push(result);
+    }
+
+    // the events after a token must all still be there, or the client starts over
+    let token = match token {
+        Some(token) if events_missing_after(&tx, token.e).await? => None,
+        token => token,
+    };
+    let latest = latest_event_id(&tx).await?;
+    let page_size = state.settings.max_page_size;
+
+    let mut touched: Vec<i32> = results.iter().filter_map(|result| result.note_id).collect();
+    let (reset, more, mut notes, next) = match token {
+        Some(SyncToken { e, n: None }) => {
+            let rows = tx.query(
+                &format!("SELECT id, note_id FROM {NOTE_EVENTS_TABLE_NAME} WHERE id > $1 AND $2 = ANY(user_ids) ORDER BY id LIMIT $3"),
+                &[&e, &user.id, &(page_size + 1)]
+            ).await.map_err(internal_error)?;
+
+            let more = rows.len() as i64 > page_size;
+            let rows = &rows[..rows.len().min(page_size as usize)];
+            touched.extend(rows.iter().map(|row| row.get::<usize, i32>(1)));
+
+            let e = match more {
+                true => rows.last().map_or(e, |row| row.get(0)),
+                false => latest.max(e),
+            };
+            (false, more, vec![], SyncToken { e, n: None })
+        }
+        token => {
+            let (reset, e, after) = match token {
+                Some(SyncToken { e, n: Some(after) }) => (false, e, after),
+                _ => (true, latest, 0),
+            };
+
+            let rows = tx.query(
+                &format!("SELECT {NOTE_COLUMNS} FROM {NOTES_TABLE_NAME} WHERE id > $1 AND {} ORDER BY id LIMIT $3", visible_to("$2")),
+                &[&after, &user.id, &(page_size + 1)]
+            ).await.map_err(internal_error)?;
+
+            let more = rows.len() as i64 > page_size;
+            let notes: Vec<Note> = rows.iter().take(page_size as usize).map(Note::from).collect();
+            let n = notes.last().map(|note| note.id).filter(|_| more);
+
+            (reset, more, notes, SyncToken { e, n })
+        }
+    };
+
+    let mut sent: HashSet<i32> = notes.iter().map(|note| note.id).collect();
+    touched.retain(|note_id| sent.insert(*note_id));
+
+    let rows = tx.query(
+        &format!("SELECT {NOTE_COLUMNS} FROM {NOTES_TABLE_NAME} WHERE id = ANY($1) AND {} ORDER BY id", visible_to("$2")),
+        &[&touched, &user.id]
+    ).await.map_err(internal_error)?;
+
+    let changed: Vec<Note> = rows.iter().map(Note::from).collect();
+    let deleted = touched.into_iter().filter(|note_id| !changed.iter().any(|note| note.id == *note_id)).collect();
+    notes.extend(changed);
+
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(SyncResponse { token: encode_token(&next)?, reset, more, notes, deleted, results }))
+}
+
+/// Notes not in the trash that belong to the user `$user` or are shared with them.
+fn visible_to(user: &str) -> String {
+    format!("deleted_at IS NULL AND (user_id = {user} OR EXISTS ( \
+        SELECT 1 FROM {NOTE_SHARES_TABLE_NAME} s WHERE s.note_id = {NOTES_TABLE_NAME}.id AND s.user_id = {user}))")
+}
+
+async fn apply_change(
+    client: &mut Transaction<'_>,
+    settings: &Settings,
+    user: &User,
+    index: usize,
+    change: SyncChange,
+) -> Result<SyncResult, (StatusCode, String)> {
+    match change {
+        SyncChange::Create { client_id, note } => apply_create(client, settings, user, index, client_id, note).await,
+        SyncChange::Update { id, base_version, changes } => apply_update(&*client, settings, user, index, id, base_version, changes).await,
+        SyncChange::Delete { id, base_version } => apply_delete(&*client, user, index, id, base_version).await,
+    }
+}
+
+/// Creates the note, unless an earlier sync already did for `client_id`. Of two syncs retried
+/// at the same time, the one claiming `client_id` last drops its note and reports the other's.
+async fn apply_create(
+    client: &mut Transaction<'_>,
+    settings: &Settings,
+    user: &User,
+    index: usize,
+    client_id: String,
+    note: CreateNotePayload,
+) -> Result<SyncResult, (StatusCode, String)> {
+    if client_id.is_empty() || client_id.chars().count() > MAX_CLIENT_ID_LENGTH {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, format!("Client ids must be between 1 and {MAX_CLIENT_ID_LENGTH} characters")));
+    }
+
+    let synced = client.query_opt(
+        &format!("SELECT note_id FROM {SYNC_CLIENT_IDS_TABLE_NAME} WHERE user_id=$1 AND client_id=$2"),
+        &[&user.id, &client_id]
+    ).await.map_err(internal_error)?;
+
+    let note_id = match synced {
+        Some(row) => row.get(0),
+        None => {
+            let attempt = client.transaction().await.map_err(internal_error)?;
+            let created = insert_note(&attempt, settings, user, note, None).await?;
+
+            // waits for a concurrent sync inserting the same client id, and skips if it commits
+            let claimed = attempt.execute(
+                &format!("INSERT INTO {SYNC_CLIENT_IDS_TABLE_NAME} (user_id, client_id, note_id) VALUES ($1, $2, $3) \
+                    ON CONFLICT (user_id, client_id) DO NOTHING"),
+                &[&user.id, &client_id, &created.id]
+            ).await.map_err(internal_error)?;
+
+            match claimed {
+                1 => {
+                    attempt.commit().await.map_err(internal_error)?;
+                    created.id
+                }
+                _ => {
+                    attempt.rollback().await.map_err(internal_error)?;
+                    client.query_one(
+                        &format!("SELECT note_id FROM {SYNC_CLIENT_IDS_TABLE_NAME} WHERE user_id=$1 AND client_id=$2"),
+                        &[&user.id, &client_id]
+                    ).await.map_err(internal_error)?
+                    .get(0)
+                }
+            }
+        }
+    };
+
+    Ok(SyncResult { client_id: Some(client_id), note_id: Some(note_id), ..SyncResult::new(index, SyncStatus::Applied) })
+}
+
+async fn lock_note<C: GenericClient>(client: &C, note_id: i32) -> Result<Note, (StatusCode, String)> {
+    let row = client.query_one(
+        &format!("SELECT {NOTE_COLUMNS} FROM {NOTES_TABLE_NAME} WHERE id=$1 FOR UPDATE"),
+        &[&note_id]
+    ).await.map_err(internal_error)?;
+
+    Ok(Note::from(&row))
+}
+
+async fn apply_update<C: GenericClient>(
+    client: &C,
+    settings: &Settings,
+    user: &User,
+    index: usize,
+    note_id: i32,
+    base_version: i32,
+    changes: UpdateNotePayload,
+) -> Result<SyncResult, (StatusCode, String)> {
+    let result = SyncResult { note_id: Some(note_id), ..SyncResult::new(index, SyncStatus::Applied) };
+
+    match ensure_note_access(client, note_id, user, Access::Editor).await {
+        Err((StatusCode::NOT_FOUND, _)) => {
+            return Ok(SyncResult { status: SyncStatus::Conflict, conflict: Some(SyncConflict::NoteDeleted), ..result });
+        }
+        access => access?,
+    };
+
+    let server = lock_note(client, note_id).await?;
+
+    if server.version == base_version {
+        let changes = NoteChanges {
+            title: changes.title,
+            text: changes.text,
+            format: changes.format,
+            tags: changes.tags.map_or(TagChange::Keep, TagChange::Replace),
+            ..Default::default()
+        };
+        write_note_changes(client, settings, user, note_id, changes, Some(vec![base_version])).await?;
+
+        return Ok(result);
+    }
+
+    let base = client.query_opt(
+        &format!("SELECT title, text FROM {NOTE_REVISIONS_TABLE_NAME} WHERE note_id=$1 AND version=$2"),
+        &[&note_id, &base_version]
+    ).await.map_err(internal_error)?;
+    let base: Option<(String, String)> = base.map(|row| (row.get(0), row.get(1)));
+
+    let mut merged = NoteChanges::default();
+    let mut fields: Vec<&'static str> = vec![];
+
+    if let Some(title) = changes.title {
+        match merge_field(title.trim(), &server.title, base.as_ref().map(|(title, _)| title.as_str())) {
+            FieldMerge::Server => {}
+            FieldMerge::Client => merged.title = Some(title),
+            FieldMerge::Conflict => fields.push("title"),
+        }
+    }
+    if let Some(text) = changes.text {
+        match merge_field(&text, &server.text, base.as_ref().map(|(_, text)| text.as_str())) {
+            FieldMerge::Server => {}
+            FieldMerge::Client => merged.text = Some(text),
+            FieldMerge::Conflict => fields.push("text"),
+        }
+    }
+
+    // revisions don't keep the format and tags, so any difference is a conflict
+    if changes.format.is_some_and(|format| format != server.format) {
+        fields.push("format");
+    }
+    if let Some(tags) = changes.tags {
+        let mut tags = normalize_tags(&tags)?;
+        let mut server_tags = server.tags.clone();
+        tags.sort();
+        server_tags.sort();
+
+        if tags != server_tags {
+            fields.push("tags");
+        }
+    }
+
+    if merged.title.is_some() || merged.text.is_some() {
+        write_note_changes(client, settings, user, note_id, merged, Some(vec![server.version])).await?;
+    }
+
+    Ok(match fields.is_empty() {
+        true => SyncResult { status: SyncStatus::Merged, ..result },
+        false => SyncResult { status: SyncStatus::Conflict, conflict: Some(SyncConflict::FieldsChanged), fields, ..result },
+    })
+}
+
+async fn apply_delete<C: GenericClient>(
+    client: &C,
+    user: &User,
+    index: usize,
+    note_id: i32,
+    base_version: i32,
+) -> Result<SyncResult, (StatusCode, String)> {
+    let result = SyncResult { note_id: Some(note_id), ..SyncResult::new(index, SyncStatus::Applied) };
+
+    // deleted on both sides
+    match ensure_note_access(client, note_id, user, Access::Viewer).await {
+        Err((StatusCode::NOT_FOUND, _)) => return Ok(result),
+        access => access?,
+    };
+
+    if lock_note(client, note_id).await?.version != base_version {
+        return Ok(SyncResult { status: SyncStatus::Conflict, conflict: Some(SyncConflict::NoteChanged), ..result });
+    }
+
+    trash_note(client, user, note_id, Some(vec![base_version])).await?;
+
+    Ok(result)
+}
@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+
+use crate::modules::notes::types::{CreateNotePayload, Note, UpdateNotePayload};
+
+/// A change a client made while offline. `base_version` is the version of the note the client
+/// last got from the server, the one it changed.
+#[derive(Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum SyncChange {
+  /// `client_id` is the client's own id for the note, a create already synced under it is
+  /// not applied again.
+  Create {
+    client_id: String,
+    #[serde(flatten)]
+    note: CreateNotePayload
+  },
+  Update {
+    id: i32,
+    base_version: i32,
+    #[serde(flatten)]
+    changes: UpdateNotePayload
+  },
+  Delete {
+    id: i32,
+    base_version: i32
+  }
+}
+
+#[derive(Deserialize)]
+pub struct SyncPayload {
+  /// The token of the previous sync, none for the first one.
+  pub token: Option<String>,
+  #[serde(default)]
+  pub changes: Vec<SyncChange>
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncStatus {
+  /// Written as sent.
+  Applied,
+  /// The note changed on the server too, in other fields: both changes were kept.
+  Merged,
+  /// The change lost, at least in part, to one made on the server, see `SyncConflict`.
+  Conflict,
+  /// The change can't be made at all, e.g. a note deleted by someone it is only shared with.
+  Rejected
+}
+
+/// How the server settled a conflict. The server side always wins, the client gets the
+/// note as it is on the server and may apply its change again on top.
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncConflict {
+  /// The fields in `fields` were changed differently on both sides and keep the server's
+  /// value, the other fields sent were merged.
+  FieldsChanged,
+  /// The note was updated by the client but deleted on the server, it stays deleted.
+  NoteDeleted,
+  /// The note was deleted by the client but changed on the server, it is kept.
+  NoteChanged
+}
+
+#[derive(Serialize)]
+pub struct SyncResult {
+  /// Position of the change in the request, starting at 0.
+  pub index: usize,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub client_id: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub note_id: Option<i32>,
+  pub status: SyncStatus,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub conflict: Option<SyncConflict>,
+  #[serde(skip_serializing_if = "Vec::is_empty")]
+  pub fields: Vec<&'static str>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub error: Option<String>
+}
+
+impl SyncResult {
+  pub fn new(index: usize, status: SyncStatus) -> Self {
+    SyncResult { index, client_id: None, note_id: None, status, conflict: None, fields: vec![], error: None }
+  }
+}
+
+/// The server changes since the token sent, the changes sent having been applied first.
+/// `notes` are created or changed, `deleted` the ids of the notes deleted or no longer
+/// visible. After `reset`, the client should drop the notes it has that aren't sent until
+/// `more` is `false`. While `more` is `true`, the client syncs again with `token` right away.
+#[derive(Serialize)]
+pub struct SyncResponse {
+  pub token: String,
+  pub reset: bool,
+  pub more: bool,
+  pub notes: Vec<Note>,
+  pub deleted: Vec<i32>,
+  pub results: Vec<SyncResult>
+}
@@ -0,0 +1,270 @@
+use axum::{
+    Extension,
+    extract::{State, Path},
+    http::{StatusCode, HeaderMap},
+    Json,
+};
+
+use tokio_postgres::{error::SqlState, GenericClient};
+
+use crate::{
+    types::{internal_error, AppState},
+    modules::common::query::Condition,
+    modules::notes::{api::apply_note_update, types::{Note, NoteChanges, TagChange}},
+    modules::tags::types::*,
+    modules::users::types::User,
+    NOTES_TABLE_NAME, TAGS_TABLE_NAME, NOTE_TAGS_TABLE_NAME
+};
+
+const MAX_TAG_LENGTH: usize = 50;
+
+/// Matches notes tagged with any/all of `names`.
+pub fn tag_condition(mode: TagMode, names: Vec<String>) -> Condition {
+    let operator = match mode {
+        TagMode::Any => "&&",
+        TagMode::All => "@>",
+    };
+
+    Condition::compare(
+        "ARRAY(SELECT t.name::text FROM note_tags nt JOIN tags t ON t.id = nt.tag_id WHERE nt.note_id = notes.id)",
+        operator,
+        names
+    )
+}
+
+/// Trims `names` and drops duplicates, answering `422 Unprocessable Entity` for an empty or too long name.
+pub fn normalize_tags(names: &[String]) -> Result<Vec<String>, (StatusCode, String)> {
+    let mut normalized: Vec<String> = vec![];
+
+    for name in names {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_TAG_LENGTH {
+            return Err((
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("Tag names must be between 1 and {MAX_TAG_LENGTH} characters")
+            ));
+        }
+        if !normalized.iter().any(|existing| existing == name) {
+            normalized.push(name.to_string());
+        }
+    }
+
+    Ok(normalized)
+}
+
+/// Applies `change` to the tags of `note_id`, creating any of `user_id`'s tags that don't exist yet.
+pub async fn apply_tag_change<C: GenericClient>(
+    client: &C,
+    note_id: i32,
+    user_id: i32,
+    change: &TagChange,
+) -> Result<(), (StatusCode, String)> {
+    match change {
+        TagChange::Keep => Ok(()),
+        TagChange::Attach(names) => attach_tags(client, note_id, user_id, &normalize_tags(names)?).await,
+        TagChange::Detach(names) => {
+            let names = normalize_tags(names)?;
+            client.execute(
+                &format!("DELETE FROM {NOTE_TAGS_TABLE_NAME} WHERE note_id=$1 AND tag_id IN ( \
+                    SELECT id FROM {TAGS_TABLE_NAME} WHERE user_id=$2 AND name = ANY($3))"),
+                &[&note_id, &user_id, &names]
+            ).await.map_err(internal_error)?;
+            Ok(())
+        }
+        TagChange::Replace(names) => {
+            let names = normalize_tags(names)?;
+            client.execute(
+                &format!("DELETE FROM {NOTE_TAGS_TABLE_NAME} WHERE note_id=$1 AND tag_id NOT IN ( \
+                    SELECT id FROM {TAGS_TABLE_NAME} WHERE user_id=$2 AND name = ANY($3))"),
+                &[&note_id, &user_id, &names]
+            ).await.map_err(internal_error)?;
+            attach_tags(client, note_id, user_id, &names).await
+        }
+    }
+}
+
+async fn attach_tags<C: GenericClient>(
+    client: &C,
+    note_id: i32,
+    user_id: i32,
+    names: &[String],
+) -> Result<(), (StatusCode, String)> {
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    client.execute(
+        &format!("INSERT INTO {TAGS_TABLE_NAME} (user_id, name) SELECT $1, unnest($2::varchar[]) \
+            ON CONFLICT (user_id, name) DO NOTHING"),
+        &[&user_id, &names]
+    ).await.map_err(internal_error)?;
+
+    client.execute(
+        &format!("INSERT INTO {NOTE_TAGS_TABLE_NAME} (note_id, tag_id) \
+            SELECT $1, id FROM {TAGS_TABLE_NAME} WHERE user_id=$2 AND name = ANY($3) \
+            ON CONFLICT DO NOTHING"),
+        &[&note_id, &user_id, &names]
+    ).await.map_err(internal_error)?;
+
+    Ok(())
+}
+
+pub async fn get_tags(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+) -> Result<Json<Vec<Tag>>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let rows = conn.query(
+        &format!("{} WHERE t.user_id=$1 GROUP BY t.id ORDER BY t.name", tags_with_counts()),
+        &[&user.id]
+    ).await.map_err(internal_error)?;
+
+    let tags = rows.iter().map(|row| Tag {
+        id: row.get(0),
+        name: row.get(1),
+        note_count: row.get(2)
+    }).collect();
+
+    Ok(Json(tags))
+}
+
+pub async fn rename_tag(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(tag_id): Path<i32>,
+    Json(body): Json<RenameTagPayload>,
+) -> Result<Json<Tag>, (StatusCode, String)> {
+    let name = normalize_tags(&[body.name])?.remove(0);
+
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    let renamed = tx.execute(
+        &format!("UPDATE {TAGS_TABLE_NAME} SET name=$1 WHERE id=$2 AND user_id=$3"),
+        &[&name, &tag_id, &user.id]
+    ).await.map_err(|e| {
+        if e.code() == Some(&SqlState::UNIQUE_VIOLATION) {
+            (StatusCode::CONFLICT, format!("Tag \"{name}\" already exists, merge the tags instead"))
+        } else {
+            internal_error(e)
+        }
+    })?;
+
+    if renamed == 0 {
+        return Err(tag_not_found());
+    }
+
+    touch_tagged_notes(&tx, tag_id).await?;
+    let tag = find_tag(&tx, tag_id, &user).await?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(tag))
+}
+
+/// Moves every note tagged with `tag_id` over to `body.into` and deletes `tag_id`.
+pub async fn merge_tag(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(tag_id): Path<i32>,
+    Json(body): Json<MergeTagPayload>,
+) -> Result<Json<Tag>, (StatusCode, String)> {
+    if tag_id == body.into {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, "Cannot merge a tag into itself".to_string()));
+    }
+
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    find_tag(&tx, tag_id, &user).await?;
+    find_tag(&tx, body.into, &user).await?;
+    touch_tagged_notes(&tx, tag_id).await?;
+
+    tx.execute(
+        &format!("INSERT INTO {NOTE_TAGS_TABLE_NAME} (note_id, tag_id) \
+            SELECT note_id, $2 FROM {NOTE_TAGS_TABLE_NAME} WHERE tag_id=$1 ON CONFLICT DO NOTHING"),
+        &[&tag_id, &body.into]
+    ).await.map_err(internal_error)?;
+
+    tx.execute(&format!("DELETE FROM {TAGS_TABLE_NAME} WHERE id=$1"), &[&tag_id])
+        .await.map_err(internal_error)?;
+
+    let tag = find_tag(&tx, body.into, &user).await?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(tag))
+}
+
+pub async fn delete_tag(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(tag_id): Path<i32>,
+) -> Result<Json<Tag>, (StatusCode, String)> {
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    let tag = find_tag(&tx, tag_id, &user).await?;
+    touch_tagged_notes(&tx, tag_id).await?;
+
+    tx.execute(&format!("DELETE FROM {TAGS_TABLE_NAME} WHERE id=$1"), &[&tag_id])
+        .await.map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(tag))
+}
+
+pub async fn attach_note_tags(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+    headers: HeaderMap,
+    Json(body): Json<TagsPayload>,
+) -> Result<Json<Note>, (StatusCode, String)> {
+    let changes = NoteChanges { tags: TagChange::Attach(body.tags), ..Default::default() };
+    apply_note_update(&state, &user, note_id, changes, &headers).await.map(Json)
+}
+
+pub async fn detach_note_tag(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path((note_id, name)): Path<(i32, String)>,
+    headers: HeaderMap,
+) -> Result<Json<Note>, (StatusCode, String)> {
+    let changes = NoteChanges { tags: TagChange::Detach(vec![name]), ..Default::default() };
+    apply_note_update(&state, &user, note_id, changes, &headers).await.map(Json)
+}
+
+fn tags_with_counts() -> String {
+    format!("SELECT t.id, t.name, count(n.id) FROM {TAGS_TABLE_NAME} t \
+        LEFT JOIN {NOTE_TAGS_TABLE_NAME} nt ON nt.tag_id = t.id \
+        LEFT JOIN {NOTES_TABLE_NAME} n ON n.id = nt.note_id AND n.deleted_at IS NULL")
+}
+
+async fn find_tag<C: GenericClient>(client: &C, tag_id: i32, user: &User) -> Result<Tag, (StatusCode, String)> {
+    let row = client.query_opt(
+        &format!("{} WHERE t.id=$1 AND t.user_id=$2 GROUP BY t.id", tags_with_counts()),
+        &[&tag_id, &user.id]
+    ).await.map_err(internal_error)?
+    .ok_or_else(tag_not_found)?;
+
+    Ok(Tag {
+        id: row.get(0),
+        name: row.get(1),
+        note_count: row.get(2)
+    })
+}
+
+/// Bumps the version of every note carrying `tag_id`, since their representation is about to change.
+async fn touch_tagged_notes<C: GenericClient>(client: &C, tag_id: i32) -> Result<(), (StatusCode, String)> {
+    client.execute(
+        &format!("UPDATE {NOTES_TABLE_NAME} SET version=version+1 \
+            WHERE id IN (SELECT note_id FROM {NOTE_TAGS_TABLE_NAME} WHERE tag_id=$1)"),
+        &[&tag_id]
+    ).await.map_err(internal_error)?;
+
+    Ok(())
+}
+
+fn tag_not_found() -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, "Tag not found".to_string())
+}
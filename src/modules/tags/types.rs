@@ -0,0 +1,40 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize, Serialize)]
+pub struct Tag {
+  pub id: i32,
+  pub name: String,
+  pub note_count: i64
+}
+
+#[derive(Deserialize)]
+pub struct RenameTagPayload {
+  pub name: String
+}
+
+#[derive(Deserialize)]
+pub struct MergeTagPayload {
+  pub into: i32
+}
+
+#[derive(Deserialize)]
+pub struct TagsPayload {
+  pub tags: Vec<String>
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMode {
+  #[default]
+  Any,
+  All
+}
+
+/// `?tag=work&tag=urgent&tag_mode=all`
+#[derive(Deserialize)]
+pub struct TagFilter {
+  #[serde(default)]
+  pub tag: Vec<String>,
+  #[serde(default)]
+  pub tag_mode: TagMode
+}
@@ -0,0 +1,224 @@
+use axum::{
+    Extension,
+    extract::{State, Query, Path},
+    http::StatusCode,
+    Json,
+};
+
+use chrono::Utc;
+use tokio_postgres::{error::SqlState, types::ToSql, GenericClient};
+
+use crate::{
+    types::{internal_error, AppState, Roles, Settings},
+    modules::notes::{api::{insert_note, validate_note}, types::{CreateNotePayload, Note}},
+    modules::tags::api::normalize_tags,
+    modules::templates::{placeholders::{builtin_values, fill}, types::*},
+    modules::users::types::User,
+    TEMPLATES_TABLE_NAME
+};
+
+const MAX_NAME_LENGTH: usize = 100;
+
+fn template_not_found() -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, "Template not found".to_string())
+}
+
+fn name_conflict(name: &str) -> impl FnOnce(tokio_postgres::Error) -> (StatusCode, String) + '_ {
+    move |e| {
+        if e.code() == Some(&SqlState::UNIQUE_VIOLATION) {
+            (StatusCode::CONFLICT, format!("A template named \"{name}\" already exists"))
+        } else {
+            internal_error(e)
+        }
+    }
+}
+
+/// Answers `403 Forbidden` unless `user` may manage global templates.
+fn ensure_moderator(user: &User) -> Result<(), (StatusCode, String)> {
+    if (user.role.clone() as i16) < (Roles::Moderator as i16) {
+        return Err((StatusCode::FORBIDDEN, "Only moderators can manage global templates".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Trims `name`, answering `422 Unprocessable Entity` for one that is empty or too long.
+fn validate_name(name: &str) -> Result<&str, (StatusCode, String)> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err((StatusCode::UNPROCESSABLE_ENTITY, format!("Template names must be between 1 and {MAX_NAME_LENGTH} characters")));
+    }
+
+    Ok(name)
+}
+
+/// Templates are held to the limits of the notes they make, placeholders included.
+fn validate_content(settings: &Settings, title: Option<&str>, text: Option<&str>) -> Result<(), (StatusCode, String)> {
+    validate_note(settings, title.map(str::trim), text)
+}
+
+/// A template `user` can use: one of their own or a global one.
+async fn find_template<C: GenericClient>(client: &C, template_id: i32, user: &User) -> Result<Template, (StatusCode, String)> {
+    let row = client.query_opt(
+        &format!("SELECT {TEMPLATE_COLUMNS} FROM {TEMPLATES_TABLE_NAME} WHERE id=$1 AND (user_id=$2 OR user_id IS NULL)"),
+        &[&template_id, &user.id]
+    ).await.map_err(internal_error)?;
+
+    row.map(|row| Template::from(&row)).ok_or_else(template_not_found)
+}
+
+/// Like `find_template`, answering `403 Forbidden` for a global template unless `user` is a moderator.
+async fn find_template_to_change<C: GenericClient>(client: &C, template_id: i32, user: &User) -> Result<Template, (StatusCode, String)> {
+    let template = find_template(client, template_id, user).await?;
+    if template.scope == TemplateScope::Global {
+        ensure_moderator(user)?;
+    }
+
+    Ok(template)
+}
+
+/// The templates the current user can use, their own first, each by name.
+pub async fn get_templates(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    filter: Query<TemplateFilter>,
+) -> Result<Json<Vec<Template>>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let (condition, params): (&str, Vec<&(dyn ToSql + Sync)>) = match filter.scope {
+        None => ("user_id=$1 OR user_id IS NULL", vec![&user.id]),
+        Some(TemplateScopeFilter::Personal) => ("user_id=$1", vec![&user.id]),
+        Some(TemplateScopeFilter::Global) => ("user_id IS NULL", vec![]),
+    };
+
+    let rows = conn.query(
+        &format!("SELECT {TEMPLATE_COLUMNS} FROM {TEMPLATES_TABLE_NAME} WHERE {condition} \
+            ORDER BY user_id IS NULL, lower(name), id"),
+        &params
+    ).await.map_err(internal_error)?;
+
+    Ok(Json(rows.iter().map(Template::from).collect()))
+}
+
+pub async fn get_template(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(template_id): Path<i32>,
+) -> Result<Json<Template>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    Ok(Json(find_template(&*conn, template_id, &user).await?))
+}
+
+/// Creates a template of the current user, or a global one for moderators.
+pub async fn create_template(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Json(body): Json<CreateTemplatePayload>,
+) -> Result<Json<Template>, (StatusCode, String)> {
+    if body.global {
+        ensure_moderator(&user)?;
+    }
+
+    let name = validate_name(&body.name)?;
+    validate_content(&state.settings, Some(&body.title), Some(&body.text))?;
+    let tags = normalize_tags(&body.tags)?;
+
+    let owner_id = (!body.global).then_some(user.id);
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let row = conn.query_one(
+        &format!("INSERT INTO {TEMPLATES_TABLE_NAME} (user_id, name, title, text, format, tags) \
+            VALUES ($1, $2, $3, $4, $5, $6) RETURNING {TEMPLATE_COLUMNS}"),
+        &[&owner_id, &name, &body.title.trim(), &body.text, &(body.format as i16), &tags]
+    ).await.map_err(name_conflict(name))?;
+
+    Ok(Json(Template::from(&row)))
+}
+
+pub async fn update_template(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(template_id): Path<i32>,
+    Json(body): Json<UpdateTemplatePayload>,
+) -> Result<Json<Template>, (StatusCode, String)> {
+    let name = body.name.as_deref().map(validate_name).transpose()?;
+    let title = body.title.as_deref().map(str::trim);
+    validate_content(&state.settings, title, body.text.as_deref())?;
+    let tags = body.tags.as_deref().map(normalize_tags).transpose()?;
+    let format = body.format.map(|format| format as i16);
+
+    let conn = state.pool.get().await.map_err(internal_error)?;
+    find_template_to_change(&*conn, template_id, &user).await?;
+
+    let row = conn.query_one(
+        &format!("UPDATE {TEMPLATES_TABLE_NAME} SET name=COALESCE($2, name), title=COALESCE($3, title), \
+            text=COALESCE($4, text), format=COALESCE($5, format), tags=COALESCE($6, tags), updated_at=now() \
+            WHERE id=$1 RETURNING {TEMPLATE_COLUMNS}"),
+        &[&template_id, &name, &title, &body.text, &format, &tags]
+    ).await.map_err(name_conflict(name.unwrap_or_default()))?;
+
+    Ok(Json(Template::from(&row)))
+}
+
+pub async fn delete_template(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(template_id): Path<i32>,
+) -> Result<Json<Template>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+    let template = find_template_to_change(&*conn, template_id, &user).await?;
+
+    conn.execute(
+        &format!("DELETE FROM {TEMPLATES_TABLE_NAME} WHERE id=$1"),
+        &[&template_id]
+    ).await.map_err(internal_error)?;
+
+    Ok(Json(template))
+}
+
+/// Creates a note of the current user from a template, filling in its placeholders with the
+/// given variables, the built-in ones or their defaults. Answers `422 Unprocessable Entity`
+/// naming the placeholders left without a value.
+pub async fn create_note_from_template(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(template_id): Path<i32>,
+    Json(body): Json<ApplyTemplatePayload>,
+) -> Result<Json<Note>, (StatusCode, String)> {
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    let template = find_template(&tx, template_id, &user).await?;
+    let builtins = builtin_values(Utc::now(), &user.username);
+
+    let title = body.title.as_deref().unwrap_or(&template.title);
+    let filled = (fill(title, &body.variables, &builtins), fill(&template.text, &body.variables, &builtins));
+
+    let (title, text) = match filled {
+        (Ok(title), Ok(text)) => (title, text),
+        (title, text) => {
+            let mut missing = title.err().unwrap_or_default();
+            for name in text.err().unwrap_or_default() {
+                if !missing.contains(&name) {
+                    missing.push(name);
+                }
+            }
+
+            return Err((StatusCode::UNPROCESSABLE_ENTITY, format!("Missing values for template variables: {}", missing.join(", "))));
+        }
+    };
+
+    let payload = CreateNotePayload {
+        title,
+        text,
+        format: template.format,
+        tags: template.tags,
+        notebook_id: body.notebook_id
+    };
+
+    let created_note = insert_note(&tx, &state.settings, &user, payload, None).await?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(created_note))
+}
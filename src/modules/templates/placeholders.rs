@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+
+/// Placeholders the server fills in itself, from the current time in UTC and the user
+/// creating the note.
+pub const BUILTIN_VARIABLES: &[&str] = &["date", "time", "datetime", "weekday", "username"];
+
+/// A `{{name}}` or `{{name|default}}` in the text of a template. Anything else between
+/// braces, e.g. `{{ }}` or `{{a b}}`, is kept as written.
+pub struct Placeholder<'a> {
+    pub name: &'a str,
+    pub default: Option<&'a str>,
+}
+
+enum Part<'a> {
+    Literal(&'a str),
+    Placeholder(Placeholder<'a>),
+}
+
+/// Splits `text` into literal parts and placeholders, in order.
+fn parse(text: &str) -> Vec<Part<'_>> {
+    let mut parts = vec![];
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start + 2..].find("}}") else {
+            break;
+        };
+
+        let inner = &rest[start + 2..start + 2 + length];
+        let (name, default) = match inner.split_once('|') {
+            Some((name, default)) => (name.trim(), Some(default)),
+            None => (inner.trim(), None),
+        };
+
+        let end = start + 2 + length + 2;
+        if is_variable_name(name) {
+            parts.push(Part::Literal(&rest[..start]));
+            parts.push(Part::Placeholder(Placeholder { name, default }));
+        } else {
+            parts.push(Part::Literal(&rest[..end]));
+        }
+        rest = &rest[end..];
+    }
+
+    parts.push(Part::Literal(rest));
+    parts
+}
+
+fn is_variable_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+/// The placeholders of each of `texts`, each name once with the first default given for it.
+pub fn placeholders<'a>(texts: &[&'a str]) -> Vec<Placeholder<'a>> {
+    let mut found: Vec<Placeholder> = vec![];
+
+    for part in texts.iter().flat_map(|text| parse(text)) {
+        let Part::Placeholder(placeholder) = part else {
+            continue;
+        };
+        match found.iter_mut().find(|existing| existing.name == placeholder.name) {
+            Some(existing) => existing.default = existing.default.or(placeholder.default),
+            None => found.push(placeholder),
+        }
+    }
+
+    found
+}
+
+/// The values of the built-in variables at `now`, for `username`.
+pub fn builtin_values(now: DateTime<Utc>, username: &str) -> HashMap<String, String> {
+    HashMap::from([
+        ("date".to_string(), now.format("%Y-%m-%d").to_string()),
+        ("time".to_string(), now.format("%H:%M").to_string()),
+        ("datetime".to_string(), now.format("%Y-%m-%d %H:%M").to_string()),
+        ("weekday".to_string(), now.format("%A").to_string()),
+        ("username".to_string(), username.to_string()),
+    ])
+}
+
+/// Replaces the placeholders of `text` with their value, from `values` first, then
+/// `builtins`, then their default. Placeholders without any are returned as `Err`, by name.
+pub fn fill(text: &str, values: &HashMap<String, String>, builtins: &HashMap<String, String>) -> Result<String, Vec<String>> {
+    let mut filled = String::with_capacity(text.len());
+    let mut missing: Vec<String> = vec![];
+
+    for part in parse(text) {
+        match part {
+            Part::Literal(literal) => filled.push_str(literal),
+            Part::Placeholder(Placeholder { name, default }) => {
+                match values.get(name).or_else(|| builtins.get(name)).map(String::as_str).or(default) {
+                    Some(value) => filled.push_str(value),
+                    None if missing.iter().any(|existing| existing == name) => {}
+                    None => missing.push(name.to_string()),
+                }
+            }
+        }
+    }
+
+    match missing.is_empty() {
+        true => Ok(filled),
+        false => Err(missing),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    fn names_and_defaults<'a>(texts: &[&'a str]) -> Vec<(&'a str, Option<&'a str>)> {
+        placeholders(texts).into_iter().map(|placeholder| (placeholder.name, placeholder.default)).collect()
+    }
+
+    #[test]
+    fn fills_placeholders_from_values_then_builtins_then_defaults() {
+        let builtins = values(&[("username", "alice"), ("date", "2026-10-19")]);
+
+        assert_eq!(fill("Hi {{ name }}!", &values(&[("name", "Bob")]), &builtins), Ok("Hi Bob!".to_string()));
+        assert_eq!(fill("{{username}} on {{date}}", &values(&[]), &builtins), Ok("alice on 2026-10-19".to_string()));
+        assert_eq!(fill("{{username}}", &values(&[("username", "bob")]), &builtins), Ok("bob".to_string()));
+        assert_eq!(fill("{{greeting|Hello there}}, {{name}}", &values(&[("name", "Bob")]), &builtins), Ok("Hello there, Bob".to_string()));
+        assert_eq!(fill("{{greeting|Hello}}", &values(&[("greeting", "Hey")]), &builtins), Ok("Hey".to_string()));
+    }
+
+    #[test]
+    fn keeps_defaults_as_written() {
+        let none = values(&[]);
+
+        assert_eq!(fill("[{{a| spaced }}]", &none, &none), Ok("[ spaced ]".to_string()));
+        assert_eq!(fill("[{{a|}}]", &none, &none), Ok("[]".to_string()));
+        assert_eq!(fill("{{a|b|c}}", &none, &none), Ok("b|c".to_string()));
+    }
+
+    #[test]
+    fn reports_each_missing_name_once_in_order() {
+        let none = values(&[]);
+
+        assert_eq!(fill("{{b}} {{a}} {{b}} {{c|default}}", &none, &none), Err(vec!["b".to_string(), "a".to_string()]));
+    }
+
+    #[test]
+    fn keeps_what_is_not_a_placeholder() {
+        let name = values(&[("name", "Bob")]);
+
+        for text in ["{{ }}", "{{}}", "{{a b}}", "{{name", "name}}", "{name}", "{{|default}}"] {
+            assert_eq!(fill(text, &name, &name), Ok(text.to_string()), "{text}");
+        }
+
+        // the first `}}` closes a placeholder, so nested braces never name a variable
+        assert_eq!(fill("{{{{name}}}}", &name, &name), Ok("{{{{name}}}}".to_string()));
+        assert_eq!(fill("{{{name}}}", &name, &name), Ok("{{{name}}}".to_string()));
+
+        // an unterminated placeholder leaves the rest as it is, after filling those before it
+        assert_eq!(fill("{{name}} and {{name", &name, &name), Ok("Bob and {{name".to_string()));
+    }
+
+    #[test]
+    fn lists_each_name_once_with_its_first_default() {
+        assert_eq!(
+            names_and_defaults(&["{{a}} {{b|x}} {{a|first}}", "{{a|second}} {{c}} {{b|y}}"]),
+            vec![("a", Some("first")), ("b", Some("x")), ("c", None)]
+        );
+        assert_eq!(names_and_defaults(&["no {{ }} placeholders", "{{a b}}"]), vec![]);
+    }
+
+    #[test]
+    fn builtin_values_use_the_given_time() {
+        let now = DateTime::parse_from_rfc3339("2026-10-19T08:05:00Z").unwrap().with_timezone(&Utc);
+        let builtins = builtin_values(now, "alice");
+
+        assert_eq!(builtins.len(), BUILTIN_VARIABLES.len());
+        assert_eq!(
+            fill("{{date}} {{time}} {{weekday}} {{username}}|{{datetime}}", &values(&[]), &builtins),
+            Ok("2026-10-19 08:05 Monday alice|2026-10-19 08:05".to_string())
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use crate::modules::{notes::types::NoteFormat, templates::placeholders::{placeholders, BUILTIN_VARIABLES}};
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TemplateScope {
+  /// Owned by the current user.
+  Personal,
+  /// Offered to every user, managed by moderators.
+  Global
+}
+
+/// A `{{name}}` or `{{name|default}}` placeholder of a template. Built-in ones are filled
+/// in by the server unless a value is given.
+#[derive(Serialize)]
+pub struct TemplateVariable {
+  pub name: String,
+  pub default: Option<String>,
+  pub builtin: bool
+}
+
+#[derive(Serialize)]
+pub struct Template {
+  pub id: i32,
+  pub scope: TemplateScope,
+  pub name: String,
+  pub title: String,
+  pub text: String,
+  pub format: NoteFormat,
+  pub tags: Vec<String>,
+  pub variables: Vec<TemplateVariable>,
+  pub created_at: DateTime<Utc>,
+  pub updated_at: DateTime<Utc>
+}
+
+/// Columns expected by `Template::from(&Row)`, in order. Variables are read from the title and text.
+pub const TEMPLATE_COLUMNS: &str = "id, user_id IS NULL, name, title, text, format, tags, created_at, updated_at";
+
+impl From<&Row> for Template {
+  fn from(row: &Row) -> Template {
+    let (title, text): (String, String) = (row.get(3), row.get(4));
+    let variables = placeholders(&[&title, &text])
+      .into_iter()
+      .map(|placeholder| TemplateVariable {
+        name: placeholder.name.to_string(),
+        default: placeholder.default.map(str::to_string),
+        builtin: BUILTIN_VARIABLES.contains(&placeholder.name)
+      })
+      .collect();
+
+    Template {
+      id: row.get(0),
+      scope: if row.get(1) { TemplateScope::Global } else { TemplateScope::Personal },
+      name: row.get(2),
+      format: row.get::<usize, i16>(5).into(),
+      tags: row.get(6),
+      title,
+      text,
+      variables,
+      created_at: row.get(7),
+      updated_at: row.get(8)
+    }
+  }
+}
+
+#[derive(Deserialize)]
+pub struct TemplateFilter {
+  pub scope: Option<TemplateScopeFilter>
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum TemplateScopeFilter {
+  Personal,
+  Global
+}
+
+#[derive(Deserialize)]
+pub struct CreateTemplatePayload {
+  pub name: String,
+  #[serde(default)]
+  pub title: String,
+  pub text: String,
+  #[serde(default)]
+  pub format: NoteFormat,
+  #[serde(default)]
+  pub tags: Vec<String>,
+  /// Creates a global template, moderators only.
+  #[serde(default)]
+  pub global: bool
+}
+
+/// Fields left out are kept as they are; `tags` replaces the whole set.
+#[derive(Deserialize)]
+pub struct UpdateTemplatePayload {
+  pub name: Option<String>,
+  pub title: Option<String>,
+  pub text: Option<String>,
+  pub format: Option<NoteFormat>,
+  pub tags: Option<Vec<String>>
+}
+
+/// Creates a note from a template. `title` replaces the template's own title, placeholders
+/// included.
+#[derive(Deserialize)]
+pub struct ApplyTemplatePayload {
+  #[serde(default)]
+  pub variables: HashMap<String, String>,
+  pub title: Option<String>,
+  pub notebook_id: Option<i32>
+}
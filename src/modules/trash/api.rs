@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+use axum::{
+    Extension,
+    extract::{State, Query, Path},
+    http::{StatusCode, HeaderValue, Response},
+    Json, response::IntoResponse,
+    body::Body
+};
+
+use tokio_postgres::types::ToSql;
+
+use crate::{
+    types::{internal_error, AppState, Pagination},
+    modules::notes::{api::ensure_unique_title, types::{Note, NOTE_COLUMNS}},
+    modules::references::api::resolve_dangling_references,
+    modules::trash::types::*,
+    modules::users::types::User,
+    NOTES_TABLE_NAME
+};
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+pub async fn get_trash(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    pagination: Query<Pagination>,
+) -> Result<Response<Body>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let query_notes = format!("SELECT {TRASHED_NOTE_COLUMNS} FROM {NOTES_TABLE_NAME} \
+        WHERE user_id=$1 AND deleted_at IS NOT NULL ORDER BY deleted_at DESC, id LIMIT $2 OFFSET $3");
+    let query_count = format!("SELECT count(*) FROM {NOTES_TABLE_NAME} WHERE user_id=$1 AND deleted_at IS NOT NULL");
+
+    let (limit, offset) = (pagination.limit(&state.settings)?, pagination.offset());
+    let params_notes: Vec<&(dyn ToSql + Sync)> = vec![&user.id, &limit, &offset];
+    let params_count: Vec<&(dyn ToSql + Sync)> = vec![&user.id];
+
+    let (rows, row_count) = tokio::try_join!(
+        conn.query(&query_notes, &params_notes),
+        conn.query_one(&query_count, &params_count)
+    ).map_err(internal_error)?;
+
+    let notes: Vec<TrashedNote> = rows.iter().map(TrashedNote::from).collect();
+
+    let count: i64 = row_count.get(0);
+    let header_count_value = HeaderValue::from_str(&count.to_string()).map_err(internal_error)?;
+
+    let mut response = Json(notes).into_response();
+    response.headers_mut().insert("x-total-count", header_count_value);
+
+    Ok(response)
+}
+
+pub async fn restore_note(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+) -> Result<Json<Note>, (StatusCode, String)> {
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    let row = tx.query_opt(
+        &format!("UPDATE {NOTES_TABLE_NAME} SET deleted_at=NULL \
+            WHERE id=$1 AND user_id=$2 AND deleted_at IS NOT NULL RETURNING {NOTE_COLUMNS}"),
+        &[&note_id, &user.id]
+    ).await.map_err(internal_error)?
+    .ok_or_else(not_in_trash)?;
+
+    // another note may have taken the title while this one was in the trash
+    let note = Note::from(&row);
+    ensure_unique_title(&tx, user.id, Some(note.id), &note.title).await?;
+    resolve_dangling_references(&tx, note.id, user.id, &note.title).await?;
+    tx.commit().await.map_err(internal_error)?;
+
+    Ok(Json(note))
+}
+
+pub async fn delete_note_permanently(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    Path(note_id): Path<i32>,
+) -> Result<Json<TrashedNote>, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    let row = conn.query_opt(
+        &format!("DELETE FROM {NOTES_TABLE_NAME} \
+            WHERE id=$1 AND user_id=$2 AND deleted_at IS NOT NULL RETURNING {TRASHED_NOTE_COLUMNS}"),
+        &[&note_id, &user.id]
+    ).await.map_err(internal_error)?
+    .ok_or_else(not_in_trash)?;
+
+    Ok(Json(TrashedNote::from(&row)))
+}
+
+/// Permanently deletes notes that have been in the trash longer than the configured retention period.
+pub async fn purge_trash(state: &AppState) -> Result<u64, (StatusCode, String)> {
+    let conn = state.pool.get().await.map_err(internal_error)?;
+
+    conn.execute(
+        &format!("DELETE FROM {NOTES_TABLE_NAME} \
+            WHERE deleted_at IS NOT NULL AND deleted_at < now() - make_interval(days => $1)"),
+        &[&state.settings.trash_retention_days]
+    ).await.map_err(internal_error)
+}
+
+/// Runs `purge_trash` forever, once per `PURGE_INTERVAL`. Meant to be spawned on startup.
+pub async fn purge_trash_periodically(state: AppState) {
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        match purge_trash(&state).await {
+            Ok(purged) => tracing::debug!("purged {} notes from trash", purged),
+            Err((_, e)) => tracing::error!("purging trash failed: {}", e),
+        }
+    }
+}
+
+fn not_in_trash() -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, "Note not found in trash".to_string())
+}
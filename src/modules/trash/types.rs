@@ -0,0 +1,27 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+#[derive(Deserialize, Serialize)]
+pub struct TrashedNote {
+  pub id: i32,
+  pub title: String,
+  pub text: String,
+  pub version: i32,
+  pub deleted_at: DateTime<Utc>
+}
+
+/// Columns expected by `TrashedNote::from(&Row)`, in order.
+pub const TRASHED_NOTE_COLUMNS: &str = "id, title, text, version, deleted_at";
+
+impl From<&Row> for TrashedNote {
+  fn from(row: &Row) -> TrashedNote {
+    TrashedNote {
+      id: row.get(0),
+      title: row.get(1),
+      text: row.get(2),
+      version: row.get(3),
+      deleted_at: row.get(4)
+    }
+  }
+}
@@ -1,16 +1,17 @@
-use std::usize;
-
 use axum::{
     Extension,
-    extract::{State, Query},
-    http::{StatusCode, HeaderValue, Response},
+    extract::{State, Query, OriginalUri},
+    http::{StatusCode, HeaderValue, Response, header},
     Json,
     response::IntoResponse,
     body::Body,
 };
 
-use tokio_postgres::types::ToSql;
-use crate::modules::common::{Search, SqlParams};
+use tokio_postgres::Transaction;
+use crate::modules::common::{Search, ListQuery, SortField, escape_like, parse_list, parse_sort};
+use crate::modules::common::pagination::{link_header, KeyKind, SortKey};
+use crate::modules::common::query::{Condition, QueryBuilder, Sql};
+use crate::modules::common::fields::{FieldsQuery, SelectedRow};
 use crate::types::{internal_error, AppState, Pagination};
 
 use crate::modules::users::types::*;
@@ -19,88 +20,139 @@ use crate::USER_TABLE_NAME;
 #[derive(serde::Deserialize)]
 pub struct Role {
     pub role: Option<i16>,
+    /// Comma separated list of roles, e.g. `role_in=1,2`.
+    pub role_in: Option<String>,
 }
 
+const USER_SORT_FIELDS: &[SortField] = &[
+    ("id", "id", KeyKind::Int),
+    ("username", "coalesce(username, '')", KeyKind::Text),
+    ("role", "role", KeyKind::Int),
+    ("created_at", "created_at", KeyKind::Time),
+];
+
+/// Lists users for logged in users only, as `include=` reveals how many notes each one keeps.
 pub async fn get_users(
     pagination: Query<Pagination>,
     role_option: Query<Role>,
     mut search_option: Query<Search>,
+    list: Query<ListQuery>,
+    fields: Query<FieldsQuery>,
+    OriginalUri(uri): OriginalUri,
     state: State<AppState>,
 ) -> Result<Response<Body>, (StatusCode, String)> {
-    let mut for_users_query: SqlParams = vec![];
-    let mut for_count_query: SqlParams = vec![];
+    let search = search_option.search
+        .take()
+        .map(|search| search.trim().to_string())
+        .filter(|search| !search.is_empty());
+
+    let keys = match (&list.sort, &search) {
+        (Some(sort), _) => parse_sort(sort, USER_SORT_FIELDS)?,
+        (None, Some(_)) => vec![SortKey::desc("similarity(username, term)", KeyKind::Float), SortKey::asc("id", KeyKind::Int)],
+        (None, None) => vec![SortKey::asc("id", KeyKind::Int)],
+    };
+    let page = pagination.page(&state.settings, keys)?;
+    let selection = fields.selection(USER_FIELDS, USER_INCLUDES)?;
+
+    // a search puts its text in scope as `term`, for ordering by similarity
+    let mut query = match &search {
+        Some(search) => {
+            let mut query = QueryBuilder::new(Sql::new(&format!("{USER_TABLE_NAME}, (SELECT "))
+                .bind(search.clone())
+                .text("::text AS term) search"));
+            query.filter(Condition::or(vec![
+                Condition::raw("username % term"),
+                Condition::ilike("username", format!("%{}%", escape_like(search))),
+            ]));
+            query
+        }
+        None => QueryBuilder::new(Sql::new(USER_TABLE_NAME)),
+    };
 
     if let Some(role) = role_option.role {
-        for_users_query.push(("role = ", Box::new(role)));
-        for_count_query.push(("role = ", Box::new(role)));
+        query.filter(Condition::eq("role", role));
     }
 
-    if let Some(search) = search_option.search.take() {
-        let search_ = format!("%{search}%");
-        for_users_query.push(("username LIKE ", Box::new(search_.clone())));
-        for_count_query.push(("username LIKE ", Box::new(search_.clone())));
+    if let Some(role_in) = &role_option.role_in {
+        query.filter(Condition::is_in("role", parse_list::<i16>("role_in", role_in)?));
     }
 
-    let mut query_users = format!("SELECT id, username, role FROM {USER_TABLE_NAME}");
-    let mut query_count = format!("SELECT count(*) FROM {USER_TABLE_NAME}");
-
-    if !for_users_query.is_empty() { query_users += " WHERE " }
-    if !for_count_query.is_empty() { query_count += " WHERE " }
-
-    let mut parameters_count = 0;
-
-    let mut params_users = for_users_query
-        .iter()
-        .enumerate()
-        .map(|(i, (key, value))| {
-            if i > 0 { query_users += " AND " }
-            let parameter_number = i+1;
-            query_users += &format!("{}${}", key, parameter_number);
-            parameters_count = parameter_number;
-            value.as_ref() as &(dyn ToSql + Sync)
-        })
-        .collect::<Vec<&(dyn ToSql + Sync)>>();
-
-    params_users.push(&pagination.limit);
-    params_users.push(&pagination.offset);
-
-    if parameters_count != 0 {
-        query_users += &format!(" ORDER BY id LIMIT ${} OFFSET ${}", parameters_count + 1, parameters_count + 2);
-    } else {
-        query_users += " ORDER BY id LIMIT $1 OFFSET $2";
+    list.push_filters(&mut query)?;
+
+    let query_users = query.select(&format!("{}{}", selection.columns(), page.key_columns())).page(&page);
+    let query_count = query.count();
+
+    let mut conn = state.pool.get().await.map_err(internal_error)?;
+    let tx = conn.transaction().await.map_err(internal_error)?;
+
+    if search.is_some() {
+        set_similarity_threshold(&tx, state.settings.username_similarity_threshold).await?;
+    }
+
+    let (rows, count) = tokio::try_join!(
+        tx.query(query_users.sql(), query_users.params()),
+        async {
+            match pagination.with_count() {
+                true => tx.query_one(query_count.sql(), query_count.params()).await.map(|row| Some(row.get::<usize, i64>(0))),
+                false => Ok(None),
+            }
+        }
+    ).map_err(internal_error)?;
+
+    tx.commit().await.map_err(internal_error)?;
+
+    let (rows, links) = page.finish(rows)?;
+
+    let reply: Vec<SelectedRow> = rows.iter().map(|row| selection.read(row)).collect();
+
+    let mut response = Json(reply).into_response();
+    if let Some(count) = count {
+        let header_count_value = HeaderValue::from_str(&count.to_string()).map_err(internal_error)?;
+        response.headers_mut().insert("x-total-count", header_count_value);
+    }
+    if let Some(link_value) = link_header(&uri, &links)? {
+        response.headers_mut().insert(header::LINK, link_value);
     }
 
-    let params_count = for_count_query
-        .iter()
-        .enumerate()
-        .map(|(i, (key, value))| {
-            if i > 0 { query_count += " AND " }
-            query_count += &format!("{}${}", key, i+1);
-            value.as_ref() as &(dyn ToSql + Sync)
-        })
-        .collect::<Vec<&(dyn ToSql + Sync)>>();
+    Ok(response)
+}
 
+const MAX_AUTOCOMPLETE_LIMIT: i64 = 20;
+
+/// Top usernames starting with `prefix`, closest matches first, for @-mention pickers.
+pub async fn autocomplete_users(
+    query: Query<AutocompleteQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<UserSuggestion>>, (StatusCode, String)> {
+    let limit = query.limit.unwrap_or(10).clamp(1, MAX_AUTOCOMPLETE_LIMIT);
+    let prefix = query.prefix.trim();
+    let pattern = format!("{}%", escape_like(prefix));
 
     let conn = state.pool.get().await.map_err(internal_error)?;
 
-    let (rows, row_count) = tokio::try_join!(
-        conn.query(&query_users, &params_users),
-        conn.query_one(&query_count, &params_count)
-    ).map_err(internal_error)?;
+    let rows = conn.query(
+        &format!("SELECT id, username FROM {USER_TABLE_NAME} WHERE username ILIKE $1 \
+            ORDER BY similarity(username, $2::text) DESC, length(username), username LIMIT $3"),
+        &[&pattern, &prefix, &limit]
+    ).await.map_err(internal_error)?;
 
-    let reply: Vec<User> = rows.iter().map(|r| User {
+    let suggestions = rows.iter().map(|r| UserSuggestion {
         id: r.get(0),
-        username: r.get(1),
-        role: r.get::<usize, i16>(2).into()
+        username: r.get(1)
     }).collect();
 
-    let count: i64 = row_count.get(0);
-    let header_count_value = HeaderValue::from_str(&count.to_string()).map_err(internal_error)?;
+    Ok(Json(suggestions))
+}
 
-    let mut response = Json(reply).into_response();
-    response.headers_mut().insert("x-total-count", header_count_value);
+/// Sets the threshold used by the pg_trgm `%` operator until the end of the transaction, so
+/// it doesn't stay with the pooled connection.
+async fn set_similarity_threshold(tx: &Transaction<'_>, threshold: f32) -> Result<(), (StatusCode, String)> {
+    tx.execute(
+        "SELECT set_config('pg_trgm.similarity_threshold', $1, true)",
+        &[&threshold.to_string()]
+    ).await.map_err(internal_error)?;
 
-    Ok(response)
+    Ok(())
 }
 
 pub async fn delete_user(
@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
 use crate::types::Roles;
+use crate::modules::common::fields::{Field, FieldKind};
 
 #[derive(Deserialize)]
 pub struct DeleteUserPayload {
@@ -21,7 +22,33 @@ pub struct User {
   pub role: Roles
 }
 
+/// Fields of a user listing, see `FieldsQuery`.
+pub const USER_FIELDS: &[Field] = &[
+  ("id", "id", FieldKind::Int),
+  ("username", "username", FieldKind::Text),
+  ("role", "role", FieldKind::Role),
+  ("created_at", "created_at", FieldKind::Time),
+];
+
+/// Related counts a user listing can embed with `include=`, not counting trashed notes.
+pub const USER_INCLUDES: &[Field] = &[
+  ("note_count", "(SELECT count(*) FROM notes n WHERE n.user_id = users.id AND n.deleted_at IS NULL)", FieldKind::BigInt),
+  ("notebook_count", "(SELECT count(*) FROM notebooks nb WHERE nb.user_id = users.id)", FieldKind::BigInt),
+];
+
 #[derive(Deserialize)]
 pub struct PromoteUserPayload {
   pub id: i32
-}
\ No newline at end of file
+}
+
+#[derive(Deserialize)]
+pub struct AutocompleteQuery {
+  pub prefix: String,
+  pub limit: Option<i64>
+}
+
+#[derive(Serialize)]
+pub struct UserSuggestion {
+  pub id: i32,
+  pub username: String
+}
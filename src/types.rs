@@ -1,11 +1,18 @@
+use std::{str::FromStr, sync::Arc};
+
 use axum::http::StatusCode;
 
 use bb8::Pool;
 use bb8_postgres::PostgresConnectionManager;
+use tokio::sync::broadcast;
 use tokio_postgres::NoTls;
 
 use serde::{Deserialize, Serialize};
 
+use crate::modules::attachments::store::BlobStore;
+use crate::modules::events::types::NoteEvent;
+use crate::modules::notifications::channel::Notifier;
+
 pub type ConnectionPool = Pool<PostgresConnectionManager<NoTls>>;
 
 /// Utility function for mapping any error into a `500 Internal Server Error` response.
@@ -13,17 +20,87 @@ pub fn internal_error<E>(err: E) -> (StatusCode, String) where E: std::error::Er
     (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
 }
 
+/// Listing query parameters. Sending `offset` selects offset pagination, otherwise pages are
+/// walked with the opaque `after`/`before` cursors handed out in `Link` headers.
+/// `count=false` skips computing `x-total-count`.
 #[derive(Deserialize)]
 pub struct Pagination {
-    pub offset: i64,
-    pub limit: i64,
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+    pub after: Option<String>,
+    pub before: Option<String>,
+    pub count: Option<bool>,
 }
 
 #[derive(Clone)]
 pub struct AppState {
     pub pool: Pool<PostgresConnectionManager<NoTls>>,
     pub secret: String,
-    pub salt: String
+    pub salt: String,
+    pub settings: Settings,
+    pub blobs: Arc<dyn BlobStore>,
+    /// Outbound channel for notifications, `None` when they stay in the app.
+    pub notifier: Option<Arc<dyn Notifier>>,
+    /// Note events of every server process, for the event streams of this one.
+    pub events: broadcast::Sender<NoteEvent>
+}
+
+/// Runtime knobs read from the environment (or `.env`) on startup.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Answer `428 Precondition Required` to note updates and deletes sent without `If-Match`.
+    pub require_if_match: bool,
+    /// Days a deleted note stays in the trash before it is purged for good.
+    pub trash_retention_days: i32,
+    /// Postgres text search configuration used to index new notes and parse searches.
+    pub search_language: String,
+    /// Minimum trigram similarity (0 to 1) for a username to match a fuzzy search.
+    pub username_similarity_threshold: f32,
+    /// Page size used when a listing request doesn't send `limit`.
+    pub default_page_size: i64,
+    /// Upper bound on `limit` for every listing.
+    pub max_page_size: i64,
+    /// Largest note text accepted, in bytes.
+    pub note_max_bytes: usize,
+    /// Largest request accepted by note imports and bulk creates, in bytes.
+    pub import_max_bytes: usize,
+    /// Most notes a single bulk operation may touch.
+    pub bulk_max_notes: usize,
+    /// Largest file accepted as a note attachment, in bytes.
+    pub attachment_max_bytes: i64,
+    /// Comma separated MIME types accepted for attachments, `type/*` allows a whole type.
+    pub attachment_allowed_types: String,
+    /// How often the job runner looks for due jobs, such as reminders, in seconds.
+    pub job_poll_interval_secs: u64,
+    /// Hours note events are kept for event streams resuming after a reconnect.
+    pub event_retention_hours: i32,
+}
+
+impl Settings {
+    pub fn from_env() -> Self {
+        Settings {
+            require_if_match: env_or("REQUIRE_IF_MATCH", false),
+            trash_retention_days: env_or("TRASH_RETENTION_DAYS", 30),
+            search_language: env_or("SEARCH_LANGUAGE", "english".to_string()),
+            username_similarity_threshold: env_or("USERNAME_SIMILARITY_THRESHOLD", 0.3),
+            default_page_size: env_or("DEFAULT_PAGE_SIZE", 20),
+            max_page_size: env_or("MAX_PAGE_SIZE", 100),
+            note_max_bytes: env_or("NOTE_MAX_BYTES", 1024 * 1024),
+            import_max_bytes: env_or("IMPORT_MAX_BYTES", 50 * 1024 * 1024),
+            bulk_max_notes: env_or("BULK_MAX_NOTES", 1000),
+            attachment_max_bytes: env_or("ATTACHMENT_MAX_BYTES", 10 * 1024 * 1024),
+            attachment_allowed_types: env_or("ATTACHMENT_ALLOWED_TYPES", "image/*,application/pdf,text/plain".to_string()),
+            job_poll_interval_secs: env_or("JOB_POLL_INTERVAL_SECS", 5),
+            event_retention_hours: env_or("EVENT_RETENTION_HOURS", 24),
+        }
+    }
+}
+
+pub fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    std::env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]